@@ -6,6 +6,7 @@ extern crate core;
 extern crate nalgebra as na;
 extern crate scoped_threadpool;
 extern crate image;
+extern crate flate2;
 extern crate noise;
 extern crate rand;
 extern crate palette;
@@ -33,13 +34,20 @@ pub mod util;
 pub mod universe;
 pub mod simulation;
 pub mod scene;
+pub mod overlay;
+pub mod output;
 
 use universe::Environment;
+use universe::tone_mapping::ToneMapping;
+use universe::render_pass::RenderPass;
+use universe::aov::AovLayer;
 use util::CustomFloat;
 use simulation::Simulation;
 use std::io::BufReader;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
 use clap::App;
 use clap::Arg;
 
@@ -55,6 +63,19 @@ fn main() {
 
     const ARG_SCENE: &str = "SCENE";
     const ARG_DEBUG: &str = "DEBUG";
+    const ARG_SPECTRAL: &str = "SPECTRAL";
+    const ARG_SAMPLES: &str = "SAMPLES";
+    const ARG_TONE_MAPPING: &str = "TONE_MAPPING";
+    const ARG_EXPOSURE: &str = "EXPOSURE";
+    const ARG_GAMMA: &str = "GAMMA";
+    const ARG_STATS: &str = "STATS";
+    const ARG_STATS_CSV: &str = "STATS_CSV";
+    const ARG_RENDER_PASS: &str = "RENDER_PASS";
+    const ARG_OUTPUT: &str = "OUTPUT";
+    const ARG_SIZE: &str = "SIZE";
+    const ARG_AOVS: &str = "AOVS";
+    const ARG_DENOISE: &str = "DENOISE";
+    const ARG_BENCH: &str = "BENCH";
 
     let matches = App::new("euclider")
                       .version(crate_version!())
@@ -70,10 +91,108 @@ fn main() {
                                .short("d")
                                .long("debug")
                                .help("Displays debug info"))
+                      .arg(Arg::with_name(ARG_SPECTRAL)
+                               .long("spectral")
+                               .value_name("SAMPLES")
+                               .help("Traces the given number of wavelengths per pixel, \
+                                      enables dispersion")
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_SAMPLES)
+                               .long("samples")
+                               .value_name("SAMPLES")
+                               .help("Traces the given number of rays per pixel through the lens \
+                                      of the camera, converges the depth of field")
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_TONE_MAPPING)
+                               .long("tone-mapping")
+                               .value_name("OPERATOR")
+                               .help("Overrides the tone mapping operator of the scene")
+                               .possible_values(&["clamp", "reinhard", "aces", "filmic"])
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_EXPOSURE)
+                               .long("exposure")
+                               .value_name("STOPS")
+                               .help("Overrides the exposure of the scene")
+                               .allow_hyphen_values(true)
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_GAMMA)
+                               .long("gamma")
+                               .value_name("GAMMA")
+                               .help("Overrides the gamma of the scene")
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_STATS)
+                               .long("stats")
+                               .help("Shows the statistics overlay, toggled with F3"))
+                      .arg(Arg::with_name(ARG_STATS_CSV)
+                               .long("stats-csv")
+                               .value_name("FILE")
+                               .help("Writes the statistics of each frame to a CSV file")
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_RENDER_PASS)
+                               .long("render-pass")
+                               .value_name("PASS")
+                               .help("Displays a debug render pass, cycled with F6")
+                               .possible_values(&["shaded", "normals", "depth", "material_id",
+                                                  "shape_id", "entity_id", "bounces",
+                                                  "inside_mismatch"])
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_OUTPUT)
+                               .short("o")
+                               .long("output")
+                               .value_name("FILE")
+                               .help("Renders a single frame without opening a window, \
+                                      to a multi-layer `.exr` file or to one `.png` file per layer")
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_SIZE)
+                               .long("size")
+                               .value_name("WIDTHxHEIGHT")
+                               .help("The dimensions of the offline render and the benchmark")
+                               .default_value("1024x768")
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_AOVS)
+                               .long("aovs")
+                               .value_name("LAYERS")
+                               .help("The comma-separated layers of the offline render: beauty, \
+                                      albedo, normal, depth, direct, reflected, entity_id")
+                               .default_value("beauty")
+                               .takes_value(true))
+                      .arg(Arg::with_name(ARG_DENOISE)
+                               .long("denoise")
+                               .help("Filters the rendered images with the denoiser, toggled with F7"))
+                      .arg(Arg::with_name(ARG_BENCH)
+                               .long("bench")
+                               .value_name("FRAMES")
+                               .help("Renders the given number of frames without opening a window \
+                                      and prints the frame time")
+                               .takes_value(true))
                       .get_matches();
 
     let scene = matches.value_of(ARG_SCENE).unwrap();
     let debug = matches.is_present(ARG_DEBUG);
+    let spectral_samples = matches.value_of(ARG_SPECTRAL)
+        .map(|samples| samples.parse::<u32>()
+             .expect("The number of spectral samples must be a non-negative integer."))
+        .unwrap_or(0);
+    let samples = matches.value_of(ARG_SAMPLES)
+        .map(|samples| samples.parse::<u32>()
+             .expect("The number of samples must be a positive integer."))
+        .unwrap_or(1);
+    let tone_mapping = matches.value_of(ARG_TONE_MAPPING)
+        .map(|operator| operator.parse::<ToneMapping>()
+             .expect("Invalid tone mapping operator."));
+    let exposure = matches.value_of(ARG_EXPOSURE)
+        .map(|exposure| exposure.parse::<F>()
+             .expect("The exposure must be a number."));
+    let gamma = matches.value_of(ARG_GAMMA)
+        .map(|gamma| gamma.parse::<F>()
+             .expect("The gamma must be a number."))
+        .map(|gamma| {
+            assert!(gamma > 0.0, "The gamma must be positive.");
+            gamma
+        });
+    let render_pass = matches.value_of(ARG_RENDER_PASS)
+        .map(|pass| pass.parse::<RenderPass>()
+             .expect("Invalid render pass."));
     let mut reader = BufReader::new(File::open(scene)
         .expect("Unable to find the scene file."));
     let mut json = String::new();
@@ -82,11 +201,65 @@ fn main() {
         .parse::<Box<Environment>>(&json)
         .expect("Unable to parse the Environment.");
 
-    let simulation = Simulation::builder()
+    let mut simulation_builder = Simulation::builder()
         .environment(*environment)
         .threads(num_cpus::get() as u32)
         .debug(debug)
-        .build();
+        .spectral_samples(spectral_samples)
+        .samples(samples)
+        .statistics(matches.is_present(ARG_STATS))
+        .denoise(matches.is_present(ARG_DENOISE));
+
+    if let Some(tone_mapping) = tone_mapping {
+        simulation_builder = simulation_builder.tone_mapping(tone_mapping);
+    }
+
+    if let Some(exposure) = exposure {
+        simulation_builder = simulation_builder.exposure(exposure);
+    }
+
+    if let Some(gamma) = gamma {
+        simulation_builder = simulation_builder.gamma(gamma);
+    }
+
+    if let Some(render_pass) = render_pass {
+        simulation_builder = simulation_builder.render_pass(render_pass);
+    }
+
+    if let Some(path) = matches.value_of(ARG_STATS_CSV) {
+        simulation_builder = simulation_builder.statistics_csv(PathBuf::from(path));
+    }
+
+    let simulation = simulation_builder.build();
+
+    let size: Vec<u32> = matches.value_of(ARG_SIZE).unwrap()
+        .split('x')
+        .map(|dimension| dimension.parse::<u32>()
+             .expect("The size must be given as WIDTHxHEIGHT."))
+        .collect();
+
+    if size.len() != 2 {
+        panic!("The size must be given as WIDTHxHEIGHT.");
+    }
+
+    if let Some(frames) = matches.value_of(ARG_BENCH) {
+        let frames = frames.parse::<u32>()
+            .expect("The number of benchmarked frames must be a non-negative integer.");
+
+        simulation.benchmark((size[0], size[1]), frames);
+        return;
+    }
+
+    if let Some(output) = matches.value_of(ARG_OUTPUT) {
+        let layers: Vec<AovLayer> = matches.value_of(ARG_AOVS).unwrap()
+            .split(',')
+            .map(|layer| layer.trim().parse::<AovLayer>()
+                 .unwrap_or_else(|error| panic!("{}", error)))
+            .collect();
+
+        simulation.render_offline((size[0], size[1]), &layers, Path::new(output));
+        return;
+    }
 
     if debug {
         println!("Running in debug mode.");
@@ -0,0 +1,352 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use image::ColorType;
+use image::save_buffer;
+use universe::aov::AovImage;
+use universe::entity::exr::*;
+use universe::tone_mapping::ToneMapper;
+
+fn write_i32<W: Write>(writer: &mut W, value: i32) -> io::Result<()> {
+    write_u32(writer, value as u32)
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8])
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    write_u32(writer, value as u32)?;
+    write_u32(writer, (value >> 32) as u32)
+}
+
+fn write_f32<W: Write>(writer: &mut W, value: f32) -> io::Result<()> {
+    write_u32(writer, value.to_bits())
+}
+
+fn write_attribute<W: Write>(writer: &mut W, name: &str, kind: &str, value: &[u8]) -> io::Result<()> {
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(kind.as_bytes())?;
+    writer.write_all(&[0])?;
+    write_i32(writer, value.len() as i32)?;
+    writer.write_all(value)
+}
+
+/// The channels of all the layers sorted by name, as required by OpenEXR,
+/// paired with the layer index and the index of the value within the layer.
+fn exr_channels(image: &AovImage) -> Vec<(String, usize, usize)> {
+    let mut channels: Vec<(String, usize, usize)> = image.layers.iter()
+        .enumerate()
+        .flat_map(|(layer_index, layer)| {
+            layer.channel_names()
+                .into_iter()
+                .enumerate()
+                .map(move |(value_index, name)| (name, layer_index, value_index))
+        })
+        .collect();
+
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+    channels
+}
+
+fn exr_header(image: &AovImage, channels: &[(String, usize, usize)]) -> io::Result<Vec<u8>> {
+    let mut header = Vec::new();
+    let mut channel_list = Vec::new();
+
+    for &(ref name, _, _) in channels {
+        channel_list.write_all(name.as_bytes())?;
+        channel_list.write_all(&[0])?;
+        write_i32(&mut channel_list, EXR_PIXEL_TYPE_FLOAT)?;
+        // pLinear and reserved bytes
+        channel_list.write_all(&[0, 0, 0, 0])?;
+        // x and y sampling
+        write_i32(&mut channel_list, 1)?;
+        write_i32(&mut channel_list, 1)?;
+    }
+
+    channel_list.write_all(&[0])?;
+
+    let mut window = Vec::new();
+
+    write_i32(&mut window, 0)?;
+    write_i32(&mut window, 0)?;
+    write_i32(&mut window, image.width as i32 - 1)?;
+    write_i32(&mut window, image.height as i32 - 1)?;
+
+    let mut one = Vec::new();
+    let mut screen_window_center = Vec::new();
+
+    write_f32(&mut one, 1.0)?;
+    write_f32(&mut screen_window_center, 0.0)?;
+    write_f32(&mut screen_window_center, 0.0)?;
+
+    header.write_all(&EXR_MAGIC)?;
+    header.write_all(&EXR_VERSION)?;
+    write_attribute(&mut header, "channels", "chlist", &channel_list)?;
+    write_attribute(&mut header, "compression", "compression", &[EXR_COMPRESSION_NONE])?;
+    write_attribute(&mut header, "dataWindow", "box2i", &window)?;
+    write_attribute(&mut header, "displayWindow", "box2i", &window)?;
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[EXR_LINE_ORDER_INCREASING_Y])?;
+    write_attribute(&mut header, "pixelAspectRatio", "float", &one)?;
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &screen_window_center)?;
+    write_attribute(&mut header, "screenWindowWidth", "float", &one)?;
+    header.write_all(&[0])?;
+
+    Ok(header)
+}
+
+/// Writes all the layers of the image to an uncompressed multi-layer OpenEXR file
+/// with 32-bit float channels.
+pub fn write_exr<W: Write>(writer: &mut W, image: &AovImage) -> io::Result<()> {
+    let channels = exr_channels(image);
+    let header = exr_header(image, &channels)?;
+    // Each uncompressed chunk contains a single scanline
+    let chunk_size = 4 + 4 + channels.len() as u64 * u64::from(image.width) * 4;
+    let chunks_start = header.len() as u64 + u64::from(image.height) * 8;
+
+    writer.write_all(&header)?;
+
+    for row in 0..u64::from(image.height) {
+        write_u64(writer, chunks_start + row * chunk_size)?;
+    }
+
+    for row in 0..image.height {
+        // The scanlines of EXR images start at the top
+        let y = image.height - 1 - row;
+
+        write_i32(writer, row as i32)?;
+        write_i32(writer, (chunk_size - 8) as i32)?;
+
+        for &(_, layer_index, value_index) in &channels {
+            let layer = image.layers[layer_index];
+
+            for x in 0..image.width {
+                let value = layer.values(image.sample(x, y))[value_index];
+
+                write_f32(writer, value as f32)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The path of the PNG file of a layer, `render.png` becomes `render_albedo.png`
+pub fn layer_path(path: &Path, layer_name: &str) -> PathBuf {
+    let stem = path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(String::new);
+
+    path.with_file_name(format!("{}_{}.png", stem, layer_name))
+}
+
+/// Writes the image to the path, as a multi-layer OpenEXR file if the path ends with `.exr`,
+/// otherwise as one PNG file per layer.
+pub fn write_aov_image(path: &Path, image: &AovImage, tone_mapper: &ToneMapper) -> io::Result<()> {
+    if is_exr(path) {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        return write_exr(&mut writer, image);
+    }
+
+    for layer in &image.layers {
+        let mut data: Vec<u8> = Vec::with_capacity((image.width * image.height) as usize * 3);
+
+        // The rows of PNG images start at the top
+        for y in (0..image.height).rev() {
+            for x in 0..image.width {
+                data.extend_from_slice(&layer.to_pixel(image.sample(x, y), tone_mapper));
+            }
+        }
+
+        save_buffer(layer_path(path, &layer.to_string()), &data,
+                    image.width, image.height, ColorType::RGB(8))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::Path;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use palette::Rgb;
+    use universe::aov::AovImage;
+    use universe::aov::AovLayer;
+    use universe::aov::AovSample;
+    use universe::entity::exr::read_exr;
+    use super::*;
+
+    /// The position of the value of the compression attribute, which follows its name,
+    /// type and size
+    fn compression_position(data: &[u8]) -> usize {
+        let attribute = b"compression\0compression\0";
+
+        data.windows(attribute.len())
+            .position(|window| window == &attribute[..])
+            .unwrap() + attribute.len() + 4
+    }
+
+    /// Rewrites the uncompressed scanlines of an image written by `write_exr`
+    /// as ZIP compressed chunks of the given number of scanlines.
+    fn compress_exr(image: &AovImage, compression: u8, lines_per_chunk: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        write_exr(&mut data, image).unwrap();
+
+        let channels = exr_channels(image);
+        let header_length = exr_header(image, &channels).unwrap().len();
+        let line_size = channels.len() * image.width as usize * 4;
+        let line_start = |row: usize| {
+            header_length + image.height as usize * 8 + row * (8 + line_size) + 8
+        };
+        let mut chunks = Vec::new();
+
+        for first_row in (0..image.height as usize).filter(|row| row % lines_per_chunk == 0) {
+            let last_row = ::std::cmp::min(first_row + lines_per_chunk, image.height as usize);
+            let lines: Vec<u8> = (first_row..last_row)
+                .flat_map(|row| data[line_start(row)..line_start(row) + line_size].iter().cloned())
+                .collect();
+            let even = lines.iter().cloned().enumerate().filter(|&(index, _)| index % 2 == 0);
+            let odd = lines.iter().cloned().enumerate().filter(|&(index, _)| index % 2 == 1);
+            let mut predicted: Vec<u8> = even.chain(odd).map(|(_, byte)| byte).collect();
+
+            for index in (1..predicted.len()).rev() {
+                predicted[index] = predicted[index].wrapping_sub(predicted[index - 1])
+                    .wrapping_add(128);
+            }
+
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+            encoder.write_all(&predicted).unwrap();
+
+            let compressed = encoder.finish().unwrap();
+            let stored = if compressed.len() < lines.len() { compressed } else { lines };
+
+            chunks.push((first_row, stored));
+        }
+
+        let mut compressed = data[..header_length].to_vec();
+        let mut chunk_start = (header_length + chunks.len() * 8) as u64;
+
+        compressed[compression_position(&data)] = compression;
+
+        for &(_, ref stored) in &chunks {
+            write_u64(&mut compressed, chunk_start).unwrap();
+            chunk_start += 8 + stored.len() as u64;
+        }
+
+        for (first_row, stored) in chunks {
+            write_i32(&mut compressed, first_row as i32).unwrap();
+            write_i32(&mut compressed, stored.len() as i32).unwrap();
+            compressed.extend_from_slice(&stored);
+        }
+
+        compressed
+    }
+
+    #[test]
+    fn exr_layout() {
+        let image = AovImage {
+            width: 3,
+            height: 2,
+            layers: vec![AovLayer::Beauty, AovLayer::Depth],
+            samples: vec![AovSample::default(); 6],
+        };
+        let channels = exr_channels(&image);
+        let header_length = exr_header(&image, &channels).unwrap().len();
+        let mut data = Vec::new();
+
+        write_exr(&mut data, &image).unwrap();
+
+        let names: Vec<&str> = channels.iter().map(|channel| &channel.0[..]).collect();
+
+        assert_eq!(names, vec!["B", "G", "R", "Z"]);
+        assert_eq!(&data[0..4], &EXR_MAGIC);
+        assert_eq!(data.len(), header_length + 2 * 8 + 2 * (8 + 4 * 3 * 4));
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let mut samples = vec![AovSample::default(); 6];
+
+        samples[1].beauty = Rgb::new(2.0, 0.5, 0.25);
+
+        let image = AovImage {
+            width: 3,
+            height: 2,
+            layers: vec![AovLayer::Beauty, AovLayer::Depth],
+            samples: samples,
+        };
+        let mut data = Vec::new();
+
+        write_exr(&mut data, &image).unwrap();
+
+        let read = read_exr(&mut &data[..]).unwrap();
+
+        assert_eq!((read.width, read.height), (3, 2));
+        // The bottom row of the rendered image is the last one in the file
+        assert_eq!(read.pixels[4], [2.0, 0.5, 0.25, 1.0]);
+        assert_eq!(read.pixels[1], [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn exr_rejects_compression() {
+        let image = AovImage {
+            width: 1,
+            height: 1,
+            layers: vec![AovLayer::Beauty],
+            samples: vec![AovSample::default()],
+        };
+        let mut data = Vec::new();
+
+        write_exr(&mut data, &image).unwrap();
+
+        // PIZ compression
+        let position = compression_position(&data);
+
+        data[position] = 4;
+
+        assert!(read_exr(&mut &data[..]).is_err());
+    }
+
+    #[test]
+    fn exr_zip_compression() {
+        let mut samples = vec![AovSample::default(); 40];
+
+        samples[1].beauty = Rgb::new(2.0, 0.5, 0.25);
+        samples[38].beauty = Rgb::new(1.0, 2.0, 3.0);
+
+        let image = AovImage {
+            width: 2,
+            height: 20,
+            layers: vec![AovLayer::Beauty, AovLayer::Depth],
+            samples: samples,
+        };
+
+        let compressions = [(EXR_COMPRESSION_ZIPS, 1), (EXR_COMPRESSION_ZIP, 16)];
+
+        for &(compression, lines_per_chunk) in &compressions {
+            let data = compress_exr(&image, compression, lines_per_chunk);
+            let read = read_exr(&mut &data[..]).unwrap();
+
+            assert_eq!((read.width, read.height), (2, 20));
+            assert_eq!(read.pixels[39], [2.0, 0.5, 0.25, 1.0]);
+            assert_eq!(read.pixels[0], [1.0, 2.0, 3.0, 1.0]);
+            assert_eq!(read.pixels[20], [0.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+
+    #[test]
+    fn layer_paths() {
+        assert_eq!(layer_path(Path::new("out/render.png"), "albedo"),
+                   Path::new("out/render_albedo.png"));
+    }
+}
@@ -0,0 +1,167 @@
+use ::F;
+use std::borrow::Cow;
+use glium::texture::ClientFormat;
+use glium::texture::RawImage2d;
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const SPACING: u32 = 1;
+const MARGIN: u32 = 2;
+const BACKGROUND: [u8; 3] = [16, 16, 16];
+const FOREGROUND: [u8; 3] = [255, 255, 255];
+
+/// The rows of a 3x5 glyph from the top, the highest bit is the leftmost pixel.
+/// Lowercase letters are drawn as uppercase ones, unknown characters as `?`.
+fn glyph(character: char) -> [u8; 5] {
+    match character.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+/// Renders the lines of text onto an opaque panel, each pixel of the font is drawn
+/// as a square of `scale` pixels.
+pub fn render_text<'a>(lines: &[String], scale: u32) -> RawImage2d<'a, u8> {
+    const COLOR_DIM: usize = 3;
+    let columns = lines.iter().map(|line| line.chars().count() as u32).max().unwrap_or(0);
+    let width = (MARGIN * 2 + columns * (GLYPH_WIDTH + SPACING)) * scale;
+    let height = (MARGIN * 2 + lines.len() as u32 * (GLYPH_HEIGHT + SPACING)) * scale;
+    let mut data: Vec<u8> = BACKGROUND.iter()
+        .cloned()
+        .cycle()
+        .take((width * height) as usize * COLOR_DIM)
+        .collect();
+
+    for (row, line) in lines.iter().enumerate() {
+        for (column, character) in line.chars().enumerate() {
+            let glyph = glyph(character);
+            let left = MARGIN + column as u32 * (GLYPH_WIDTH + SPACING);
+            let top = MARGIN + row as u32 * (GLYPH_HEIGHT + SPACING);
+
+            for (glyph_y, glyph_row) in glyph.iter().enumerate() {
+                for glyph_x in 0..GLYPH_WIDTH {
+                    if glyph_row & (1 << (GLYPH_WIDTH - 1 - glyph_x)) == 0 {
+                        continue;
+                    }
+
+                    for offset_y in 0..scale {
+                        for offset_x in 0..scale {
+                            let x = (left + glyph_x) * scale + offset_x;
+                            let y = (top + glyph_y as u32) * scale + offset_y;
+                            // The rows of the image start at the bottom
+                            let index = ((height - 1 - y) * width + x) as usize * COLOR_DIM;
+
+                            data[index..index + COLOR_DIM].copy_from_slice(&FOREGROUND);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    RawImage2d {
+        data: Cow::Owned(data),
+        width: width,
+        height: height,
+        format: ClientFormat::U8U8U8,
+    }
+}
+
+/// Draws a line of single pixels between the points given in pixels from the bottom left
+/// corner, the parts outside of the image are skipped.
+pub fn draw_line(image: &mut RawImage2d<u8>, from: (F, F), to: (F, F), color: [u8; 3]) {
+    const COLOR_DIM: usize = 3;
+    let (width, height) = (image.width as i64, image.height as i64);
+    let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil().max(1.0);
+    // Avoid rasterizing lines to points projected very far outside of the screen
+    let steps = steps.min(((width + height) * 4) as F);
+    let data = image.data.to_mut();
+
+    for step in 0..(steps as i64 + 1) {
+        let ratio = step as F / steps;
+        let x = (from.0 + (to.0 - from.0) * ratio).round() as i64;
+        let y = (from.1 + (to.1 - from.1) * ratio).round() as i64;
+
+        if x < 0 || y < 0 || x >= width || y >= height {
+            continue;
+        }
+
+        let index = (y * width + x) as usize * COLOR_DIM;
+
+        data[index..index + COLOR_DIM].copy_from_slice(&color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panel_dimensions() {
+        let image = render_text(&["FPS: 60".to_string(), "".to_string()], 2);
+
+        assert_eq!(image.width, (2 * 2 + 7 * 4) * 2);
+        assert_eq!(image.height, (2 * 2 + 2 * 6) * 2);
+        assert_eq!(image.data.len(), (image.width * image.height * 3) as usize);
+    }
+
+    #[test]
+    fn line_is_clipped() {
+        let mut image = render_text(&[], 1);
+        let (width, height) = (image.width, image.height);
+
+        draw_line(&mut image, (-10.0, 0.0), (10.0, 0.0), [1, 2, 3]);
+
+        assert_eq!(&image.data[0..3], &[1, 2, 3]);
+        assert_eq!(&image.data[(width as usize - 1) * 3..width as usize * 3], &[1, 2, 3]);
+        assert_eq!(&image.data[width as usize * 3..width as usize * 3 + 3], &[16, 16, 16]);
+        assert!(height > 1);
+    }
+}
@@ -1,3 +1,4 @@
+use palette::Rgb;
 use palette::Rgba;
 use palette::Hsva;
 use std::any::Any;
@@ -12,7 +13,16 @@ use json::JsonValue;
 use universe::entity::*;
 use universe::entity::material::*;
 use universe::entity::shape::*;
+use universe::entity::polytope::*;
+use universe::entity::lens::Aperture;
+use universe::entity::lens::ThinLens;
+use universe::entity::stereo::Stereo;
+use universe::entity::stereo::StereoLayout;
 use universe::entity::surface::*;
+use universe::entity::procedural::*;
+use universe::entity::environment_map::*;
+use universe::entity::texture::*;
+use universe::entity::uv::*;
 use universe::d3::entity::Entity3Impl;
 use universe::d3::entity::Camera3;
 use universe::d3::entity::surface::*;
@@ -23,12 +33,13 @@ use universe::d4::entity::Camera4;
 use universe::d4::entity::surface::*;
 use universe::d4::entity::shape::*;
 use universe::d4::entity::camera::*;
+use universe::spectrum::*;
+use universe::tone_mapping::*;
 use universe::d3::Point3;
 use universe::d3::Vector3;
 use universe::d4::Point4;
 use universe::d4::Vector4;
 use util::JsonFloat;
-use image;
 use meval::Expr;
 use core::str::FromStr;
 use ::F;
@@ -652,6 +663,13 @@ impl Parser {
                 }
             };
 
+            add_deserializer! {
+                "Rgb", "Rgb::new";
+                [r: F] [g: F] [b: F] -> Rgb<F> {
+                    Rgb::<F>::new(r, g, b)
+                }
+            };
+
             add_deserializer! {
                 "Rgba::new_u8";
                 [r: u8] [g: u8] [b: u8] [a: u8] -> Rgba<F> {
@@ -718,6 +736,52 @@ impl Parser {
                 }
             }
 
+            add_deserializer! {
+                "Entity3Impl::new_with_priority";
+                [shape: Box<Shape<Point3, Vector3>>]
+                [material: Box<Material<Point3, Vector3>>]
+                [surface: Box<Surface<Point3, Vector3>>]
+                [priority: i32]
+                -> Box<Entity<Point3, Vector3>> {
+                    Box::new(Entity3Impl::new_with_surface(shape, material, surface)
+                             .with_priority(priority))
+                }
+            }
+
+            add_deserializer! {
+                "Entity4Impl::new_with_priority";
+                [shape: Box<Shape<Point4, Vector4>>]
+                [material: Box<Material<Point4, Vector4>>]
+                [surface: Box<Surface<Point4, Vector4>>]
+                [priority: i32]
+                -> Box<Entity<Point4, Vector4>> {
+                    Box::new(Entity4Impl::new_with_surface(shape, material, surface)
+                             .with_priority(priority))
+                }
+            }
+
+            add_deserializer! {
+                "Entity3Impl::new_solid";
+                [shape: Box<Shape<Point3, Vector3>>]
+                [material: Box<Material<Point3, Vector3>>]
+                [surface: Box<Surface<Point3, Vector3>>]
+                -> Box<Entity<Point3, Vector3>> {
+                    Box::new(Entity3Impl::new_with_surface(shape, material, surface)
+                             .with_solid(true))
+                }
+            }
+
+            add_deserializer! {
+                "Entity4Impl::new_solid";
+                [shape: Box<Shape<Point4, Vector4>>]
+                [material: Box<Material<Point4, Vector4>>]
+                [surface: Box<Surface<Point4, Vector4>>]
+                -> Box<Entity<Point4, Vector4>> {
+                    Box::new(Entity4Impl::new_with_surface(shape, material, surface)
+                             .with_solid(true))
+                }
+            }
+
             add_deserializer! {
                 "Entity3Impl::new_without_surface";
                 [shape: Box<Shape<Point3, Vector3>>]
@@ -894,7 +958,7 @@ impl Parser {
             }
 
             add_deserializer! {
-                "HalfSpace3::cuboid";
+                "HalfSpace3::cuboid", "ConvexPolytope3::cuboid";
                 [center: Point3] [dimensions: Vector3]
                 -> Box<Shape<Point3, Vector3>> {
                     Box::new(cuboid(center, dimensions))
@@ -902,13 +966,203 @@ impl Parser {
             }
 
             add_deserializer! {
-                "HalfSpace4::hypercuboid";
+                "HalfSpace4::hypercuboid", "ConvexPolytope4::hypercuboid";
                 [center: Point4] [dimensions: Vector4]
                 -> Box<Shape<Point4, Vector4>> {
                     Box::new(hypercuboid(center, dimensions))
                 }
             }
 
+            add_deserializer! {
+                "ConvexPolytope3", "ConvexPolytope3::of";
+                [half_spaces: Vec<Box<Shape<Point3, Vector3>>>]
+                -> Box<Shape<Point3, Vector3>> {
+                    let mut result = Vec::with_capacity(half_spaces.len());
+
+                    for half_space in half_spaces {
+                        let half_space: HalfSpace<Point3, Vector3>
+                            = *try!(<Shape<Point3, Vector3>>::downcast(half_space)
+                                .or_else(|err| Err(ParserError::CustomError {
+                                    description: "Invalid type, expected a `HalfSpace3`.".to_string(),
+                                })));
+
+                        result.push(half_space);
+                    }
+
+                    Box::new(ConvexPolytope::of(result))
+                }
+            }
+
+            add_deserializer! {
+                "ConvexPolytope4", "ConvexPolytope4::of";
+                [half_spaces: Vec<Box<Shape<Point4, Vector4>>>]
+                -> Box<Shape<Point4, Vector4>> {
+                    let mut result = Vec::with_capacity(half_spaces.len());
+
+                    for half_space in half_spaces {
+                        let half_space: HalfSpace<Point4, Vector4>
+                            = *try!(<Shape<Point4, Vector4>>::downcast(half_space)
+                                .or_else(|err| Err(ParserError::CustomError {
+                                    description: "Invalid type, expected a `HalfSpace4`.".to_string(),
+                                })));
+
+                        result.push(half_space);
+                    }
+
+                    Box::new(ConvexPolytope::of(result))
+                }
+            }
+
+            add_deserializer! {
+                "ConvexPolytope3::prism";
+                [center: Point3] [direction: Vector3] [circumradius: F] [sides: u32] [height: F]
+                -> Box<Shape<Point3, Vector3>> {
+                    Box::new(prism(center, direction, circumradius, sides, height))
+                }
+            }
+
+            add_deserializer! {
+                "ConvexPolytope4::tesseract";
+                [center: Point4] [circumradius: F]
+                -> Box<Shape<Point4, Vector4>> {
+                    Box::new(tesseract().solid(&center, circumradius))
+                }
+            }
+
+            add_deserializer! {
+                "ConvexPolytope4::sixteen_cell";
+                [center: Point4] [circumradius: F]
+                -> Box<Shape<Point4, Vector4>> {
+                    Box::new(sixteen_cell().solid(&center, circumradius))
+                }
+            }
+
+            add_deserializer! {
+                "ConvexPolytope4::twenty_four_cell";
+                [center: Point4] [circumradius: F]
+                -> Box<Shape<Point4, Vector4>> {
+                    Box::new(twenty_four_cell().solid(&center, circumradius))
+                }
+            }
+
+            add_deserializer! {
+                "ConvexPolytope4::hundred_twenty_cell";
+                [center: Point4] [circumradius: F]
+                -> Box<Shape<Point4, Vector4>> {
+                    Box::new(hundred_twenty_cell().solid(&center, circumradius))
+                }
+            }
+
+            add_deserializer! {
+                "PlaneRotation3", "PlaneRotation3::new";
+                [from: Vector3] [to: Vector3] [angle: F]
+                -> PlaneRotation<Vector3> {
+                    PlaneRotation::new(from, to, angle)
+                }
+            }
+
+            add_deserializer! {
+                "RegularPolytope3", "RegularPolytope3::solid";
+                [name: &str] [center: Point3] [circumradius: F]
+                [orientation: Vec<PlaneRotation<Vector3>>]
+                -> Box<Shape<Point3, Vector3>> {
+                    let polytope = try!(platonic_solid(name).ok_or_else(|| {
+                        ParserError::CustomError {
+                            description: format!("Invalid Platonic solid: \"{}\", expected one of {}",
+                                                 name, PLATONIC_SOLIDS.join(", ")),
+                        }
+                    }));
+
+                    Box::new(polytope.with_orientation(&orientation).solid(&center, circumradius))
+                }
+            }
+
+            add_deserializer! {
+                "RegularPolytope3::wireframe";
+                [name: &str] [center: Point3] [circumradius: F]
+                [orientation: Vec<PlaneRotation<Vector3>>] [edge_radius: F]
+                -> Box<Shape<Point3, Vector3>> {
+                    let polytope = try!(platonic_solid(name).ok_or_else(|| {
+                        ParserError::CustomError {
+                            description: format!("Invalid Platonic solid: \"{}\", expected one of {}",
+                                                 name, PLATONIC_SOLIDS.join(", ")),
+                        }
+                    }));
+
+                    Box::new(polytope.with_orientation(&orientation)
+                                 .wireframe(&center, circumradius, edge_radius))
+                }
+            }
+
+            add_deserializer! {
+                "PlaneRotation4", "PlaneRotation4::new";
+                [from: Vector4] [to: Vector4] [angle: F]
+                -> PlaneRotation<Vector4> {
+                    PlaneRotation::new(from, to, angle)
+                }
+            }
+
+            add_deserializer! {
+                "RegularPolytope4", "RegularPolytope4::solid";
+                [name: &str] [center: Point4] [circumradius: F]
+                [orientation: Vec<PlaneRotation<Vector4>>]
+                -> Box<Shape<Point4, Vector4>> {
+                    let polytope = try!(regular_polytope(name).ok_or_else(|| {
+                        ParserError::CustomError {
+                            description: format!("Invalid regular polytope: \"{}\", expected one of {}",
+                                                 name, REGULAR_POLYTOPES.join(", ")),
+                        }
+                    }));
+
+                    Box::new(polytope.with_orientation(&orientation).solid(&center, circumradius))
+                }
+            }
+
+            add_deserializer! {
+                "RegularPolytope4::wireframe";
+                [name: &str] [center: Point4] [circumradius: F]
+                [orientation: Vec<PlaneRotation<Vector4>>] [edge_radius: F]
+                -> Box<Shape<Point4, Vector4>> {
+                    let polytope = try!(regular_polytope(name).ok_or_else(|| {
+                        ParserError::CustomError {
+                            description: format!("Invalid regular polytope: \"{}\", expected one of {}",
+                                                 name, REGULAR_POLYTOPES.join(", ")),
+                        }
+                    }));
+
+                    Box::new(polytope.with_orientation(&orientation)
+                                 .wireframe(&center, circumradius, edge_radius))
+                }
+            }
+
+            add_deserializer! {
+                "Wireframe3", "Wireframe3::new";
+                [shape: Box<Shape<Point3, Vector3>>] [edge_radius: F]
+                -> Box<Shape<Point3, Vector3>> {
+                    let edges = try!(edges_of(shape.as_ref(), edge_radius).ok_or_else(|| {
+                        ParserError::CustomError {
+                            description: "Invalid shape, expected a bounded convex shape made of half-spaces.".to_string(),
+                        }
+                    }));
+
+                    Box::new(edges)
+                }
+            }
+
+            add_deserializer! {
+                "Wireframe4", "Wireframe4::new";
+                [shape: Box<Shape<Point4, Vector4>>] [edge_radius: F]
+                -> Box<Shape<Point4, Vector4>> {
+                    let edges = try!(edges_of(shape.as_ref(), edge_radius).ok_or_else(|| {
+                        ParserError::CustomError {
+                            description: "Invalid shape, expected a bounded convex shape made of half-spaces.".to_string(),
+                        }
+                    }));
+
+                    Box::new(edges)
+                }
+            }
+
             add_deserializer! {
                 "Cylinder3", "Cylinder3::new";
                 [center: Point3] [direction: Vector3] [radius: F]
@@ -1031,180 +1285,789 @@ impl Parser {
                 }
             }
 
-            // Surfaces
-
             add_deserializer! {
-                "uv_sphere_3";
-                [center: Point3] -> Box<UVFn<Point3>> {
-                    uv_sphere(center)
+                "density_uniform_3";
+                [density: F] -> Box<DensityProvider<Point3>> {
+                    density_uniform::<Point3, Vector3>(density)
                 }
             }
 
             add_deserializer! {
-                "uv_derank_4";
-                [uvfn: Box<UVFn<Point3>>] -> Box<UVFn<Point4>> {
-                    uv_derank(uvfn)
+                "density_perlin_3";
+                [seed: u32] [size: F] [speed: F] [density: F]
+                -> Box<DensityProvider<Point3>> {
+                    density_perlin::<Point3, Vector3>(seed as usize, size, speed, density)
                 }
             }
 
             add_deserializer! {
-                "texture_image_nearest_neighbor";
-                [path: &str] -> Box<Texture> {
-                    let data = try!(image::open(path)
-                        .map_err(|_| ParserError::CustomError {
-                            description: format!("Could not load texture `{}`", path),
-                        }));
-
-                    texture_image_nearest_neighbor(data)
+                "ParticipatingMedium3", "ParticipatingMedium3::new";
+                [absorption: Rgb<F>]
+                [scattering: Rgb<F>]
+                [density: Box<DensityProvider<Point3>>]
+                -> Box<Material<Point3, Vector3>> {
+                    Box::new(ParticipatingMedium::new(absorption, scattering, density))
                 }
             }
 
             add_deserializer! {
-                "texture_image_linear";
-                [path: &str] -> Box<Texture> {
-                    let data = try!(image::open(path)
-                        .map_err(|_| ParserError::CustomError {
-                            description: format!("Could not load texture `{}`", path),
-                        }));
-
-                    texture_image_linear(data)
+                "ParticipatingMedium3::new_lit";
+                [absorption: Rgb<F>]
+                [scattering: Rgb<F>]
+                [density: Box<DensityProvider<Point3>>]
+                [light_direction: Vector3]
+                [light_color: Rgb<F>]
+                [anisotropy: F]
+                -> Box<Material<Point3, Vector3>> {
+                    Box::new(ParticipatingMedium::new(absorption, scattering, density)
+                             .with_light(light_direction, light_color)
+                             .with_anisotropy(anisotropy))
                 }
             }
 
             add_deserializer! {
-                "MappedTextureImpl3", "MappedTextureImpl3::new";
-                [uvfn: Box<UVFn<Point3>>]
-                [texture: Box<Texture>]
-                -> Box<MappedTexture<Point3, Vector3>> {
-                    Box::new(MappedTextureImpl::new(uvfn, texture))
+                "density_uniform_4";
+                [density: F] -> Box<DensityProvider<Point4>> {
+                    density_uniform::<Point4, Vector4>(density)
                 }
             }
 
             add_deserializer! {
-                "MappedTextureImpl4", "MappedTextureImpl4::new";
-                [uvfn: Box<UVFn<Point4>>]
-                [texture: Box<Texture>]
-                -> Box<MappedTexture<Point4, Vector4>> {
-                    Box::new(MappedTextureImpl::new(uvfn, texture))
+                "density_perlin_4";
+                [seed: u32] [size: F] [speed: F] [density: F]
+                -> Box<DensityProvider<Point4>> {
+                    density_perlin::<Point4, Vector4>(seed as usize, size, speed, density)
                 }
             }
 
             add_deserializer! {
-                "ComposableSurface3", "ComposableSurface3::new";
-                [reflection_ratio: Box<ReflectionRatioProvider<Point3, Vector3>>]
-                [reflection_direction: Box<ReflectionDirectionProvider<Point3, Vector3>>]
-                [threshold_direction: Box<ThresholdDirectionProvider<Point3, Vector3>>]
-                [surface_color: Box<SurfaceColorProvider<Point3, Vector3>>]
-                -> Box<Surface<Point3, Vector3>> {
-                    Box::new(ComposableSurface {
-                        reflection_ratio: reflection_ratio.into(),
-                        reflection_direction: reflection_direction.into(),
-                        threshold_direction: threshold_direction.into(),
-                        surface_color: surface_color.into(),
-                    })
+                "ParticipatingMedium4", "ParticipatingMedium4::new";
+                [absorption: Rgb<F>]
+                [scattering: Rgb<F>]
+                [density: Box<DensityProvider<Point4>>]
+                -> Box<Material<Point4, Vector4>> {
+                    Box::new(ParticipatingMedium::new(absorption, scattering, density))
                 }
             }
 
             add_deserializer! {
-                "ComposableSurface4", "ComposableSurface4::new";
-                [reflection_ratio: Box<ReflectionRatioProvider<Point4, Vector4>>]
-                [reflection_direction: Box<ReflectionDirectionProvider<Point4, Vector4>>]
-                [threshold_direction: Box<ThresholdDirectionProvider<Point4, Vector4>>]
-                [surface_color: Box<SurfaceColorProvider<Point4, Vector4>>]
-                -> Box<Surface<Point4, Vector4>> {
-                    Box::new(ComposableSurface {
-                        reflection_ratio: reflection_ratio.into(),
-                        reflection_direction: reflection_direction.into(),
-                        threshold_direction: threshold_direction.into(),
-                        surface_color: surface_color.into(),
-                    })
+                "ParticipatingMedium4::new_lit";
+                [absorption: Rgb<F>]
+                [scattering: Rgb<F>]
+                [density: Box<DensityProvider<Point4>>]
+                [light_direction: Vector4]
+                [light_color: Rgb<F>]
+                [anisotropy: F]
+                -> Box<Material<Point4, Vector4>> {
+                    Box::new(ParticipatingMedium::new(absorption, scattering, density)
+                             .with_light(light_direction, light_color)
+                             .with_anisotropy(anisotropy))
                 }
             }
 
+            // Surfaces
+
             add_deserializer! {
-                "blend_function_ratio";
-                [ratio: F] -> Box<BlendFunction> {
-                    blend_function_ratio(ratio)
+                "uv_sphere_3";
+                [center: Point3] -> Box<UVFn<Point3>> {
+                    uv_sphere(center)
                 }
             }
 
-            macro_rules! deserialize_blending_functions {
-                (
-                    $($name:ident),+
-                ) => {
-                    $(
-                        add_deserializer! {
-                            concat!(
-                                "blend_function_",
-                                stringify!($name)
-                            );
-                            -> Box<BlendFunction> {
-                                mashup! {
-                                    substitute["blend_function"] = blend_function_ $name;
-                                }
-
-                                substitute! {
-                                    "blend_function"()
-                                }
-                                // concat_idents!(blend_function_, $name)()
-                            }
-                        }
-                    )+
+            add_deserializer! {
+                "uv_derank_4";
+                [uvfn: Box<UVFn<Point3>>] -> Box<UVFn<Point4>> {
+                    uv_derank(uvfn)
                 }
             }
 
-            deserialize_blending_functions!(over, inside, outside, atop, xor, plus, multiply,
-                                            screen, overlay, darken, lighten, dodge, burn,
-                                            hard_light, soft_light, difference, exclusion);
-
             add_deserializer! {
-                "surface_color_blend_3";
-                [source: Box<SurfaceColorProvider<Point3, Vector3>>]
-                [destination: Box<SurfaceColorProvider<Point3, Vector3>>]
-                [blend_function: Box<BlendFunction>]
-                -> Box<SurfaceColorProvider<Point3, Vector3>> {
-                    surface_color_blend(source, destination, blend_function)
+                "uv_planar_3";
+                [origin: Point3] [axis_u: Vector3] [axis_v: Vector3] -> Box<UVFn<Point3>> {
+                    uv_planar(origin, axis_u, axis_v)
                 }
             }
 
             add_deserializer! {
-                "surface_color_blend_4";
-                [source: Box<SurfaceColorProvider<Point4, Vector4>>]
-                [destination: Box<SurfaceColorProvider<Point4, Vector4>>]
-                [blend_function: Box<BlendFunction>]
-                -> Box<SurfaceColorProvider<Point4, Vector4>> {
-                    surface_color_blend(source, destination, blend_function)
+                "uv_axes_3";
+                [axis_u: u32] [axis_v: u32] [size: F] -> Box<UVFn<Point3>> {
+                    if axis_u >= 3 || axis_v >= 3 {
+                        return Err(ParserError::CustomError {
+                            description: "The axes must be lower than 3.".to_string(),
+                        });
+                    }
+
+                    uv_axes::<Point3, Vector3>(axis_u as usize, axis_v as usize, size)
                 }
             }
 
             add_deserializer! {
-                "surface_color_illumination_global_3";
-                [light_color: Rgba<F>]
-                [dark_color: Rgba<F>]
-                -> Box<SurfaceColorProvider<Point3, Vector3>> {
-                    surface_color_illumination_global(light_color, dark_color)
+                "uv_cylindrical_3";
+                [origin: Point3] [axis: Vector3] [reference: Vector3] -> Box<UVFn<Point3>> {
+                    uv_cylinder(origin, axis, reference)
                 }
             }
 
             add_deserializer! {
-                "surface_color_illumination_global_4";
-                [light_color: Rgba<F>]
-                [dark_color: Rgba<F>]
-                -> Box<SurfaceColorProvider<Point4, Vector4>> {
-                    surface_color_illumination_global(light_color, dark_color)
+                "uv_box_3";
+                [center: Point3] [half_extents: Vector3] -> Box<UVFn<Point3>> {
+                    uv_box(center, half_extents)
                 }
             }
 
             add_deserializer! {
-                "surface_color_perlin_hue_seed_3";
-                [seed: u32] [size: F] [speed: F]
-                -> Box<SurfaceColorProvider<Point3, Vector3>> {
-                    surface_color_perlin_hue_seed(seed, size, speed)
+                "uv_planar_4";
+                [origin: Point4] [axis_u: Vector4] [axis_v: Vector4] -> Box<UVFn<Point4>> {
+                    uv_planar(origin, axis_u, axis_v)
                 }
             }
 
             add_deserializer! {
-                "surface_color_perlin_hue_random_3";
+                "uv_axes_4";
+                [axis_u: u32] [axis_v: u32] [size: F] -> Box<UVFn<Point4>> {
+                    if axis_u >= 4 || axis_v >= 4 {
+                        return Err(ParserError::CustomError {
+                            description: "The axes must be lower than 4.".to_string(),
+                        });
+                    }
+
+                    uv_axes::<Point4, Vector4>(axis_u as usize, axis_v as usize, size)
+                }
+            }
+
+            add_deserializer! {
+                "uv_cylindrical_4";
+                [origin: Point4] [axis: Vector4] [reference: Vector4] [side: Vector4]
+                -> Box<UVFn<Point4>> {
+                    uv_cylindrical(origin, axis, reference, side)
+                }
+            }
+
+            add_deserializer! {
+                "uv_hyperspherical_4";
+                [center: Point4] -> Box<UVFn<Point4>> {
+                    uv_hyperspherical(center)
+                }
+            }
+
+            add_deserializer! {
+                "uv_box_4";
+                [center: Point4] [half_extents: Vector4] -> Box<UVFn<Point4>> {
+                    uv_box(center, half_extents)
+                }
+            }
+
+            add_deserializer! {
+                "texture_image_nearest_neighbor";
+                [path: &str] -> Box<Texture> {
+                    let data = try!(load_image(path)
+                        .map_err(|_| ParserError::CustomError {
+                            description: format!("Could not load texture `{}`", path),
+                        }));
+
+                    texture_image_nearest_neighbor(data)
+                }
+            }
+
+            add_deserializer! {
+                "texture_image_linear";
+                [path: &str] -> Box<Texture> {
+                    let data = try!(load_image(path)
+                        .map_err(|_| ParserError::CustomError {
+                            description: format!("Could not load texture `{}`", path),
+                        }));
+
+                    texture_image_linear(data)
+                }
+            }
+
+            add_deserializer! {
+                "texture_hdr_nearest_neighbor";
+                [path: &str] -> Box<Texture> {
+                    let data = try!(FloatImage::open_hdr(path)
+                        .map_err(|error| ParserError::CustomError {
+                            description: format!("Could not load HDR texture `{}`: {}", path, error),
+                        }));
+
+                    texture_float_image_nearest_neighbor(data)
+                }
+            }
+
+            add_deserializer! {
+                "texture_hdr_linear";
+                [path: &str] -> Box<Texture> {
+                    let data = try!(FloatImage::open_hdr(path)
+                        .map_err(|error| ParserError::CustomError {
+                            description: format!("Could not load HDR texture `{}`: {}", path, error),
+                        }));
+
+                    texture_float_image_linear(data)
+                }
+            }
+
+            add_deserializer! {
+                "texture_mipmap_trilinear";
+                [path: &str] -> Box<TextureFiltered> {
+                    let mipmap = try!(load_mipmap(path)
+                        .map_err(|error| ParserError::CustomError {
+                            description: format!("Could not load texture `{}`: {}", path, error),
+                        }));
+
+                    texture_mipmap_trilinear(mipmap)
+                }
+            }
+
+            add_deserializer! {
+                "texture_mipmap_anisotropic";
+                [path: &str] [max_anisotropy: u32] -> Box<TextureFiltered> {
+                    let mipmap = try!(load_mipmap(path)
+                        .map_err(|error| ParserError::CustomError {
+                            description: format!("Could not load texture `{}`: {}", path, error),
+                        }));
+
+                    texture_mipmap_anisotropic(mipmap, max_anisotropy)
+                }
+            }
+
+            add_deserializer! {
+                "texture_unfiltered";
+                [texture: Box<Texture>] -> Box<TextureFiltered> {
+                    texture_unfiltered(texture)
+                }
+            }
+
+            add_deserializer! {
+                "environment_equirectangular_3";
+                [texture: Box<Texture>] -> Box<EnvironmentSampler<Vector3>> {
+                    environment_equirectangular(texture)
+                }
+            }
+
+            add_deserializer! {
+                "environment_cube_map_3";
+                [faces: Vec<Box<Texture>>] -> Box<EnvironmentSampler<Vector3>> {
+                    if faces.len() != 6 {
+                        return Err(ParserError::CustomError {
+                            description: "A cube map requires exactly 6 faces.".to_string(),
+                        });
+                    }
+
+                    environment_cube_map(faces)
+                }
+            }
+
+            add_deserializer! {
+                "environment_derank_4";
+                [sampler: Box<EnvironmentSampler<Vector3>>] -> Box<EnvironmentSampler<Vector4>> {
+                    environment_derank(sampler)
+                }
+            }
+
+            add_deserializer! {
+                "environment_hopf_4";
+                [sampler: Box<EnvironmentSampler<Vector3>>] -> Box<EnvironmentSampler<Vector4>> {
+                    environment_hopf(sampler)
+                }
+            }
+
+            add_deserializer! {
+                "environment_rotate_3";
+                [sampler: Box<EnvironmentSampler<Vector3>>] [from: Vector3] [to: Vector3] [angle: F]
+                -> Box<EnvironmentSampler<Vector3>> {
+                    environment_rotate::<Point3, Vector3>(sampler, from, to, angle)
+                }
+            }
+
+            add_deserializer! {
+                "environment_intensity_3";
+                [sampler: Box<EnvironmentSampler<Vector3>>] [intensity: F]
+                -> Box<EnvironmentSampler<Vector3>> {
+                    environment_intensity(sampler, intensity)
+                }
+            }
+
+            add_deserializer! {
+                "EnvironmentMap3", "EnvironmentMap3::new";
+                [sampler: Box<EnvironmentSampler<Vector3>>]
+                -> Box<MappedTexture<Point3, Vector3>> {
+                    Box::new(EnvironmentMap::new(sampler))
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_illumination_environment_3";
+                [sampler: Box<EnvironmentSampler<Vector3>>] [albedo: Rgba<F>] [samples: u32]
+                -> Box<SurfaceColorProvider<Point3, Vector3>> {
+                    surface_color_illumination_environment(sampler, albedo, samples)
+                }
+            }
+
+            add_deserializer! {
+                "environment_rotate_4";
+                [sampler: Box<EnvironmentSampler<Vector4>>] [from: Vector4] [to: Vector4] [angle: F]
+                -> Box<EnvironmentSampler<Vector4>> {
+                    environment_rotate::<Point4, Vector4>(sampler, from, to, angle)
+                }
+            }
+
+            add_deserializer! {
+                "environment_intensity_4";
+                [sampler: Box<EnvironmentSampler<Vector4>>] [intensity: F]
+                -> Box<EnvironmentSampler<Vector4>> {
+                    environment_intensity(sampler, intensity)
+                }
+            }
+
+            add_deserializer! {
+                "EnvironmentMap4", "EnvironmentMap4::new";
+                [sampler: Box<EnvironmentSampler<Vector4>>]
+                -> Box<MappedTexture<Point4, Vector4>> {
+                    Box::new(EnvironmentMap::new(sampler))
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_illumination_environment_4";
+                [sampler: Box<EnvironmentSampler<Vector4>>] [albedo: Rgba<F>] [samples: u32]
+                -> Box<SurfaceColorProvider<Point4, Vector4>> {
+                    surface_color_illumination_environment(sampler, albedo, samples)
+                }
+            }
+
+            add_deserializer! {
+                "MappedTextureImpl3", "MappedTextureImpl3::new";
+                [uvfn: Box<UVFn<Point3>>]
+                [texture: Box<Texture>]
+                -> Box<MappedTexture<Point3, Vector3>> {
+                    Box::new(MappedTextureImpl::new(uvfn, texture))
+                }
+            }
+
+            add_deserializer! {
+                "MappedTextureImpl4", "MappedTextureImpl4::new";
+                [uvfn: Box<UVFn<Point4>>]
+                [texture: Box<Texture>]
+                -> Box<MappedTexture<Point4, Vector4>> {
+                    Box::new(MappedTextureImpl::new(uvfn, texture))
+                }
+            }
+
+            add_deserializer! {
+                "MappedTextureTriplanar3", "MappedTextureTriplanar3::new";
+                [texture: Box<Texture>]
+                [origin: Point3]
+                [size: F]
+                [sharpness: F]
+                -> Box<MappedTexture<Point3, Vector3>> {
+                    Box::new(MappedTextureTriplanar::new(texture, origin, size, sharpness))
+                }
+            }
+
+            add_deserializer! {
+                "MappedTextureTriplanar4", "MappedTextureTriplanar4::new";
+                [texture: Box<Texture>]
+                [origin: Point4]
+                [size: F]
+                [sharpness: F]
+                -> Box<MappedTexture<Point4, Vector4>> {
+                    Box::new(MappedTextureTriplanar::new(texture, origin, size, sharpness))
+                }
+            }
+
+            add_deserializer! {
+                "MappedTextureFiltered3", "MappedTextureFiltered3::new";
+                [uvfn: Box<UVFn<Point3>>]
+                [texture: Box<TextureFiltered>]
+                -> Box<MappedTexture<Point3, Vector3>> {
+                    Box::new(MappedTextureFiltered::new(uvfn, texture))
+                }
+            }
+
+            add_deserializer! {
+                "MappedTextureFiltered4", "MappedTextureFiltered4::new";
+                [uvfn: Box<UVFn<Point4>>]
+                [texture: Box<TextureFiltered>]
+                -> Box<MappedTexture<Point4, Vector4>> {
+                    Box::new(MappedTextureFiltered::new(uvfn, texture))
+                }
+            }
+
+            add_deserializer! {
+                "ComposableSurface3", "ComposableSurface3::new";
+                [reflection_ratio: Box<ReflectionRatioProvider<Point3, Vector3>>]
+                [reflection_direction: Box<ReflectionDirectionProvider<Point3, Vector3>>]
+                [threshold_direction: Box<ThresholdDirectionProvider<Point3, Vector3>>]
+                [surface_color: Box<SurfaceColorProvider<Point3, Vector3>>]
+                -> Box<Surface<Point3, Vector3>> {
+                    Box::new(ComposableSurface {
+                        reflection_ratio: reflection_ratio.into(),
+                        reflection_direction: reflection_direction.into(),
+                        threshold_direction: threshold_direction.into(),
+                        surface_color: surface_color.into(),
+                        normal: normal_geometric().into(),
+                    })
+                }
+            }
+
+            add_deserializer! {
+                "ComposableSurface3::new_with_normal";
+                [reflection_ratio: Box<ReflectionRatioProvider<Point3, Vector3>>]
+                [reflection_direction: Box<ReflectionDirectionProvider<Point3, Vector3>>]
+                [threshold_direction: Box<ThresholdDirectionProvider<Point3, Vector3>>]
+                [surface_color: Box<SurfaceColorProvider<Point3, Vector3>>]
+                [normal: Box<NormalProvider<Point3, Vector3>>]
+                -> Box<Surface<Point3, Vector3>> {
+                    Box::new(ComposableSurface {
+                        reflection_ratio: reflection_ratio.into(),
+                        reflection_direction: reflection_direction.into(),
+                        threshold_direction: threshold_direction.into(),
+                        surface_color: surface_color.into(),
+                        normal: normal.into(),
+                    })
+                }
+            }
+
+            add_deserializer! {
+                "ComposableSurface4", "ComposableSurface4::new";
+                [reflection_ratio: Box<ReflectionRatioProvider<Point4, Vector4>>]
+                [reflection_direction: Box<ReflectionDirectionProvider<Point4, Vector4>>]
+                [threshold_direction: Box<ThresholdDirectionProvider<Point4, Vector4>>]
+                [surface_color: Box<SurfaceColorProvider<Point4, Vector4>>]
+                -> Box<Surface<Point4, Vector4>> {
+                    Box::new(ComposableSurface {
+                        reflection_ratio: reflection_ratio.into(),
+                        reflection_direction: reflection_direction.into(),
+                        threshold_direction: threshold_direction.into(),
+                        surface_color: surface_color.into(),
+                        normal: normal_geometric().into(),
+                    })
+                }
+            }
+
+            add_deserializer! {
+                "ComposableSurface4::new_with_normal";
+                [reflection_ratio: Box<ReflectionRatioProvider<Point4, Vector4>>]
+                [reflection_direction: Box<ReflectionDirectionProvider<Point4, Vector4>>]
+                [threshold_direction: Box<ThresholdDirectionProvider<Point4, Vector4>>]
+                [surface_color: Box<SurfaceColorProvider<Point4, Vector4>>]
+                [normal: Box<NormalProvider<Point4, Vector4>>]
+                -> Box<Surface<Point4, Vector4>> {
+                    Box::new(ComposableSurface {
+                        reflection_ratio: reflection_ratio.into(),
+                        reflection_direction: reflection_direction.into(),
+                        threshold_direction: threshold_direction.into(),
+                        surface_color: surface_color.into(),
+                        normal: normal.into(),
+                    })
+                }
+            }
+
+            add_deserializer! {
+                "normal_geometric_3";
+                -> Box<NormalProvider<Point3, Vector3>> {
+                    normal_geometric()
+                }
+            }
+
+            add_deserializer! {
+                "normal_bump_3";
+                [height: Box<HeightProvider<Point3>>] [strength: F]
+                -> Box<NormalProvider<Point3, Vector3>> {
+                    normal_bump(height, strength)
+                }
+            }
+
+            add_deserializer! {
+                "normal_map_3";
+                [uvfn: Box<UVFn<Point3>>] [texture: Box<Texture>] [strength: F]
+                -> Box<NormalProvider<Point3, Vector3>> {
+                    normal_map(uvfn, texture, strength)
+                }
+            }
+
+            add_deserializer! {
+                "height_texture_3";
+                [uvfn: Box<UVFn<Point3>>] [texture: Box<Texture>]
+                -> Box<HeightProvider<Point3>> {
+                    height_texture::<Point3, Vector3>(uvfn, texture)
+                }
+            }
+
+            add_deserializer! {
+                "height_perlin_3", "scalar_perlin_3";
+                [seed: u32] [size: F] [speed: F]
+                -> Box<HeightProvider<Point3>> {
+                    height_perlin::<Point3, Vector3>(seed as usize, size, speed)
+                }
+            }
+
+            add_deserializer! {
+                "normal_geometric_4";
+                -> Box<NormalProvider<Point4, Vector4>> {
+                    normal_geometric()
+                }
+            }
+
+            add_deserializer! {
+                "normal_bump_4";
+                [height: Box<HeightProvider<Point4>>] [strength: F]
+                -> Box<NormalProvider<Point4, Vector4>> {
+                    normal_bump(height, strength)
+                }
+            }
+
+            add_deserializer! {
+                "normal_map_4";
+                [uvfn: Box<UVFn<Point4>>] [texture: Box<Texture>] [strength: F]
+                -> Box<NormalProvider<Point4, Vector4>> {
+                    normal_map(uvfn, texture, strength)
+                }
+            }
+
+            add_deserializer! {
+                "height_texture_4";
+                [uvfn: Box<UVFn<Point4>>] [texture: Box<Texture>]
+                -> Box<HeightProvider<Point4>> {
+                    height_texture::<Point4, Vector4>(uvfn, texture)
+                }
+            }
+
+            add_deserializer! {
+                "height_perlin_4", "scalar_perlin_4";
+                [seed: u32] [size: F] [speed: F]
+                -> Box<HeightProvider<Point4>> {
+                    height_perlin::<Point4, Vector4>(seed as usize, size, speed)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_fbm_3";
+                [seed: u32] [size: F] [speed: F] [octaves: u32] [lacunarity: F] [gain: F]
+                -> Box<HeightProvider<Point3>> {
+                    scalar_fbm::<Point3, Vector3>(seed as usize, size, speed, octaves, lacunarity, gain)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_turbulence_3";
+                [seed: u32] [size: F] [speed: F] [octaves: u32] [lacunarity: F] [gain: F]
+                -> Box<HeightProvider<Point3>> {
+                    scalar_turbulence::<Point3, Vector3>(seed as usize, size, speed, octaves, lacunarity, gain)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_worley_3";
+                [seed: u32] [size: F] [speed: F]
+                -> Box<HeightProvider<Point3>> {
+                    scalar_worley::<Point3, Vector3>(seed as u64, size, speed)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_voronoi_3";
+                [seed: u32] [size: F] [speed: F]
+                -> Box<HeightProvider<Point3>> {
+                    scalar_voronoi::<Point3, Vector3>(seed as u64, size, speed)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_checkerboard_3";
+                [size: F]
+                -> Box<HeightProvider<Point3>> {
+                    scalar_checkerboard::<Point3, Vector3>(size)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_gradient_3";
+                [direction: Vector3]
+                -> Box<HeightProvider<Point3>> {
+                    scalar_gradient(direction)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_radial_3";
+                [center: Point3]
+                -> Box<HeightProvider<Point3>> {
+                    scalar_radial::<Point3, Vector3>(center)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_ramp_3";
+                [field: Box<HeightProvider<Point3>>] [positions: Vec<F>] [colors: Vec<Rgba<F>>]
+                -> Box<SurfaceColorProvider<Point3, Vector3>> {
+                    surface_color_ramp(field, positions, colors)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_checkerboard_3";
+                [size: F] [color_even: Rgba<F>] [color_odd: Rgba<F>]
+                -> Box<SurfaceColorProvider<Point3, Vector3>> {
+                    surface_color_checkerboard(size, color_even, color_odd)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_fbm_4";
+                [seed: u32] [size: F] [speed: F] [octaves: u32] [lacunarity: F] [gain: F]
+                -> Box<HeightProvider<Point4>> {
+                    scalar_fbm::<Point4, Vector4>(seed as usize, size, speed, octaves, lacunarity, gain)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_turbulence_4";
+                [seed: u32] [size: F] [speed: F] [octaves: u32] [lacunarity: F] [gain: F]
+                -> Box<HeightProvider<Point4>> {
+                    scalar_turbulence::<Point4, Vector4>(seed as usize, size, speed, octaves, lacunarity, gain)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_worley_4";
+                [seed: u32] [size: F] [speed: F]
+                -> Box<HeightProvider<Point4>> {
+                    scalar_worley::<Point4, Vector4>(seed as u64, size, speed)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_voronoi_4";
+                [seed: u32] [size: F] [speed: F]
+                -> Box<HeightProvider<Point4>> {
+                    scalar_voronoi::<Point4, Vector4>(seed as u64, size, speed)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_checkerboard_4";
+                [size: F]
+                -> Box<HeightProvider<Point4>> {
+                    scalar_checkerboard::<Point4, Vector4>(size)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_gradient_4";
+                [direction: Vector4]
+                -> Box<HeightProvider<Point4>> {
+                    scalar_gradient(direction)
+                }
+            }
+
+            add_deserializer! {
+                "scalar_radial_4";
+                [center: Point4]
+                -> Box<HeightProvider<Point4>> {
+                    scalar_radial::<Point4, Vector4>(center)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_ramp_4";
+                [field: Box<HeightProvider<Point4>>] [positions: Vec<F>] [colors: Vec<Rgba<F>>]
+                -> Box<SurfaceColorProvider<Point4, Vector4>> {
+                    surface_color_ramp(field, positions, colors)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_checkerboard_4";
+                [size: F] [color_even: Rgba<F>] [color_odd: Rgba<F>]
+                -> Box<SurfaceColorProvider<Point4, Vector4>> {
+                    surface_color_checkerboard(size, color_even, color_odd)
+                }
+            }
+
+            add_deserializer! {
+                "blend_function_ratio";
+                [ratio: F] -> Box<BlendFunction> {
+                    blend_function_ratio(ratio)
+                }
+            }
+
+            macro_rules! deserialize_blending_functions {
+                (
+                    $($name:ident),+
+                ) => {
+                    $(
+                        add_deserializer! {
+                            concat!(
+                                "blend_function_",
+                                stringify!($name)
+                            );
+                            -> Box<BlendFunction> {
+                                mashup! {
+                                    substitute["blend_function"] = blend_function_ $name;
+                                }
+
+                                substitute! {
+                                    "blend_function"()
+                                }
+                                // concat_idents!(blend_function_, $name)()
+                            }
+                        }
+                    )+
+                }
+            }
+
+            deserialize_blending_functions!(over, inside, outside, atop, xor, plus, multiply,
+                                            screen, overlay, darken, lighten, dodge, burn,
+                                            hard_light, soft_light, difference, exclusion);
+
+            add_deserializer! {
+                "surface_color_blend_3";
+                [source: Box<SurfaceColorProvider<Point3, Vector3>>]
+                [destination: Box<SurfaceColorProvider<Point3, Vector3>>]
+                [blend_function: Box<BlendFunction>]
+                -> Box<SurfaceColorProvider<Point3, Vector3>> {
+                    surface_color_blend(source, destination, blend_function)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_blend_4";
+                [source: Box<SurfaceColorProvider<Point4, Vector4>>]
+                [destination: Box<SurfaceColorProvider<Point4, Vector4>>]
+                [blend_function: Box<BlendFunction>]
+                -> Box<SurfaceColorProvider<Point4, Vector4>> {
+                    surface_color_blend(source, destination, blend_function)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_illumination_global_3";
+                [light_color: Rgba<F>]
+                [dark_color: Rgba<F>]
+                -> Box<SurfaceColorProvider<Point3, Vector3>> {
+                    surface_color_illumination_global(light_color, dark_color)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_illumination_global_4";
+                [light_color: Rgba<F>]
+                [dark_color: Rgba<F>]
+                -> Box<SurfaceColorProvider<Point4, Vector4>> {
+                    surface_color_illumination_global(light_color, dark_color)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_perlin_hue_seed_3";
+                [seed: u32] [size: F] [speed: F]
+                -> Box<SurfaceColorProvider<Point3, Vector3>> {
+                    surface_color_perlin_hue_seed(seed, size, speed)
+                }
+            }
+
+            add_deserializer! {
+                "surface_color_perlin_hue_random_3";
                 [size: F] [speed: F]
                 -> Box<SurfaceColorProvider<Point3, Vector3>> {
                     surface_color_perlin_hue_random(size, speed)
@@ -1317,6 +2180,63 @@ impl Parser {
                 }
             }
 
+            add_deserializer! {
+                "refractive_index_constant";
+                [index: F] -> Box<RefractiveIndexProvider> {
+                    refractive_index_constant(index)
+                }
+            }
+
+            add_deserializer! {
+                "refractive_index_cauchy";
+                [coefficients: Vec<F>] -> Box<RefractiveIndexProvider> {
+                    refractive_index_cauchy(coefficients)
+                }
+            }
+
+            add_deserializer! {
+                "refractive_index_sellmeier";
+                [b: Vec<F>] [c: Vec<F>] -> Box<RefractiveIndexProvider> {
+                    refractive_index_sellmeier(b, c)
+                }
+            }
+
+            add_deserializer! {
+                "threshold_direction_snell_dispersive_3";
+                [refractive_index: Box<RefractiveIndexProvider>]
+                -> Box<ThresholdDirectionProvider<Point3, Vector3>> {
+                    threshold_direction_snell_dispersive(refractive_index)
+                }
+            }
+
+            add_deserializer! {
+                "threshold_direction_snell_dispersive_4";
+                [refractive_index: Box<RefractiveIndexProvider>]
+                -> Box<ThresholdDirectionProvider<Point4, Vector4>> {
+                    threshold_direction_snell_dispersive(refractive_index)
+                }
+            }
+
+            add_deserializer! {
+                "reflection_ratio_fresnel_dispersive_3";
+                [refractive_index_inside: Box<RefractiveIndexProvider>]
+                [refractive_index_outside: Box<RefractiveIndexProvider>]
+                -> Box<ReflectionRatioProvider<Point3, Vector3>> {
+                    reflection_ratio_fresnel_dispersive(refractive_index_inside,
+                                                        refractive_index_outside)
+                }
+            }
+
+            add_deserializer! {
+                "reflection_ratio_fresnel_dispersive_4";
+                [refractive_index_inside: Box<RefractiveIndexProvider>]
+                [refractive_index_outside: Box<RefractiveIndexProvider>]
+                -> Box<ReflectionRatioProvider<Point4, Vector4>> {
+                    reflection_ratio_fresnel_dispersive(refractive_index_inside,
+                                                        refractive_index_outside)
+                }
+            }
+
             add_deserializer! {
                 "surface_color_texture_3";
                 [mapped_texture: Box<MappedTexture<Point3, Vector3>>]
@@ -1335,6 +2255,24 @@ impl Parser {
 
             // Environments
 
+            add_deserializer! {
+                "ToneMapper", "ToneMapper::new";
+                [operator: &str] [exposure: F] [gamma: F] -> ToneMapper {
+                    if gamma.is_nan() || gamma <= 0.0 {
+                        return Err(ParserError::CustomError {
+                            description: "The gamma must be positive.".to_string(),
+                        });
+                    }
+
+                    let operator = try!(operator.parse::<ToneMapping>()
+                        .map_err(|description| ParserError::CustomError {
+                            description: description,
+                        }));
+
+                    ToneMapper::new(operator, exposure, gamma)
+                }
+            }
+
             add_deserializer! {
                 "Universe3", "Universe3::new";
                 [camera: Box<Camera3>]
@@ -1350,6 +2288,23 @@ impl Parser {
                 }
             }
 
+            add_deserializer! {
+                "Universe3::new_with_tone_mapper";
+                [camera: Box<Camera3>]
+                [entities: Vec<Box<Entity<Point3, Vector3>>>]
+                [background: Box<MappedTexture<Point3, Vector3>>]
+                [tone_mapper: ToneMapper]
+                -> Box<Environment> {
+                    let mut universe = Universe3::construct(camera);
+
+                    universe.set_entities(entities);
+                    universe.set_background(background);
+                    universe.set_tone_mapper(tone_mapper);
+
+                    Box::new(universe)
+                }
+            }
+
             add_deserializer! {
                 "PitchYawCamera3", "PitchYawCamera3::new";
                 -> Box<Camera3> {
@@ -1378,6 +2333,198 @@ impl Parser {
                 }
             }
 
+            add_deserializer! {
+                "PitchYawCamera3::new_with_projection";
+                [location: Point3]
+                [projection: Projection3] -> Box<Camera3> {
+                    Box::new(PitchYawCamera3::new_with_location(location)
+                             .with_projection(projection))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera3::new_with_projection";
+                [location: Point3]
+                [projection: Projection3] -> Box<Camera3> {
+                    Box::new(FreeCamera3::new_with_location(location)
+                             .with_projection(projection))
+                }
+            }
+
+            add_deserializer! {
+                "PitchYawCamera3::new_with_stereo";
+                [location: Point3]
+                [projection: Projection3]
+                [lens: ThinLens]
+                [stereo: Stereo] -> Box<Camera3> {
+                    Box::new(PitchYawCamera3::new_with_location(location)
+                             .with_projection(projection)
+                             .with_lens(lens)
+                             .with_stereo(stereo))
+                }
+            }
+
+            add_deserializer! {
+                "PitchYawCamera3::new_with_stereo_pinhole";
+                [location: Point3]
+                [projection: Projection3]
+                [stereo: Stereo] -> Box<Camera3> {
+                    Box::new(PitchYawCamera3::new_with_location(location)
+                             .with_projection(projection)
+                             .with_stereo(stereo))
+                }
+            }
+
+            add_deserializer! {
+                "PitchYawCamera3::new_with_lens";
+                [location: Point3]
+                [projection: Projection3]
+                [lens: ThinLens] -> Box<Camera3> {
+                    Box::new(PitchYawCamera3::new_with_location(location)
+                             .with_projection(projection)
+                             .with_lens(lens))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera3::new_with_stereo";
+                [location: Point3]
+                [projection: Projection3]
+                [lens: ThinLens]
+                [stereo: Stereo] -> Box<Camera3> {
+                    Box::new(FreeCamera3::new_with_location(location)
+                             .with_projection(projection)
+                             .with_lens(lens)
+                             .with_stereo(stereo))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera3::new_with_stereo_pinhole";
+                [location: Point3]
+                [projection: Projection3]
+                [stereo: Stereo] -> Box<Camera3> {
+                    Box::new(FreeCamera3::new_with_location(location)
+                             .with_projection(projection)
+                             .with_stereo(stereo))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera3::new_with_lens";
+                [location: Point3]
+                [projection: Projection3]
+                [lens: ThinLens] -> Box<Camera3> {
+                    Box::new(FreeCamera3::new_with_location(location)
+                             .with_projection(projection)
+                             .with_lens(lens))
+                }
+            }
+
+            add_deserializer! {
+                "PitchYawCamera3::new_walking";
+                [location: Point3] -> Box<Camera3> {
+                    Box::new(PitchYawCamera3::new_with_location(location)
+                             .with_walking(true))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera3::new_with_collision";
+                [location: Point3] -> Box<Camera3> {
+                    Box::new(FreeCamera3::new_with_location(location)
+                             .with_collision(true))
+                }
+            }
+
+            add_deserializer! {
+                "Projection3::perspective";
+                -> Projection3 {
+                    Projection3::Perspective
+                }
+            }
+
+            add_deserializer! {
+                "Projection3::orthographic";
+                [size: F] -> Projection3 {
+                    Projection3::Orthographic(size)
+                }
+            }
+
+            add_deserializer! {
+                "Projection3::fisheye";
+                -> Projection3 {
+                    Projection3::Fisheye
+                }
+            }
+
+            add_deserializer! {
+                "Projection3::equirectangular";
+                -> Projection3 {
+                    Projection3::Equirectangular
+                }
+            }
+
+            add_deserializer! {
+                "Projection3::panini";
+                [distance: F] -> Projection3 {
+                    Projection3::Panini(distance)
+                }
+            }
+
+            add_deserializer! {
+                "ThinLens", "ThinLens::new";
+                [radius: F]
+                [focal_distance: F]
+                [aperture: Aperture]
+                [autofocus: bool] -> ThinLens {
+                    ThinLens::new(radius, focal_distance)
+                        .with_aperture(aperture)
+                        .with_autofocus(autofocus)
+                }
+            }
+
+            add_deserializer! {
+                "Stereo::side_by_side";
+                [eye_separation: F] -> Stereo {
+                    Stereo::new(StereoLayout::SideBySide, eye_separation)
+                }
+            }
+
+            add_deserializer! {
+                "Stereo::top_bottom";
+                [eye_separation: F] -> Stereo {
+                    Stereo::new(StereoLayout::TopBottom, eye_separation)
+                }
+            }
+
+            add_deserializer! {
+                "Stereo::anaglyph";
+                [eye_separation: F] -> Stereo {
+                    Stereo::new(StereoLayout::Anaglyph, eye_separation)
+                }
+            }
+
+            add_deserializer! {
+                "Aperture::circle";
+                -> Aperture {
+                    Aperture::Circle
+                }
+            }
+
+            add_deserializer! {
+                "Aperture::polygon";
+                [blades: u32] -> Aperture {
+                    if blades < 3 {
+                        return Err(ParserError::CustomError {
+                            description: "An aperture polygon needs at least 3 blades.".to_string(),
+                        });
+                    }
+
+                    Aperture::Polygon(blades)
+                }
+            }
+
             add_deserializer! {
                 "Universe4", "Universe4::new";
                 [camera: Box<Camera4>]
@@ -1393,6 +2540,23 @@ impl Parser {
                 }
             }
 
+            add_deserializer! {
+                "Universe4::new_with_tone_mapper";
+                [camera: Box<Camera4>]
+                [entities: Vec<Box<Entity<Point4, Vector4>>>]
+                [background: Box<MappedTexture<Point4, Vector4>>]
+                [tone_mapper: ToneMapper]
+                -> Box<Environment> {
+                    let mut universe = Universe4::construct(camera);
+
+                    universe.set_entities(entities);
+                    universe.set_background(background);
+                    universe.set_tone_mapper(tone_mapper);
+
+                    Box::new(universe)
+                }
+            }
+
             add_deserializer! {
                 "FreeCamera4", "FreeCamera4::new";
                 -> Box<Camera4> {
@@ -1406,6 +2570,116 @@ impl Parser {
                     Box::new(FreeCamera4::new_with_location(location))
                 }
             }
+
+            add_deserializer! {
+                "FreeCamera4::new_with_collision";
+                [location: Point4] -> Box<Camera4> {
+                    Box::new(FreeCamera4::new_with_location(location)
+                             .with_collision(true))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera4::new_with_projection";
+                [location: Point4]
+                [projection: Projection4]
+                [retina_view: RetinaView] -> Box<Camera4> {
+                    Box::new(FreeCamera4::new_with_location(location)
+                             .with_projection(projection)
+                             .with_retina_view(retina_view))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera4::new_with_stereo_pinhole";
+                [location: Point4]
+                [projection: Projection4]
+                [retina_view: RetinaView]
+                [stereo: Stereo] -> Box<Camera4> {
+                    Box::new(FreeCamera4::new_with_location(location)
+                             .with_projection(projection)
+                             .with_retina_view(retina_view)
+                             .with_stereo(stereo))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera4::new_with_stereo";
+                [location: Point4]
+                [projection: Projection4]
+                [retina_view: RetinaView]
+                [lens: ThinLens]
+                [stereo: Stereo] -> Box<Camera4> {
+                    Box::new(FreeCamera4::new_with_location(location)
+                             .with_projection(projection)
+                             .with_retina_view(retina_view)
+                             .with_lens(lens)
+                             .with_stereo(stereo))
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera4::new_with_lens";
+                [location: Point4]
+                [projection: Projection4]
+                [retina_view: RetinaView]
+                [lens: ThinLens] -> Box<Camera4> {
+                    Box::new(FreeCamera4::new_with_location(location)
+                             .with_projection(projection)
+                             .with_retina_view(retina_view)
+                             .with_lens(lens))
+                }
+            }
+
+            add_deserializer! {
+                "Projection4::perspective";
+                -> Projection4 {
+                    Projection4::Perspective
+                }
+            }
+
+            add_deserializer! {
+                "Projection4::orthographic";
+                [size: F] -> Projection4 {
+                    Projection4::Orthographic(size)
+                }
+            }
+
+            add_deserializer! {
+                "Projection4::stereographic";
+                -> Projection4 {
+                    Projection4::Stereographic
+                }
+            }
+
+            add_deserializer! {
+                "RetinaView::slice";
+                -> RetinaView {
+                    RetinaView::Slice
+                }
+            }
+
+            add_deserializer! {
+                "RetinaView::slices";
+                [count: u32] -> RetinaView {
+                    RetinaView::Slices(count)
+                }
+            }
+
+            add_deserializer! {
+                "RetinaView::rotating";
+                -> RetinaView {
+                    RetinaView::Rotating
+                }
+            }
+
+            add_deserializer! {
+                "FreeCamera4::new_cross_section";
+                [location: Point4]
+                [offset: F] -> Box<Camera4> {
+                    Box::new(FreeCamera4::new_cross_section(location, offset))
+                }
+            }
         }
 
         parser
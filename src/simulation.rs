@@ -1,5 +1,12 @@
 use ::F;
+use std::borrow::Cow;
 use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
 use std::time::Instant;
 use std::time::Duration;
 use num::One;
@@ -10,6 +17,7 @@ use na::Vector2;
 use na::BaseFloat;
 use glium::Surface as GliumSurface;
 use glium::BlitTarget;
+use glium::Frame;
 use glium::backend::glutin::Display;
 use glium::glutin::ContextBuilder;
 use glium::glutin::Event;
@@ -23,19 +31,40 @@ use glium::glutin::WindowEvent;
 use glium::glutin::MouseScrollDelta;
 use glium::glutin::MouseCursor;
 use glium::glutin::WindowBuilder;
+use glium::texture::ClientFormat;
+use glium::texture::RawImage2d;
 use glium::texture::Texture2d;
 use glium::uniforms::MagnifySamplerFilter;
+use scoped_threadpool::Pool;
+use overlay;
+use output;
 use universe::Environment;
+use universe::statistics::FrameStatistics;
+use universe::inspection::RayInspection;
+use universe::inspection::RayKind;
+use universe::tone_mapping::ToneMapper;
+use universe::tone_mapping::ToneMapping;
+use universe::render_pass::RenderPass;
+use universe::aov::AovLayer;
+use universe::denoise::Denoiser;
 use util::CustomFloat;
 
 pub struct Simulation {
     events_loop: Option<EventsLoop>,
     debug: bool,
-    threads: u32,
+    /// Reused by every rendered frame
+    pool: Pool,
     environment: Box<Environment>,
     display: Option<Display>,
     start_instant: Option<Instant>,
     last_updated_instant: Option<Instant>,
+    /// The time between the last two updates
+    frame_duration: Duration,
+    frame_index: u64,
+    statistics_writer: Option<BufWriter<File>>,
+    /// The last captured ray inspection
+    inspection: Option<RayInspection>,
+    inspection_index: u32,
     context: SimulationContext,
 }
 
@@ -43,6 +72,15 @@ pub struct SimulationBuilder {
     environment: Option<Box<Environment>>,
     threads: Option<u32>,
     debug: bool,
+    spectral_samples: u32,
+    samples: u32,
+    tone_mapping: Option<ToneMapping>,
+    exposure: Option<F>,
+    gamma: Option<F>,
+    statistics: bool,
+    statistics_csv: Option<PathBuf>,
+    render_pass: RenderPass,
+    denoise: bool,
 }
 
 impl Simulation {
@@ -75,30 +113,128 @@ impl Simulation {
         }
     }
 
+    /// Renders a single frame at the full resolution without opening a window and writes
+    /// the layers to the path, see `output::write_aov_image`.
+    pub fn render_offline(mut self, dimensions: (u32, u32), layers: &[AovLayer], path: &Path) {
+        self.context.resolution = 1;
+
+        let time = Duration::new(0, 0);
+        let tone_mapper = self.context.tone_mapper(self.environment.tone_mapper());
+        let mut image = self.environment.render_aovs(dimensions, &time, &mut self.pool,
+                                                     &self.context, layers, None);
+
+        if self.context.denoising {
+            let colors = Denoiser::new().denoise(&image);
+
+            for (sample, color) in image.samples.iter_mut().zip(colors) {
+                sample.beauty = color;
+            }
+        }
+
+        output::write_aov_image(path, &image, &tone_mapper)
+            .expect("Could not write the rendered image.");
+    }
+
+    /// Renders the given number of frames at the full resolution without opening a window
+    /// and prints the average frame time and the number of traced rays per second.
+    pub fn benchmark(mut self, dimensions: (u32, u32), frames: u32) {
+        self.context.resolution = 1;
+
+        let time = Duration::new(0, 0);
+        let statistics = Mutex::new(FrameStatistics::default());
+        let start = Instant::now();
+
+        for _ in 0..frames {
+            self.environment.render(dimensions, &time, &mut self.pool, &self.context,
+                                    Some(&statistics), None);
+        }
+
+        let total_millis = duration_millis(&start.elapsed());
+        let statistics = statistics.into_inner()
+            .expect("Could not read the statistics, the lock is poisoned.");
+
+        println!("Rendered {} frames of {}x{} in {:.1} ms", frames, dimensions.0, dimensions.1,
+                 total_millis);
+        println!("Frame time: {:.1} ms", total_millis / frames.max(1) as F);
+        println!("Rays per second: {:.0}", statistics.rays as F * 1000.0 / total_millis);
+    }
+
     fn render(&mut self) {
-        let frame = self.display.as_mut().unwrap().draw();
-        let readable_display = self.display.as_ref().unwrap();
-        let dimensions = frame.get_dimensions();
-        let (width, height) = dimensions;
+        let dimensions = self.display.as_ref().unwrap().get_framebuffer_dimensions();
+        let height = dimensions.1;
         let now = Instant::now();
         let time = now - self.start_instant.unwrap();
 
-        let image = self.environment.render(dimensions, &time, self.threads, &self.context);
+        if self.context.inspecting {
+            self.context.inspecting = false;
+            self.inspect(dimensions, &time);
+        }
+
+        let readable_display = self.display.as_ref().unwrap();
+        let statistics = if self.context.statistics || self.statistics_writer.is_some() {
+            Some(Mutex::new(FrameStatistics::default()))
+        } else {
+            None
+        };
+
+        let mut image = if self.context.denoising && self.context.render_pass == RenderPass::Shaded {
+            render_denoised(self.environment.as_ref(), dimensions, &time, &mut self.pool,
+                            &self.context, statistics.as_ref())
+        } else {
+            // Slow frames are shown while they are being rendered
+            let mut preview = |image: RawImage2d<u8>| {
+                let frame = readable_display.draw();
+
+                blit_image(readable_display, &frame, image);
+                finish_frame(frame);
+            };
 
-        let texture = Texture2d::new(readable_display, image).unwrap();
-        let image_surface = texture.as_surface();
-        let blit_target = BlitTarget {
-            left: 0,
-            bottom: 0,
-            width: width as i32,
-            height: height as i32,
+            self.environment.render(dimensions, &time, &mut self.pool, &self.context,
+                                    statistics.as_ref(), Some(&mut preview))
         };
-        image_surface.blit_whole_color_to(&frame, &blit_target, MagnifySamplerFilter::Nearest);
+        let render_duration = now.elapsed();
 
-        if let Err(error) = frame.finish() {
-            panic!("An error occured while swapping the OpenGL buffers: {:?}",
-                   error)
+        if self.context.inspection_lines {
+            if let Some(ref inspection) = self.inspection {
+                draw_inspection(self.environment.as_ref(), inspection, &mut image);
+            }
+        }
+
+        let frame = readable_display.draw();
+
+        blit_image(readable_display, &frame, image);
+
+        if let Some(statistics) = statistics {
+            let statistics = statistics.into_inner()
+                .expect("Could not read the statistics, the lock is poisoned.");
+
+            if self.context.statistics {
+                let lines = statistics_lines(&statistics, &self.frame_duration, &render_duration,
+                                             self.context.resolution, self.context.render_pass);
+                let overlay_image = overlay::render_text(&lines, 2);
+                let (overlay_width, overlay_height) = (overlay_image.width, overlay_image.height);
+                let overlay = Texture2d::new(readable_display, overlay_image).unwrap();
+                let overlay_target = BlitTarget {
+                    left: 0,
+                    bottom: height.saturating_sub(overlay_height),
+                    width: overlay_width as i32,
+                    height: overlay_height as i32,
+                };
+
+                overlay.as_surface().blit_whole_color_to(&frame, &overlay_target,
+                                                         MagnifySamplerFilter::Nearest);
+            }
+
+            if let Some(ref mut writer) = self.statistics_writer {
+                write_statistics_row(writer, self.frame_index, &time, &statistics,
+                                     &self.frame_duration, &render_duration,
+                                     self.context.resolution)
+                    .expect("Could not write the statistics.");
+            }
         }
+
+        self.frame_index += 1;
+        finish_frame(frame);
     }
 
     fn update(&mut self) -> Result<(), WindowEvent> {
@@ -118,6 +254,7 @@ impl Simulation {
         }
 
         self.last_updated_instant = Some(now);
+        self.frame_duration = delta;
         let result = self.context.update(&mut self.events_loop, self.display.as_ref().unwrap(), self.debug);
 
         self.environment.update(&delta, &self.context);
@@ -125,15 +262,172 @@ impl Simulation {
         result
     }
 
+    /// Captures the rays of the pixel in the center of the screen and writes them
+    /// to a JSON file in the working directory.
+    fn inspect(&mut self, dimensions: (u32, u32), time: &Duration) {
+        let inspection = self.environment.inspect(dimensions, time, &self.context);
+        let path = format!("ray_inspection_{}.json", self.inspection_index);
+        let result = File::create(&path)
+            .and_then(|mut file| file.write_all(inspection.to_json().pretty(2).as_bytes()));
+
+        match result {
+            Ok(()) => println!("The ray inspection was written to `{}`.", path),
+            Err(error) => println!("Could not write the ray inspection to `{}`: {}", path, error),
+        }
+
+        self.inspection_index += 1;
+        self.inspection = Some(inspection);
+    }
+
     pub fn builder() -> SimulationBuilder {
         SimulationBuilder {
             environment: None,
             threads: None,
             debug: false,
+            spectral_samples: 0,
+            samples: 1,
+            tone_mapping: None,
+            exposure: None,
+            gamma: None,
+            statistics: false,
+            statistics_csv: None,
+            render_pass: RenderPass::default(),
+            denoise: false,
         }
     }
 }
 
+/// Renders the frame along with the guide buffers and filters it with the denoiser.
+fn render_denoised<'a>(environment: &Environment,
+                       dimensions: (u32, u32),
+                       time: &Duration,
+                       pool: &mut Pool,
+                       context: &SimulationContext,
+                       statistics: Option<&Mutex<FrameStatistics>>)
+                       -> RawImage2d<'a, u8> {
+    let image = environment.render_aovs(dimensions, time, pool, context,
+                                        &[AovLayer::Beauty], statistics);
+    let colors = Denoiser::new().denoise(&image);
+    let tone_mapper = context.tone_mapper(environment.tone_mapper());
+    let data: Vec<u8> = colors.iter()
+        .flat_map(|color| tone_mapper.to_pixel(*color).to_vec())
+        .collect();
+
+    RawImage2d {
+        data: Cow::Owned(data),
+        width: image.width,
+        height: image.height,
+        format: ClientFormat::U8U8U8,
+    }
+}
+
+/// Stretches the image over the whole frame.
+fn blit_image(display: &Display, frame: &Frame, image: RawImage2d<u8>) {
+    let (width, height) = frame.get_dimensions();
+    let texture = Texture2d::new(display, image).unwrap();
+    let blit_target = BlitTarget {
+        left: 0,
+        bottom: 0,
+        width: width as i32,
+        height: height as i32,
+    };
+
+    texture.as_surface().blit_whole_color_to(frame, &blit_target, MagnifySamplerFilter::Nearest);
+}
+
+fn finish_frame(frame: Frame) {
+    if let Err(error) = frame.finish() {
+        panic!("An error occured while swapping the OpenGL buffers: {:?}",
+               error)
+    }
+}
+
+/// Draws the segments of the inspected rays that are visible to the camera.
+fn draw_inspection(environment: &Environment, inspection: &RayInspection, image: &mut RawImage2d<u8>) {
+    let (width, height) = (image.width as i32, image.height as i32);
+
+    for (kind, from, to) in inspection.segments() {
+        let color = match kind {
+            RayKind::Primary => [255, 255, 0],
+            RayKind::Reflection => [0, 255, 255],
+            RayKind::Transmission => [255, 0, 255],
+            RayKind::PassThrough => [128, 128, 128],
+            RayKind::Secondary => [255, 128, 0],
+        };
+
+        if let (Some(from), Some(to)) = (environment.project_point(from, width, height),
+                                         environment.project_point(to, width, height)) {
+            overlay::draw_line(image, from, to, color);
+        }
+    }
+}
+
+fn duration_millis(duration: &Duration) -> F {
+    duration.as_secs() as F * 1000.0 + duration.subsec_nanos() as F / 1_000_000.0
+}
+
+/// The maximum number of intersector pairs shown in the overlay
+const OVERLAY_INTERSECTORS: usize = 12;
+
+fn statistics_lines(statistics: &FrameStatistics,
+                    frame_duration: &Duration,
+                    render_duration: &Duration,
+                    resolution: u32,
+                    render_pass: RenderPass)
+                    -> Vec<String> {
+    let frame_millis = duration_millis(frame_duration);
+    let fps = if frame_millis > 0.0 { 1000.0 / frame_millis } else { 0.0 };
+    let mut lines = vec![format!("FPS: {:.1}", fps),
+                         format!("Frame time: {:.1} ms", frame_millis),
+                         format!("Render time: {:.1} ms", duration_millis(render_duration)),
+                         format!("Resolution divisor: {}", resolution),
+                         format!("Render pass: {}", render_pass),
+                         format!("Rays: {}", statistics.rays),
+                         format!("Primary rays: {}", statistics.paths),
+                         format!("Average bounce depth: {:.2}", statistics.average_bounce_depth()),
+                         "Intersector calls:".to_string()];
+
+    for calls in statistics.intersector_calls_sorted().iter().take(OVERLAY_INTERSECTORS) {
+        lines.push(format!("  {} / {}: {}", calls.material, calls.shape, calls.calls));
+    }
+
+    lines
+}
+
+const STATISTICS_CSV_HEADER: &str = "frame,time_ms,frame_time_ms,render_time_ms,fps,\
+                                     resolution_divisor,rays,primary_rays,\
+                                     average_bounce_depth,intersector_calls";
+
+/// Writes the statistics of a single frame, the intersector calls are written to the last column
+/// as `material/shape:calls` pairs separated by semicolons.
+fn write_statistics_row<W: Write>(writer: &mut W,
+                                  frame_index: u64,
+                                  time: &Duration,
+                                  statistics: &FrameStatistics,
+                                  frame_duration: &Duration,
+                                  render_duration: &Duration,
+                                  resolution: u32)
+                                  -> ::std::io::Result<()> {
+    let frame_millis = duration_millis(frame_duration);
+    let fps = if frame_millis > 0.0 { 1000.0 / frame_millis } else { 0.0 };
+    let intersector_calls: Vec<String> = statistics.intersector_calls_sorted()
+        .iter()
+        .map(|calls| format!("{}/{}:{}", calls.material, calls.shape, calls.calls))
+        .collect();
+
+    writeln!(writer, "{},{:.3},{:.3},{:.3},{:.3},{},{},{},{:.4},{}",
+             frame_index,
+             duration_millis(time),
+             frame_millis,
+             duration_millis(render_duration),
+             fps,
+             resolution,
+             statistics.rays,
+             statistics.paths,
+             statistics.average_bounce_depth(),
+             intersector_calls.join(";"))
+}
+
 impl SimulationBuilder {
     pub fn environment(mut self, environment: Box<Environment>) -> Self {
         self.environment = Some(environment);
@@ -150,16 +444,95 @@ impl SimulationBuilder {
         self
     }
 
+    /// Traces the given number of wavelengths per pixel, `0` disables spectral rendering
+    pub fn spectral_samples(mut self, spectral_samples: u32) -> Self {
+        self.spectral_samples = spectral_samples;
+        self
+    }
+
+    /// Traces the given number of rays per pixel through different points of the camera lens,
+    /// which blurs the surfaces out of focus of a `ThinLens`
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Overrides the tone mapping operator of the scene
+    pub fn tone_mapping(mut self, tone_mapping: ToneMapping) -> Self {
+        self.tone_mapping = Some(tone_mapping);
+        self
+    }
+
+    /// Overrides the exposure of the scene, in stops
+    pub fn exposure(mut self, exposure: F) -> Self {
+        self.exposure = Some(exposure);
+        self
+    }
+
+    /// Overrides the gamma of the scene
+    pub fn gamma(mut self, gamma: F) -> Self {
+        self.gamma = Some(gamma);
+        self
+    }
+
+    /// Shows the statistics overlay from the start, it can be toggled with F3
+    pub fn statistics(mut self, statistics: bool) -> Self {
+        self.statistics = statistics;
+        self
+    }
+
+    /// Writes the statistics of each frame to a CSV file
+    pub fn statistics_csv(mut self, path: PathBuf) -> Self {
+        self.statistics_csv = Some(path);
+        self
+    }
+
+    /// The pass displayed from the start, it can be cycled with F6
+    pub fn render_pass(mut self, render_pass: RenderPass) -> Self {
+        self.render_pass = render_pass;
+        self
+    }
+
+    /// Filters the rendered images with the denoiser, it can be toggled with F7
+    pub fn denoise(mut self, denoise: bool) -> Self {
+        self.denoise = denoise;
+        self
+    }
+
     pub fn build(self) -> Simulation {
+        let mut context = SimulationContext::new();
+        context.spectral_samples = self.spectral_samples;
+        context.samples = self.samples;
+        context.tone_mapping = self.tone_mapping;
+        context.exposure = self.exposure;
+        context.gamma = self.gamma;
+        context.statistics = self.statistics;
+        context.render_pass = self.render_pass;
+        context.denoising = self.denoise;
+        let statistics_writer = self.statistics_csv.map(|path| {
+            let mut writer = BufWriter::new(File::create(path)
+                .expect("Could not create the statistics file."));
+
+            writeln!(writer, "{}", STATISTICS_CSV_HEADER)
+                .expect("Could not write the statistics.");
+
+            writer
+        });
+
         Simulation {
             events_loop: None,
             debug: self.debug,
-            threads: self.threads.expect("Specify the number of threads before building the simulation."),
+            pool: Pool::new(self.threads.expect("Specify the number of threads before building the simulation.")),
             environment: self.environment.expect("Specify the environment before bulding the simulation."),
             display: None,
             start_instant: None,
             last_updated_instant: None,
-            context: SimulationContext::new(),
+            frame_duration: Duration::new(0, 0),
+            frame_index: 0,
+            statistics_writer: statistics_writer,
+            inspection: None,
+            inspection_index: 0,
+            context: context,
         }
     }
 }
@@ -171,6 +544,24 @@ pub struct SimulationContext {
     pub delta_mouse: Vector2<f64>,
     pub resolution: u32,
     pub debugging: bool,
+    /// The number of wavelengths traced per pixel, `0` traces RGB directly
+    pub spectral_samples: u32,
+    /// The number of rays traced per pixel through different points of the camera lens
+    pub samples: u32,
+    pub tone_mapping: Option<ToneMapping>,
+    pub exposure: Option<F>,
+    pub gamma: Option<F>,
+    /// Whether the statistics overlay is shown
+    pub statistics: bool,
+    /// Requests the rays of the center pixel, the one the camera is aimed at,
+    /// to be captured in the next frame
+    pub inspecting: bool,
+    /// Whether the captured rays are drawn over the image
+    pub inspection_lines: bool,
+    /// The quantity displayed instead of the traced colors
+    pub render_pass: RenderPass,
+    /// Whether the traced colors are filtered with the denoiser
+    pub denoising: bool,
 }
 
 impl SimulationContext {
@@ -182,9 +573,26 @@ impl SimulationContext {
             delta_mouse: na::zero(),
             resolution: 8,
             debugging: false,
+            spectral_samples: 0,
+            samples: 1,
+            tone_mapping: None,
+            exposure: None,
+            gamma: None,
+            statistics: false,
+            inspecting: false,
+            inspection_lines: false,
+            render_pass: RenderPass::default(),
+            denoising: false,
         }
     }
 
+    /// Applies the overrides given on the command line to the tone mapper of the scene.
+    pub fn tone_mapper(&self, scene: ToneMapper) -> ToneMapper {
+        ToneMapper::new(self.tone_mapping.unwrap_or(scene.operator),
+                        self.exposure.unwrap_or(scene.exposure),
+                        self.gamma.unwrap_or(scene.gamma))
+    }
+
     pub fn pressed_keys(&self) -> &HashSet<VirtualKeyCode> {
         &self.pressed_keys
     }
@@ -244,6 +652,21 @@ impl SimulationContext {
                                             self.debugging = false;
                                         }
                                     },
+                                    VirtualKeyCode::F3 => {
+                                        self.statistics = !self.statistics;
+                                    },
+                                    VirtualKeyCode::F4 => {
+                                        self.inspecting = true;
+                                    },
+                                    VirtualKeyCode::F5 => {
+                                        self.inspection_lines = !self.inspection_lines;
+                                    },
+                                    VirtualKeyCode::F6 => {
+                                        self.render_pass = self.render_pass.next();
+                                    },
+                                    VirtualKeyCode::F7 => {
+                                        self.denoising = !self.denoising;
+                                    },
                                     _ => ()
                                 }
 
@@ -0,0 +1,347 @@
+use ::F;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::fmt;
+use std::str::FromStr;
+use num::Float;
+use num::Zero;
+use palette::Rgb;
+use universe::spectrum;
+use universe::entity::stereo;
+use universe::render_pass;
+use universe::tone_mapping::ToneMapper;
+use util::CustomPoint;
+use util::CustomVector;
+
+/// An arbitrary output variable, rendered alongside the beauty image for compositing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AovLayer {
+    /// The traced colors, before tone mapping
+    Beauty,
+    /// The color of the surface hit by the primary ray, without any lighting
+    Albedo,
+    /// The first three coordinates of the shading normal at the primary hit
+    Normal,
+    /// The distance to the primary hit, infinite where nothing was hit
+    Depth,
+    /// The color transmitted through or emitted by the surface hit by the primary ray
+    Direct,
+    /// The color reflected off the surface hit by the primary ray
+    Reflected,
+    /// A distinct color per entity hit by the primary ray, usable as a mask
+    EntityId,
+}
+
+pub const AOV_LAYERS: [AovLayer; 7] = [AovLayer::Beauty,
+                                       AovLayer::Albedo,
+                                       AovLayer::Normal,
+                                       AovLayer::Depth,
+                                       AovLayer::Direct,
+                                       AovLayer::Reflected,
+                                       AovLayer::EntityId];
+
+impl AovLayer {
+    /// The names of the EXR channels of the layer, the beauty and depth are stored
+    /// in the default layer as `R`, `G`, `B` and `Z`.
+    pub fn channel_names(&self) -> Vec<String> {
+        match *self {
+            AovLayer::Beauty => vec!["R".to_string(), "G".to_string(), "B".to_string()],
+            AovLayer::Depth => vec!["Z".to_string()],
+            AovLayer::Normal => ["X", "Y", "Z"].iter()
+                .map(|component| format!("{}.{}", self, component))
+                .collect(),
+            _ => ["R", "G", "B"].iter()
+                .map(|component| format!("{}.{}", self, component))
+                .collect(),
+        }
+    }
+
+    /// The values of the channels of the layer, in the order of `channel_names`
+    pub fn values(&self, sample: &AovSample) -> Vec<F> {
+        let color = |color: &Rgb<F>| vec![color.red, color.green, color.blue];
+
+        match *self {
+            AovLayer::Beauty => color(&sample.beauty),
+            AovLayer::Albedo => color(&sample.albedo),
+            AovLayer::Normal => sample.normal.to_vec(),
+            AovLayer::Depth => vec![sample.depth],
+            AovLayer::Direct => color(&sample.direct),
+            AovLayer::Reflected => color(&sample.reflected),
+            AovLayer::EntityId => color(&sample.entity_id),
+        }
+    }
+
+    /// Converts the layer to a displayable pixel. The colors are tone mapped,
+    /// the normals and depth are mapped the same way as in the debug render passes.
+    pub fn to_pixel(&self, sample: &AovSample, tone_mapper: &ToneMapper) -> [u8; 3] {
+        match *self {
+            AovLayer::Beauty => tone_mapper.to_pixel(sample.beauty),
+            AovLayer::Albedo => ToneMapper::default().to_pixel(sample.albedo),
+            AovLayer::Normal => {
+                let half = 0.5 as F;
+                let normal = sample.normal;
+
+                ToneMapper::default().to_pixel(Rgb::new(normal[0] * half + half,
+                                                        normal[1] * half + half,
+                                                        normal[2] * half + half))
+            }
+            AovLayer::Depth => {
+                let brightness = render_pass::depth_brightness(sample.depth);
+
+                ToneMapper::default().to_pixel(Rgb::new(brightness, brightness, brightness))
+            }
+            AovLayer::Direct => tone_mapper.to_pixel(sample.direct),
+            AovLayer::Reflected => tone_mapper.to_pixel(sample.reflected),
+            AovLayer::EntityId => ToneMapper::default().to_pixel(sample.entity_id),
+        }
+    }
+}
+
+impl FromStr for AovLayer {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        AOV_LAYERS.iter()
+            .find(|layer| layer.to_string() == name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| {
+                format!("Invalid AOV layer: \"{}\", expected one of {}", name,
+                        AOV_LAYERS.iter()
+                            .map(|layer| format!("`{}`", layer))
+                            .collect::<Vec<String>>()
+                            .join(", "))
+            })
+    }
+}
+
+impl fmt::Display for AovLayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            AovLayer::Beauty => "beauty",
+            AovLayer::Albedo => "albedo",
+            AovLayer::Normal => "normal",
+            AovLayer::Depth => "depth",
+            AovLayer::Direct => "direct",
+            AovLayer::Reflected => "reflected",
+            AovLayer::EntityId => "entity_id",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The output variables of a single pixel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AovSample {
+    pub beauty: Rgb<F>,
+    pub albedo: Rgb<F>,
+    pub normal: [F; 3],
+    pub depth: F,
+    pub direct: Rgb<F>,
+    pub reflected: Rgb<F>,
+    pub entity_id: Rgb<F>,
+}
+
+fn black() -> Rgb<F> {
+    Rgb::new(<F as Zero>::zero(), <F as Zero>::zero(), <F as Zero>::zero())
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        AovSample {
+            beauty: black(),
+            albedo: black(),
+            normal: [<F as Zero>::zero(); 3],
+            depth: <F as Float>::infinity(),
+            direct: black(),
+            reflected: black(),
+            entity_id: black(),
+        }
+    }
+}
+
+/// Collects the output variables of the primary ray while it is being traced.
+/// It is passed to the surface hit by the primary ray through `ColorTracingContext::aovs`.
+#[derive(Debug, Default)]
+pub struct AovRecorder {
+    sample: RefCell<AovSample>,
+    contributions_recorded: Cell<bool>,
+}
+
+/// The first three coordinates of the normalized vector
+pub fn normal_coordinates<P: CustomPoint<V>, V: CustomVector<P>>(normal: &V) -> [F; 3] {
+    let normal = normal.normalize();
+
+    [normal[0], normal[1], normal[2]]
+}
+
+impl AovRecorder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records the geometry of the primary hit
+    pub fn record_hit(&self, distance: F, normal: [F; 3], entity_id: usize) {
+        let mut sample = self.sample.borrow_mut();
+
+        sample.depth = distance;
+        sample.normal = normal;
+        sample.entity_id = render_pass::id_color(&entity_id);
+    }
+
+    /// Records the unlit color and the shading normal of the surface
+    pub fn record_surface(&self, albedo: Rgb<F>, normal: [F; 3]) {
+        let mut sample = self.sample.borrow_mut();
+
+        sample.albedo = albedo;
+        sample.normal = normal;
+    }
+
+    /// Records the parts of the color of the surface, before the attenuation by the medium
+    pub fn record_contributions(&self, direct: Rgb<F>, reflected: Rgb<F>) {
+        let mut sample = self.sample.borrow_mut();
+
+        sample.direct = direct;
+        sample.reflected = reflected;
+        self.contributions_recorded.set(true);
+    }
+
+    /// Completes the sample with the traced color. The whole color is considered direct
+    /// when the surface did not split it.
+    pub fn finish(self, beauty: Rgb<F>) -> AovSample {
+        let mut sample = self.sample.into_inner();
+
+        sample.beauty = beauty;
+
+        if !self.contributions_recorded.get() {
+            sample.direct = beauty;
+        }
+
+        sample
+    }
+}
+
+/// Traces the output variables of a pixel. The trace function is given the wavelength and
+/// the recorder and returns the traced color. With spectral sampling, the colors are integrated
+/// over all the wavelengths and the geometry is taken from the first one.
+pub fn trace_aovs<T>(spectral_samples: u32, trace: T) -> AovSample
+    where T: Fn(Option<F>, &AovRecorder) -> Rgb<F>
+{
+    if spectral_samples == 0 {
+        let recorder = AovRecorder::new();
+        let beauty = trace(None, &recorder);
+
+        return recorder.finish(beauty);
+    }
+
+    let samples: RefCell<Vec<AovSample>> = RefCell::new(Vec::new());
+    let beauty = spectrum::integrate(spectral_samples, |wavelength| {
+        let recorder = AovRecorder::new();
+        let color = trace(Some(wavelength), &recorder);
+
+        samples.borrow_mut().push(recorder.finish(color));
+        color
+    });
+    let samples = samples.into_inner();
+    // `integrate` samples the wavelengths in the same order each time
+    let integrate_samples = |component: &Fn(&AovSample) -> Rgb<F>| {
+        let index = Cell::new(0);
+
+        spectrum::integrate(spectral_samples, |_| {
+            let sample = &samples[index.get()];
+
+            index.set(index.get() + 1);
+            component(sample)
+        })
+    };
+    let mut result = samples[0];
+
+    result.beauty = beauty;
+    result.direct = integrate_samples(&|sample| sample.direct);
+    result.reflected = integrate_samples(&|sample| sample.reflected);
+    result.albedo = integrate_samples(&|sample| sample.albedo);
+
+    result
+}
+
+/// Averages the colors of the samples traced through several points of the lens,
+/// the geometry is taken from the first one.
+pub fn average(samples: &[AovSample]) -> AovSample {
+    let count = samples.len() as F;
+    let average_color = |component: &Fn(&AovSample) -> Rgb<F>| {
+        let sum = samples.iter().fold(black(), |sum, sample| {
+            let color = component(sample);
+
+            Rgb::new(sum.red + color.red, sum.green + color.green, sum.blue + color.blue)
+        });
+
+        Rgb::new(sum.red / count, sum.green / count, sum.blue / count)
+    };
+    let mut result = samples[0];
+
+    result.beauty = average_color(&|sample| sample.beauty);
+    result.direct = average_color(&|sample| sample.direct);
+    result.reflected = average_color(&|sample| sample.reflected);
+    result.albedo = average_color(&|sample| sample.albedo);
+
+    result
+}
+
+/// Combines the colors seen by both eyes into a red/cyan anaglyph, the geometry is taken
+/// from the left eye.
+pub fn anaglyph(left: &AovSample, right: &AovSample) -> AovSample {
+    let mut result = *left;
+
+    result.beauty = stereo::anaglyph(left.beauty, right.beauty);
+    result.direct = stereo::anaglyph(left.direct, right.direct);
+    result.reflected = stereo::anaglyph(left.reflected, right.reflected);
+    result.albedo = stereo::anaglyph(left.albedo, right.albedo);
+
+    result
+}
+
+/// The output variables of a rendered image, the rows start at the bottom
+#[derive(Clone, Debug, PartialEq)]
+pub struct AovImage {
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<AovLayer>,
+    pub samples: Vec<AovSample>,
+}
+
+impl AovImage {
+    pub fn sample(&self, x: u32, y: u32) -> &AovSample {
+        &self.samples[(y * self.width + x) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use na::ApproxEq;
+    use palette::Rgb;
+    use super::*;
+
+    #[test]
+    fn layers_parse_and_name_channels() {
+        for layer in &AOV_LAYERS {
+            assert_eq!(layer.to_string().parse::<AovLayer>(), Ok(*layer));
+            assert_eq!(layer.channel_names().len(), layer.values(&AovSample::default()).len());
+        }
+
+        assert_eq!(AovLayer::Albedo.channel_names()[0], "albedo.R");
+        assert!("unknown".parse::<AovLayer>().is_err());
+    }
+
+    #[test]
+    fn spectral_contributions_are_integrated() {
+        let sample = trace_aovs(8, |_, recorder| {
+            recorder.record_contributions(Rgb::new(0.25, 0.25, 0.25), Rgb::new(0.5, 0.5, 0.5));
+            Rgb::new(0.75, 0.75, 0.75)
+        });
+
+        assert!(sample.beauty.red.approx_eq(&0.75));
+        assert!(sample.direct.green.approx_eq(&0.25));
+        assert!(sample.reflected.blue.approx_eq(&0.5));
+        assert!(sample.depth.is_infinite());
+    }
+}
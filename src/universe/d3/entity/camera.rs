@@ -14,6 +14,7 @@ use num::traits::NumCast;
 use simulation::SimulationContext;
 use std::collections::HashSet;
 use std::time::Duration;
+use universe::Collision;
 use universe::Universe;
 use universe::d3::Point3;
 use universe::d3::Universe3;
@@ -24,9 +25,101 @@ use universe::entity::Entity;
 use universe::entity::Locatable;
 use universe::entity::Rotatable;
 use universe::entity::Traceable;
+use universe::entity::lens::LensSample;
+use universe::entity::lens::ThinLens;
+use universe::entity::stereo;
+use universe::entity::stereo::Eye;
+use universe::entity::stereo::Stereo;
 use util::AngleBetween;
 use util::CustomFloat;
 
+/// The distance kept from solid entities when collisions are enabled
+const COLLISION_RADIUS: F = 0.5;
+/// The height of the walking camera above the ground
+const EYE_HEIGHT: F = 2.0;
+/// How far above the eye height the ground is still touched
+const GROUND_TOLERANCE: F = 0.05;
+/// The highest step the walking camera climbs, the solid entities above it stop the camera
+const STEP_HEIGHT: F = 0.5;
+/// The number of heights between the step height and the eye checked for obstacles
+const BODY_SAMPLES: u32 = 4;
+/// The acceleration of the walking camera towards the ground, in units per second squared
+const GRAVITY: F = 20.0;
+/// The initial vertical speed of a jump, in units per second
+const JUMP_SPEED: F = 8.0;
+
+/// Whether the key was pressed since the last update, `held` remembers its state
+fn key_pressed(context: &SimulationContext, key: VirtualKeyCode, held: &mut bool) -> bool {
+    let key_held = context.pressed_keys().contains(&key);
+    let pressed = key_held && !*held;
+
+    *held = key_held;
+    pressed
+}
+
+/// How the directions seen by the camera are mapped onto the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection3 {
+    /// Rectilinear, straight lines stay straight, the field of view spans the diagonal
+    Perspective,
+    /// Parallel rays cast along `forward` from a screen whose diagonal has the given length
+    Orthographic(F),
+    /// Equidistant fisheye, the distance from the center of the screen is proportional
+    /// to the angle from `forward`, the field of view spans the diagonal
+    Fisheye,
+    /// The full 360° by 180° panorama, the longitude spans the width and the latitude
+    /// the height of the screen regardless of the field of view
+    Equirectangular,
+    /// The Panini projection with the given compression distance, `0` is rectilinear and `1`
+    /// is the stereographic projection of a cylinder, the field of view spans the diagonal
+    Panini(F),
+}
+
+impl Projection3 {
+    /// The projection following this one, wrapping around, with the default parameters
+    pub fn next(&self) -> Projection3 {
+        match *self {
+            Projection3::Perspective => Projection3::Orthographic(10.0),
+            Projection3::Orthographic(_) => Projection3::Fisheye,
+            Projection3::Fisheye => Projection3::Equirectangular,
+            Projection3::Equirectangular => Projection3::Panini(1.0),
+            Projection3::Panini(_) => Projection3::Perspective,
+        }
+    }
+
+    /// Whether the rays look sideways or behind, where the eyes of a stereo camera
+    /// follow the longitude of the ray
+    pub fn is_panoramic(&self) -> bool {
+        match *self {
+            Projection3::Perspective | Projection3::Orthographic(_) => false,
+            Projection3::Fisheye | Projection3::Equirectangular | Projection3::Panini(_) => true,
+        }
+    }
+}
+
+impl Default for Projection3 {
+    fn default() -> Self {
+        Projection3::Perspective
+    }
+}
+
+/// The horizontal position of the direction given by its longitude in the Panini projection
+fn panini_x(distance: F, longitude: F) -> F {
+    (distance + 1.0) * longitude.sin() / (distance + longitude.cos())
+}
+
+/// The position on the screen relative to its center, in pixels, and the dimensions of the screen
+fn screen_offset(screen_x: i32, screen_y: i32, screen_width: i32, screen_height: i32)
+                 -> (F, F, F, F) {
+    let rel_x: F = <F as NumCast>::from(screen_x - screen_width / 2).unwrap() +
+                   <F as NumCast>::from(1 - screen_width % 2).unwrap() / 2.0;
+    let rel_y: F = <F as NumCast>::from(screen_y - screen_height / 2).unwrap() +
+                   <F as NumCast>::from(1 - screen_height % 2).unwrap() / 2.0;
+
+    (rel_x, rel_y, <F as NumCast>::from(screen_width).unwrap(),
+     <F as NumCast>::from(screen_height).unwrap())
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub struct Camera3Data {
     location: Point3,
@@ -36,6 +129,21 @@ pub struct Camera3Data {
     speed: F,
     fov: u8,
     max_depth: u32,
+    projection: Projection3,
+    /// Whether the key cycling the projections was held during the last update
+    projection_key_held: bool,
+    /// The lens focusing the rays, `None` for a pinhole camera
+    lens: Option<ThinLens>,
+    stereo: Option<Stereo>,
+    /// Stops the camera in front of solid entities, `None` to fly through them
+    collision: Option<Collision>,
+    /// Whether the camera walks on the solid entities below it, pulled by gravity
+    walking: bool,
+    /// The speed of the walking camera along the Z axis
+    vertical_speed: F,
+    /// Whether the keys toggling the collisions and walking were held during the last update
+    collision_key_held: bool,
+    walking_key_held: bool,
 }
 
 impl Camera3Data {
@@ -48,6 +156,15 @@ impl Camera3Data {
             speed: 10.0,
             fov: 90,
             max_depth: 10,
+            projection: Projection3::default(),
+            projection_key_held: false,
+            lens: None,
+            stereo: None,
+            collision: None,
+            walking: false,
+            vertical_speed: <F as Zero>::zero(),
+            collision_key_held: false,
+            walking_key_held: false,
         }
     }
 
@@ -65,6 +182,384 @@ impl Camera3Data {
     fn get_right(&self) -> Vector3 {
         na::cross(&self.forward, &self.up).normalize()
     }
+
+    fn get_fov_rad(&self) -> F {
+        <F as BaseFloat>::pi() * <F as NumCast>::from(self.fov).unwrap() / 180.0
+    }
+
+    /// The distance of the screen plane from the camera, in pixels
+    fn get_screen_distance(&self, screen_width: F, screen_height: F) -> F {
+        (screen_width * screen_width + screen_height * screen_height).sqrt() /
+            (2.0 * (self.get_fov_rad() / 2.0).tan())
+    }
+
+    fn get_ray_point(&self,
+                     screen_x: i32,
+                     screen_y: i32,
+                     screen_width: i32,
+                     screen_height: i32)
+                     -> Point3 {
+        match self.projection {
+            Projection3::Orthographic(size) => {
+                let (rel_x, rel_y, screen_width, screen_height) =
+                    screen_offset(screen_x, screen_y, screen_width, screen_height);
+                let scale = size /
+                    (screen_width * screen_width + screen_height * screen_height).sqrt();
+
+                self.location + (self.get_right() * rel_x + self.up * rel_y) * scale
+            }
+            _ => self.location,
+        }
+    }
+
+    fn get_ray_vector(&self,
+                      screen_x: i32,
+                      screen_y: i32,
+                      screen_width: i32,
+                      screen_height: i32)
+                      -> Vector3 {
+        let (rel_x, rel_y, screen_width, screen_height) =
+            screen_offset(screen_x, screen_y, screen_width, screen_height);
+        let right = self.get_right();
+        let half_diagonal =
+            (screen_width * screen_width + screen_height * screen_height).sqrt() / 2.0;
+
+        match self.projection {
+            Projection3::Perspective => {
+                let distance_from_screen_center =
+                    self.get_screen_distance(screen_width, screen_height);
+
+                (self.forward * distance_from_screen_center + self.up * rel_y + right * rel_x)
+                    .normalize()
+            }
+            Projection3::Orthographic(_) => self.forward,
+            Projection3::Fisheye => {
+                let radius = (rel_x * rel_x + rel_y * rel_y).sqrt();
+
+                if radius == <F as Zero>::zero() {
+                    return self.forward;
+                }
+
+                let angle = radius / half_diagonal * self.get_fov_rad() / 2.0;
+
+                self.forward * angle.cos()
+                    + (self.up * rel_y + right * rel_x) * (angle.sin() / radius)
+            }
+            Projection3::Equirectangular => {
+                let longitude = rel_x / (screen_width / 2.0) * <F as BaseFloat>::pi();
+                let latitude = rel_y / (screen_height / 2.0) * <F as BaseFloat>::frac_pi_2();
+
+                (self.forward * longitude.cos() + right * longitude.sin()) * latitude.cos()
+                    + self.up * latitude.sin()
+            }
+            Projection3::Panini(distance) => {
+                let half_fov = self.get_fov_rad() / 2.0;
+                let scale = panini_x(distance, half_fov) / half_diagonal;
+                let x = rel_x * scale;
+                let y = rel_y * scale;
+                // Inverts `panini_x` to find the cosine of the longitude
+                let k = x * x / ((distance + 1.0) * (distance + 1.0));
+                let discriminant = k * k * distance * distance
+                    - (k + 1.0) * (k * distance * distance - 1.0);
+                let longitude_cos = (-k * distance + discriminant.max(0.0).sqrt()) / (k + 1.0);
+                let cylinder_scale = (distance + 1.0) / (distance + longitude_cos);
+                let longitude = x.atan2(cylinder_scale * longitude_cos);
+
+                (self.forward * longitude.cos() + right * longitude.sin()
+                 + self.up * (y / cylinder_scale)).normalize()
+            }
+        }
+    }
+
+    fn get_ray(&self,
+               screen_x: i32,
+               screen_y: i32,
+               screen_width: i32,
+               screen_height: i32,
+               lens_sample: LensSample,
+               eye: Option<Eye>)
+               -> (Point3, Vector3) {
+        let (eye, screen_x, screen_y, screen_width, screen_height) = match self.stereo {
+            Some(ref stereo) => stereo.viewport(eye, screen_x, screen_y, screen_width, screen_height),
+            None => (None, screen_x, screen_y, screen_width, screen_height),
+        };
+        let mut point = self.get_ray_point(screen_x, screen_y, screen_width, screen_height);
+        let vector = self.get_ray_vector(screen_x, screen_y, screen_width, screen_height);
+
+        if let (Some(stereo), Some(eye)) = (self.stereo, eye) {
+            let left = if self.projection.is_panoramic() {
+                stereo::omnidirectional_left(&vector, &self.forward, &self.get_left())
+            } else {
+                self.get_left()
+            };
+
+            point = point + left * stereo.eye_offset(eye);
+        }
+
+        match self.lens {
+            Some(ref lens) => lens.focus(&point, &vector, &self.forward,
+                                         &[self.get_right(), self.up], lens_sample),
+            None => (point, vector),
+        }
+    }
+
+    /// Projects the point onto the screen, the inverse of `get_ray_vector`
+    fn project_point(&self, point: &Point3, screen_width: i32, screen_height: i32)
+                     -> Option<(F, F)> {
+        // The screen is shared by both eyes
+        if self.stereo.is_some() {
+            return None;
+        }
+
+        let relative = *point - self.location;
+        let depth = na::dot(&relative, &self.forward);
+        let horizontal = na::dot(&relative, &self.get_right());
+        let vertical = na::dot(&relative, &self.up);
+        let width: F = <F as NumCast>::from(screen_width).unwrap();
+        let height: F = <F as NumCast>::from(screen_height).unwrap();
+        let half_diagonal = (width * width + height * height).sqrt() / 2.0;
+
+        let (rel_x, rel_y) = match self.projection {
+            Projection3::Perspective => {
+                if depth <= <F as Zero>::zero() {
+                    return None;
+                }
+
+                let scale = self.get_screen_distance(width, height) / depth;
+
+                (horizontal * scale, vertical * scale)
+            }
+            Projection3::Orthographic(size) => {
+                if depth <= <F as Zero>::zero() {
+                    return None;
+                }
+
+                let scale = half_diagonal * 2.0 / size;
+
+                (horizontal * scale, vertical * scale)
+            }
+            Projection3::Fisheye => {
+                let sideways = (horizontal * horizontal + vertical * vertical).sqrt();
+
+                if sideways == <F as Zero>::zero() {
+                    if depth <= <F as Zero>::zero() {
+                        return None;
+                    }
+
+                    (<F as Zero>::zero(), <F as Zero>::zero())
+                } else {
+                    let radius = sideways.atan2(depth) / (self.get_fov_rad() / 2.0) * half_diagonal;
+
+                    (horizontal / sideways * radius, vertical / sideways * radius)
+                }
+            }
+            Projection3::Equirectangular => {
+                let longitude = horizontal.atan2(depth);
+                let latitude = vertical.atan2((depth * depth + horizontal * horizontal).sqrt());
+
+                (longitude / <F as BaseFloat>::pi() * width / 2.0,
+                 latitude / <F as BaseFloat>::frac_pi_2() * height / 2.0)
+            }
+            Projection3::Panini(distance) => {
+                let longitude = horizontal.atan2(depth);
+                let horizontal_distance = (depth * depth + horizontal * horizontal).sqrt();
+                let cylinder_scale = (distance + 1.0) / (distance + longitude.cos());
+
+                if horizontal_distance == <F as Zero>::zero()
+                        || cylinder_scale <= <F as Zero>::zero() {
+                    return None;
+                }
+
+                let scale = half_diagonal / panini_x(distance, self.get_fov_rad() / 2.0);
+
+                (panini_x(distance, longitude) * scale,
+                 vertical / horizontal_distance * cylinder_scale * scale)
+            }
+        };
+
+        Some((rel_x + <F as NumCast>::from(screen_width / 2).unwrap()
+                    - <F as NumCast>::from(1 - screen_width % 2).unwrap() / 2.0,
+              rel_y + <F as NumCast>::from(screen_height / 2).unwrap()
+                    - <F as NumCast>::from(1 - screen_height % 2).unwrap() / 2.0))
+    }
+}
+
+impl Camera3Data {
+    pub fn with_projection(mut self, projection: Projection3) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_lens(mut self, lens: ThinLens) -> Self {
+        self.lens = Some(lens);
+        self
+    }
+
+    pub fn with_stereo(mut self, stereo: Stereo) -> Self {
+        self.stereo = Some(stereo);
+        self
+    }
+
+    /// Focuses the lens on the surface in the center of the screen, see `ThinLens::update`
+    fn update_lens(&mut self, delta_time: &Duration, context: &SimulationContext,
+                   universe: &Universe3) {
+        if let Some(mut lens) = self.lens {
+            let delta_seconds = <F as NumCast>::from((*delta_time * 1000u32).as_secs()).unwrap()
+                / 1000.0;
+            let center_distance = if lens.autofocus {
+                universe.trace_distance_unknown(delta_time, &self.location, &self.forward,
+                                                &|_| true)
+            } else {
+                None
+            };
+
+            lens.update(delta_seconds, context.pressed_keys(), center_distance);
+            self.lens = Some(lens);
+        }
+    }
+
+    pub fn with_collision(mut self, collision: bool) -> Self {
+        self.set_collision(collision);
+        self
+    }
+
+    pub fn with_walking(mut self, walking: bool) -> Self {
+        self.set_walking(walking);
+        self
+    }
+
+    /// Walking stops with the collisions, there is no ground to stand on without them
+    fn set_collision(&mut self, collision: bool) {
+        self.collision = if collision { Some(Collision::new(COLLISION_RADIUS)) } else { None };
+        self.walking = self.walking && collision;
+    }
+
+    /// Walking needs the collisions to stand on the ground
+    fn set_walking(&mut self, walking: bool) {
+        self.walking = walking;
+        self.vertical_speed = <F as Zero>::zero();
+
+        if walking && self.collision.is_none() {
+            self.collision = Some(Collision::new(COLLISION_RADIUS));
+        }
+    }
+
+    /// Cycles the projections with [`P`]
+    fn update_projection(&mut self, context: &SimulationContext) {
+        if key_pressed(context, VirtualKeyCode::P, &mut self.projection_key_held) {
+            self.projection = self.projection.next();
+        }
+    }
+
+    /// Toggles the collisions with [`C`]
+    fn update_collision(&mut self, context: &SimulationContext) {
+        if key_pressed(context, VirtualKeyCode::C, &mut self.collision_key_held) {
+            let collision = self.collision.is_none();
+
+            self.set_collision(collision);
+        }
+    }
+
+    /// Toggles walking with [`G`]
+    fn update_walking(&mut self, context: &SimulationContext) {
+        if key_pressed(context, VirtualKeyCode::G, &mut self.walking_key_held) {
+            let walking = !self.walking;
+
+            self.set_walking(walking);
+        }
+    }
+
+    /// Pulls the walking camera down to the solid entities below it,
+    /// it jumps off them with [`Space`].
+    fn update_gravity(&mut self, delta_time: &Duration, delta_seconds: F,
+                      context: &SimulationContext, universe: &Universe3) {
+        let collision = match self.collision {
+            Some(collision) => collision,
+            None => return,
+        };
+        let down = -Vector3::z();
+        let ground_distance = universe.trace_distance_unknown(delta_time, &self.location, &down,
+                                                              &|other| other.is_solid());
+        let grounded = ground_distance
+            .map_or(false, |distance| distance <= EYE_HEIGHT + GROUND_TOLERANCE);
+
+        if grounded && self.vertical_speed <= <F as Zero>::zero() {
+            // Stand at the eye height above the ground, which also climbs steps and slopes
+            if let Some(ground_distance) = ground_distance {
+                self.location.z += EYE_HEIGHT - ground_distance;
+            }
+
+            self.vertical_speed = if context.pressed_keys().contains(&VirtualKeyCode::Space) {
+                JUMP_SPEED
+            } else {
+                <F as Zero>::zero()
+            };
+        } else {
+            self.vertical_speed -= GRAVITY * delta_seconds;
+        }
+
+        let step = self.vertical_speed * delta_seconds;
+
+        if step == <F as Zero>::zero() {
+            return;
+        }
+
+        // The head hits the ceiling, the feet stand on the ground
+        let (direction, collision) = if step > <F as Zero>::zero() {
+            (Vector3::z(), collision)
+        } else {
+            (down, Collision { radius: EYE_HEIGHT, .. collision })
+        };
+
+        if let Some((new_location, _)) = universe.trace_path_unknown(delta_time,
+                                                                     &step.abs(),
+                                                                     &self.location,
+                                                                     &direction,
+                                                                     Some(collision),
+                                                                     context.debugging) {
+            if na::distance(&self.location, &new_location) < step.abs() / 2.0 {
+                self.vertical_speed = <F as Zero>::zero();
+            }
+
+            self.location = new_location;
+        }
+    }
+
+    /// Moves the walking camera, stopped by the solid entities anywhere between the top
+    /// of the highest step it climbs and its eye
+    fn walk(&mut self,
+            delta_time: &Duration,
+            distance: &F,
+            direction: &Vector3,
+            context: &SimulationContext,
+            universe: &Universe3) {
+        let mut displacement: Option<Vector3> = None;
+        let last_sample: F = <F as NumCast>::from(BODY_SAMPLES - 1).unwrap();
+
+        for sample in 0..BODY_SAMPLES {
+            let height = (EYE_HEIGHT - STEP_HEIGHT) * <F as NumCast>::from(sample).unwrap()
+                / last_sample;
+            let origin = self.location - Vector3::z() * height;
+
+            if let Some((new_location, _)) = universe.trace_path_unknown(delta_time,
+                                                                         distance,
+                                                                         &origin,
+                                                                         direction,
+                                                                         self.collision,
+                                                                         context.debugging) {
+                let moved = new_location - origin;
+
+                // The body moves as far as its most obstructed part
+                if displacement.map_or(true, |shortest| moved.norm_squared() < shortest.norm_squared()) {
+                    displacement = Some(moved);
+                }
+            }
+        }
+
+        if let Some(displacement) = displacement {
+            self.location = self.location + displacement;
+        }
+    }
 }
 
 impl Default for Camera3Data {
@@ -91,6 +586,31 @@ impl PitchYawCamera3 {
         }
     }
 
+    pub fn with_projection(mut self, projection: Projection3) -> Self {
+        self.data = self.data.with_projection(projection);
+        self
+    }
+
+    pub fn with_lens(mut self, lens: ThinLens) -> Self {
+        self.data = self.data.with_lens(lens);
+        self
+    }
+
+    pub fn with_stereo(mut self, stereo: Stereo) -> Self {
+        self.data = self.data.with_stereo(stereo);
+        self
+    }
+
+    pub fn with_collision(mut self, collision: bool) -> Self {
+        self.data = self.data.with_collision(collision);
+        self
+    }
+
+    pub fn with_walking(mut self, walking: bool) -> Self {
+        self.data = self.data.with_walking(walking);
+        self
+    }
+
     fn update_rotation(&mut self, context: &SimulationContext) {
         let delta_mouse_float: na::Vector2<F> =
             na::Vector2::<F>::new(<F as NumCast>::from(context.delta_mouse.x).unwrap(),
@@ -151,14 +671,13 @@ impl Default for PitchYawCamera3 {
 }
 
 impl Camera<Point3, Vector3, Universe3> for PitchYawCamera3 {
-    #[allow(unused_variables)]
     fn get_ray_point(&self,
                      screen_x: i32,
                      screen_y: i32,
                      screen_width: i32,
                      screen_height: i32)
                      -> Point3 {
-        self.data.location
+        self.data.get_ray_point(screen_x, screen_y, screen_width, screen_height)
     }
 
     fn get_ray_vector(&self,
@@ -167,52 +686,81 @@ impl Camera<Point3, Vector3, Universe3> for PitchYawCamera3 {
                       screen_width: i32,
                       screen_height: i32)
                       -> Vector3 {
-        let rel_x: F = <F as NumCast>::from(screen_x - screen_width / 2).unwrap() +
-                       <F as NumCast>::from(1 - screen_width % 2).unwrap() / 2.0;
-        let rel_y: F = <F as NumCast>::from(screen_y - screen_height / 2).unwrap() +
-                       <F as NumCast>::from(1 - screen_height % 2).unwrap() / 2.0;
-        let screen_width: F = <F as NumCast>::from(screen_width).unwrap();
-        let screen_height: F = <F as NumCast>::from(screen_height).unwrap();
-        let right = self.data.get_right();
-        let fov_rad: F = <F as BaseFloat>::pi() * <F as NumCast>::from(self.data.fov).unwrap() / 180.0;
-        let distance_from_screen_center: F =
-            (screen_width * screen_width + screen_height * screen_height).sqrt() /
-            (2.0 * (fov_rad / 2.0).tan());
-        let screen_center_point_3d = self.data.location + self.data.forward * distance_from_screen_center;
-        let screen_point_3d = screen_center_point_3d + (self.data.up * rel_y) + (right * rel_x);
-
-        (screen_point_3d - self.data.location).normalize()
+        self.data.get_ray_vector(screen_x, screen_y, screen_width, screen_height)
+    }
+
+    fn get_ray(&self,
+               screen_x: i32,
+               screen_y: i32,
+               screen_width: i32,
+               screen_height: i32,
+               lens_sample: LensSample,
+               eye: Option<Eye>)
+               -> (Point3, Vector3) {
+        self.data.get_ray(screen_x, screen_y, screen_width, screen_height, lens_sample, eye)
+    }
+
+    fn stereo(&self) -> Option<Stereo> {
+        self.data.stereo
     }
 
     fn max_depth(&self) -> u32 {
         self.data.max_depth
     }
 
+    fn project_point(&self, point: &Point3, screen_width: i32, screen_height: i32) -> Option<(F, F)> {
+        self.data.project_point(point, screen_width, screen_height)
+    }
+
     fn update(&mut self, delta_time: &Duration, context: &SimulationContext, universe: &Universe3) {
         self.update_rotation(context);
+        self.data.update_projection(context);
+        self.data.update_lens(delta_time, context, universe);
+        self.data.update_collision(context);
+        self.data.update_walking(context);
 
         let pressed_keys: &HashSet<VirtualKeyCode> = context.pressed_keys();
         let delta_millis = <F as NumCast>::from((*delta_time * 1000u32).as_secs()).unwrap() / 1000.0;
         let mut distance = self.data.speed * delta_millis;
 
+        if self.data.walking {
+            self.data.update_gravity(delta_time, delta_millis, context, universe);
+        }
+
         if distance == <F as Zero>::zero() {
             return;
         }
 
         let mut direction: Vector3 = na::zero();
+        // The walking camera stays on the ground when looking up or down
+        let forward = if self.data.walking {
+            let horizontal = Vector3::new(self.data.forward.x, self.data.forward.y,
+                                          <F as Zero>::zero());
+
+            if horizontal.norm_squared() == <F as Zero>::zero() {
+                horizontal
+            } else {
+                horizontal.normalize()
+            }
+        } else {
+            self.data.forward
+        };
 
         pressed_keys.contains(&VirtualKeyCode::W).as_option()
-            .map(|()| direction += self.data.forward);
+            .map(|()| direction += forward);
         pressed_keys.contains(&VirtualKeyCode::S).as_option()
-            .map(|()| direction -= self.data.forward);
+            .map(|()| direction -= forward);
         pressed_keys.contains(&VirtualKeyCode::A).as_option()
             .map(|()| direction += self.data.get_left());
         pressed_keys.contains(&VirtualKeyCode::D).as_option()
             .map(|()| direction -= self.data.get_left());
-        pressed_keys.contains(&VirtualKeyCode::LShift).as_option()
-            .map(|()| direction += Vector3::z());
-        pressed_keys.contains(&VirtualKeyCode::LControl).as_option()
-            .map(|()| direction -= Vector3::z());
+
+        if !self.data.walking {
+            pressed_keys.contains(&VirtualKeyCode::LShift).as_option()
+                .map(|()| direction += Vector3::z());
+            pressed_keys.contains(&VirtualKeyCode::LControl).as_option()
+                .map(|()| direction -= Vector3::z());
+        }
 
         if direction.norm_squared() != <F as Zero>::zero() {
             let length = direction.norm();
@@ -220,11 +768,17 @@ impl Camera<Point3, Vector3, Universe3> for PitchYawCamera3 {
 
             direction.normalize_mut();
 
+            if self.data.walking {
+                self.data.walk(delta_time, &distance, &direction, context, universe);
+                return;
+            }
+
             if let Some((new_location, new_direction))
                     = universe.trace_path_unknown(delta_time,
                                                   &distance,
                                                   &self.data.location,
                                                   &direction,
+                                                  self.data.collision,
                                                   context.debugging) {
                 let rotation_scale = direction.angle_between(&new_direction);
 
@@ -300,6 +854,26 @@ impl FreeCamera3 {
         }
     }
 
+    pub fn with_projection(mut self, projection: Projection3) -> Self {
+        self.data = self.data.with_projection(projection);
+        self
+    }
+
+    pub fn with_lens(mut self, lens: ThinLens) -> Self {
+        self.data = self.data.with_lens(lens);
+        self
+    }
+
+    pub fn with_stereo(mut self, stereo: Stereo) -> Self {
+        self.data = self.data.with_stereo(stereo);
+        self
+    }
+
+    pub fn with_collision(mut self, collision: bool) -> Self {
+        self.data = self.data.with_collision(collision);
+        self
+    }
+
     fn update_rotation(&mut self, delta_millis: F, context: &SimulationContext) {
         let delta_mouse_float: na::Vector2<F> =
             na::Vector2::<F>::new(<F as NumCast>::from(context.delta_mouse.x).unwrap(),
@@ -356,14 +930,13 @@ impl Default for FreeCamera3 {
 }
 
 impl Camera<Point3, Vector3, Universe3> for FreeCamera3 {
-    #[allow(unused_variables)]
     fn get_ray_point(&self,
                      screen_x: i32,
                      screen_y: i32,
                      screen_width: i32,
                      screen_height: i32)
                      -> Point3 {
-        self.data.location
+        self.data.get_ray_point(screen_x, screen_y, screen_width, screen_height)
     }
 
     fn get_ray_vector(&self,
@@ -372,31 +945,39 @@ impl Camera<Point3, Vector3, Universe3> for FreeCamera3 {
                       screen_width: i32,
                       screen_height: i32)
                       -> Vector3 {
-        let rel_x: F = <F as NumCast>::from(screen_x - screen_width / 2).unwrap() +
-                       <F as NumCast>::from(1 - screen_width % 2).unwrap() / 2.0;
-        let rel_y: F = <F as NumCast>::from(screen_y - screen_height / 2).unwrap() +
-                       <F as NumCast>::from(1 - screen_height % 2).unwrap() / 2.0;
-        let screen_width: F = <F as NumCast>::from(screen_width).unwrap();
-        let screen_height: F = <F as NumCast>::from(screen_height).unwrap();
-        let right = self.data.get_right();
-        let fov_rad: F = <F as BaseFloat>::pi() * <F as NumCast>::from(self.data.fov).unwrap() / 180.0;
-        let distance_from_screen_center: F =
-            (screen_width * screen_width + screen_height * screen_height).sqrt() /
-            (<F as NumCast>::from(2.0).unwrap() * (fov_rad / 2.0).tan());
-        let screen_center_point_3d = self.data.location + self.data.forward * distance_from_screen_center;
-        let screen_point_3d = screen_center_point_3d + (self.data.up * rel_y) + (right * rel_x);
-
-        (screen_point_3d - self.data.location).normalize()
+        self.data.get_ray_vector(screen_x, screen_y, screen_width, screen_height)
+    }
+
+    fn get_ray(&self,
+               screen_x: i32,
+               screen_y: i32,
+               screen_width: i32,
+               screen_height: i32,
+               lens_sample: LensSample,
+               eye: Option<Eye>)
+               -> (Point3, Vector3) {
+        self.data.get_ray(screen_x, screen_y, screen_width, screen_height, lens_sample, eye)
+    }
+
+    fn stereo(&self) -> Option<Stereo> {
+        self.data.stereo
     }
 
     fn max_depth(&self) -> u32 {
         self.data.max_depth
     }
 
+    fn project_point(&self, point: &Point3, screen_width: i32, screen_height: i32) -> Option<(F, F)> {
+        self.data.project_point(point, screen_width, screen_height)
+    }
+
     fn update(&mut self, delta_time: &Duration, context: &SimulationContext, universe: &Universe3) {
         let delta_millis = <F as NumCast>::from((*delta_time * 1000u32).as_secs()).unwrap() / 1000.0;
 
         self.update_rotation(delta_millis, context);
+        self.data.update_projection(context);
+        self.data.update_lens(delta_time, context, universe);
+        self.data.update_collision(context);
 
         let pressed_keys: &HashSet<VirtualKeyCode> = context.pressed_keys();
         let mut distance = self.data.speed * delta_millis;
@@ -431,6 +1012,7 @@ impl Camera<Point3, Vector3, Universe3> for FreeCamera3 {
                                                   &distance,
                                                   &self.data.location,
                                                   &direction,
+                                                  self.data.collision,
                                                   context.debugging) {
                 let rotation_scale = direction.angle_between(&new_direction);
 
@@ -487,3 +1069,44 @@ impl Rotatable<Point3, Vector3> for FreeCamera3 {
         self.data.forward = rotation;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projected_rays_hit_their_pixels() {
+        let (width, height) = (64, 48);
+
+        for projection in &[Projection3::Perspective,
+                            Projection3::Orthographic(4.0),
+                            Projection3::Fisheye,
+                            Projection3::Equirectangular,
+                            Projection3::Panini(1.0)] {
+            let camera = Camera3Data::new().with_projection(*projection);
+
+            for &(screen_x, screen_y) in &[(32, 24), (3, 40), (60, 5)] {
+                let point = camera.get_ray_point(screen_x, screen_y, width, height);
+                let vector = camera.get_ray_vector(screen_x, screen_y, width, height);
+                let (x, y) = camera.project_point(&(point + vector * 5.0), width, height)
+                    .expect("The point should be visible.");
+
+                assert!((x - screen_x as F).abs() < 1e-6, "{:?}: {} != {}", projection, x, screen_x);
+                assert!((y - screen_y as F).abs() < 1e-6, "{:?}: {} != {}", projection, y, screen_y);
+            }
+        }
+    }
+
+    #[test]
+    fn walking_needs_collisions() {
+        let camera = Camera3Data::new().with_walking(true);
+
+        assert!(camera.walking);
+        assert!(camera.collision.is_some());
+
+        let camera = camera.with_collision(false);
+
+        assert!(!camera.walking);
+        assert!(camera.collision.is_none());
+    }
+}
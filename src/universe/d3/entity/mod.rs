@@ -27,6 +27,8 @@ pub struct Entity3Impl {
     shape: Arc<Shape3>,
     material: Arc<Material3>,
     surface: Option<Box<Surface3>>,
+    priority: i32,
+    solid: bool,
 }
 
 impl Entity3Impl {
@@ -38,6 +40,8 @@ impl Entity3Impl {
             shape: shape.into(),
             material: material.into(),
             surface: surface,
+            priority: 0,
+            solid: false,
         }
     }
 
@@ -53,6 +57,16 @@ impl Entity3Impl {
                -> Self {
         Self::new(shape, material, None)
     }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_solid(mut self, solid: bool) -> Self {
+        self.solid = solid;
+        self
+    }
 }
 
 impl Entity<Point3, Vector3> for Entity3Impl {
@@ -77,4 +91,12 @@ impl Traceable<Point3, Vector3> for Entity3Impl {
     fn surface(&self) -> Option<&Surface3> {
         self.surface.as_ref().map(|x| &**x)
     }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn is_solid(&self) -> bool {
+        self.solid
+    }
 }
@@ -5,62 +5,125 @@ use num::traits::NumCast;
 use num::Zero;
 use num::One;
 use na;
+use na::Norm;
+use na::BaseFloat;
 use util::CustomFloat;
 use universe::entity::shape::Shape;
 use universe::entity::shape::Hyperplane;
-use universe::entity::shape::HalfSpace;
-use universe::entity::shape::ComposableShape;
-use universe::entity::shape::SetOperation;
+use universe::entity::shape::ConvexPolytope;
+use universe::entity::polytope::RegularPolytope;
+use universe::entity::polytope::Parity;
+use universe::entity::polytope::golden_ratio;
+use universe::entity::polytope::signed_permutations;
 
 pub type Shape3 = Shape<Point3, Vector3>;
+pub type RegularPolytope3 = RegularPolytope<Point3, Vector3>;
 
-pub fn cuboid(center: Point3, abc: Vector3) -> ComposableShape<Point3, Vector3> {
-    let half_abc: Vector3 = abc / <F as NumCast>::from(2.0).unwrap();
-    let x = Vector3::new(<F as One>::one(), <F as Zero>::zero(), <F as Zero>::zero());
-    let y = Vector3::new(<F as Zero>::zero(), <F as One>::one(), <F as Zero>::zero());
-    let z = Vector3::new(<F as Zero>::zero(), <F as Zero>::zero(), <F as One>::one());
-    let shapes: Vec<Box<Shape<Point3, Vector3>>> = vec![Box::new(HalfSpace::new_with_point(Hyperplane::new_with_vectors(&y,
-                                                                             &z,
-                                                                             &na::translate(&(x *
-                                                                                 half_abc),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_vectors(&y,
-                                                                             &z,
-                                                                             &na::translate(&-(x *
-                                                                                  half_abc),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_vectors(&x,
-                                                                             &z,
-                                                                             &na::translate(&(y *
-                                                                                 half_abc),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_vectors(&x,
-                                                                             &z,
-                                                                             &na::translate(&-(y *
-                                                                                  half_abc),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_vectors(&x,
-                                                                             &y,
-                                                                             &na::translate(&(z *
-                                                                                 half_abc),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_vectors(&x,
-                                                                             &y,
-                                                                             &na::translate(&-(z *
-                                                                                  half_abc),
-                                                                               &center),
-                                                    ),
-                                                    &center))];
-    ComposableShape::of(shapes,
-                        SetOperation::Intersection)
+pub fn cuboid(center: Point3, abc: Vector3) -> ConvexPolytope<Point3, Vector3> {
+    ConvexPolytope::new_box(&center, &abc)
+}
+
+/// A right prism with a regular polygon as its base, the base is perpendicular to the direction
+pub fn prism(center: Point3,
+             direction: Vector3,
+             circumradius: F,
+             sides: u32,
+             height: F)
+             -> ConvexPolytope<Point3, Vector3> {
+    assert!(sides >= 3, "A prism needs at least 3 sides.");
+
+    let direction = direction.normalize();
+    // Any vector not parallel to the direction
+    let helper = if direction.x.abs() < <F as NumCast>::from(0.9).unwrap() {
+        Vector3::new(<F as One>::one(), <F as Zero>::zero(), <F as Zero>::zero())
+    } else {
+        Vector3::new(<F as Zero>::zero(), <F as One>::one(), <F as Zero>::zero())
+    };
+    let u = na::cross(&direction, &helper).normalize();
+    let v = na::cross(&direction, &u);
+    let sides_float = <F as NumCast>::from(sides).unwrap();
+    let inradius = circumradius * (<F as BaseFloat>::pi() / sides_float).cos();
+    let half_height = height / <F as NumCast>::from(2.0).unwrap();
+    let mut planes: Vec<Hyperplane<Point3, Vector3>> = (0..sides)
+        .map(|side| {
+            let angle = <F as BaseFloat>::two_pi() * <F as NumCast>::from(side).unwrap()
+                / sides_float;
+            let normal = u * angle.cos() + v * angle.sin();
+
+            Hyperplane::new_with_point(normal, &na::translate(&(normal * inradius), &center))
+        })
+        .collect();
+
+    for normal in &[direction, -direction] {
+        planes.push(Hyperplane::new_with_point(*normal,
+                                               &na::translate(&(*normal * half_height), &center)));
+    }
+
+    ConvexPolytope::new(planes)
+}
+
+fn cube_vertices() -> Vec<Vector3> {
+    signed_permutations(&[<F as One>::one(); 3], Parity::Any)
+}
+
+fn octahedron_vertices() -> Vec<Vector3> {
+    signed_permutations(&[<F as One>::one(), <F as Zero>::zero(), <F as Zero>::zero()],
+                        Parity::Any)
+}
+
+fn icosahedron_vertices() -> Vec<Vector3> {
+    signed_permutations(&[<F as Zero>::zero(), <F as One>::one(), golden_ratio()], Parity::Even)
+}
+
+/// Oriented so that its vertices point to the faces of `icosahedron_vertices`
+fn dodecahedron_vertices() -> Vec<Vector3> {
+    let phi = golden_ratio();
+    let mut vertices = cube_vertices();
+
+    vertices.extend(signed_permutations(&[<F as Zero>::zero(), phi, phi.recip()], Parity::Even));
+
+    vertices
+}
+
+pub fn tetrahedron() -> RegularPolytope3 {
+    let one = <F as One>::one();
+    let vertices = vec![Vector3::new(one, one, one),
+                        Vector3::new(one, -one, -one),
+                        Vector3::new(-one, one, -one),
+                        Vector3::new(-one, -one, one)];
+    // Each face is opposite to a vertex
+    let normals = vertices.iter().map(|vertex| -*vertex).collect();
+
+    RegularPolytope::new(vertices, normals)
+}
+
+pub fn cube() -> RegularPolytope3 {
+    RegularPolytope::new(cube_vertices(), octahedron_vertices())
+}
+
+pub fn octahedron() -> RegularPolytope3 {
+    RegularPolytope::new(octahedron_vertices(), cube_vertices())
+}
+
+pub fn dodecahedron() -> RegularPolytope3 {
+    RegularPolytope::new(dodecahedron_vertices(), icosahedron_vertices())
+}
+
+pub fn icosahedron() -> RegularPolytope3 {
+    RegularPolytope::new(icosahedron_vertices(), dodecahedron_vertices())
+}
+
+pub const PLATONIC_SOLIDS: [&str; 5] =
+    ["tetrahedron", "cube", "octahedron", "dodecahedron", "icosahedron"];
+
+/// Looks up a Platonic solid by one of the `PLATONIC_SOLIDS` names
+pub fn platonic_solid(name: &str) -> Option<RegularPolytope3> {
+    match name {
+        "tetrahedron" => Some(tetrahedron()),
+        "cube" | "hexahedron" => Some(cube()),
+        "octahedron" => Some(octahedron()),
+        "dodecahedron" => Some(dodecahedron()),
+        "icosahedron" => Some(icosahedron()),
+        _ => None,
+    }
 }
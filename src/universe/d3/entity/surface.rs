@@ -14,7 +14,10 @@ use palette::RgbHue;
 use util::CustomFloat;
 use universe::entity::surface::Surface;
 use universe::entity::surface::UVFn;
+use universe::entity::uv::uv_cylindrical;
 use universe::entity::surface::SurfaceColorProvider;
+use universe::entity::surface::Texture;
+use universe::entity::environment_map::EnvironmentSampler;
 use universe::entity::shape::TracingContext;
 
 pub type Surface3 = Surface<Point3, Vector3>;
@@ -66,3 +69,57 @@ pub fn uv_sphere(center_location: Point3) -> Box<UVFn<Point3>> {
                     <F as NumCast>::from(0.5).unwrap() - point.z.asin() / <F as BaseFloat>::pi())
     })
 }
+
+/// Wraps the texture around the axis, the angle is measured from `reference`
+/// counterclockwise when looking against the axis.
+pub fn uv_cylinder(origin: Point3, axis: Vector3, reference: Vector3) -> Box<UVFn<Point3>> {
+    uv_cylindrical(origin, axis, reference, na::cross(&axis, &reference))
+}
+
+/// Maps the direction to the texture coordinates of an equirectangular (latitude-longitude)
+/// image, the `z` axis points up. Matches the mapping of `uv_sphere`.
+fn uv_equirectangular(direction: &Vector3) -> na::Point2<F> {
+    na::Point2::<F>::new(<F as NumCast>::from(0.5).unwrap() +
+                direction.y.atan2(direction.x) / (<F as NumCast>::from(2.0).unwrap() * <F as BaseFloat>::pi()),
+                <F as NumCast>::from(0.5).unwrap() - direction.z.asin() / <F as BaseFloat>::pi())
+}
+
+pub fn environment_equirectangular(texture: Box<Texture>) -> Box<EnvironmentSampler<Vector3>> {
+    Box::new(move |direction: &Vector3| {
+        texture(&uv_equirectangular(direction))
+    })
+}
+
+/// Looks the direction up in one of the six faces of a cube map, ordered
+/// `+x`, `-x`, `+y`, `-y`, `+z`, `-z`. The side faces are viewed from the inside with
+/// the `z` axis pointing up, the top edges of the `+z` and `-z` faces lie towards `-x`
+/// and `+x` respectively.
+pub fn environment_cube_map(faces: Vec<Box<Texture>>) -> Box<EnvironmentSampler<Vector3>> {
+    assert_eq!(faces.len(), 6, "A cube map requires exactly 6 faces.");
+
+    Box::new(move |direction: &Vector3| {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (abs_x, abs_y, abs_z) = (x.abs(), y.abs(), z.abs());
+        // The face index and the coordinates on the face in the range [-1; 1]
+        let (face, u, v) = if abs_x >= abs_y && abs_x >= abs_z {
+            if x > 0.0 {
+                (0, -y / abs_x, -z / abs_x)
+            } else {
+                (1, y / abs_x, -z / abs_x)
+            }
+        } else if abs_y >= abs_z {
+            if y > 0.0 {
+                (2, x / abs_y, -z / abs_y)
+            } else {
+                (3, -x / abs_y, -z / abs_y)
+            }
+        } else if z > 0.0 {
+            (4, -y / abs_z, x / abs_z)
+        } else {
+            (5, -y / abs_z, -x / abs_z)
+        };
+        let half = <F as NumCast>::from(0.5).unwrap();
+
+        faces[face](&na::Point2::new((u + 1.0) * half, (v + 1.0) * half))
+    })
+}
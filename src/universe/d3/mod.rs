@@ -13,6 +13,7 @@ use universe::d3::entity::Entity3;
 use universe::entity::material::*;
 use universe::entity::shape::*;
 use universe::Universe;
+use universe::tone_mapping::ToneMapper;
 use util::CustomFloat;
 use util::HasId;
 use core::ops::Deref;
@@ -26,6 +27,7 @@ pub struct Universe3 {
     pub entities: Vec<Box<Entity3>>,
     pub intersections: GeneralIntersectors<Point3, Vector3>,
     pub background: Box<MappedTexture<Point3, Vector3>>,
+    pub tone_mapper: ToneMapper,
 }
 
 impl Universe3 {
@@ -42,6 +44,8 @@ impl Universe3 {
                             Box::new(HalfSpace::<Point3, Vector3>::intersect_linear));
         intersectors.insert((Vacuum::id_static(), Cylinder::<Point3, Vector3>::id_static()),
                             Box::new(Cylinder::<Point3, Vector3>::intersect_linear));
+        intersectors.insert((Vacuum::id_static(), ConvexPolytope::<Point3, Vector3>::id_static()),
+                            Box::new(ConvexPolytope::<Point3, Vector3>::intersect_linear));
         intersectors.insert((Vacuum::id_static(),
                      ComposableShape::<Point3, Vector3>::id_static()),
                     Box::new(ComposableShape::<Point3, Vector3>::intersect_linear));
@@ -55,15 +59,33 @@ impl Universe3 {
                             Box::new(HalfSpace::<Point3, Vector3>::intersect_linear));
         intersectors.insert((LinearSpace::<Point3, Vector3>::id_static(), Cylinder::<Point3, Vector3>::id_static()),
                             Box::new(Cylinder::<Point3, Vector3>::intersect_linear));
+        intersectors.insert((LinearSpace::<Point3, Vector3>::id_static(), ConvexPolytope::<Point3, Vector3>::id_static()),
+                            Box::new(ConvexPolytope::<Point3, Vector3>::intersect_linear));
         intersectors.insert((LinearSpace::<Point3, Vector3>::id_static(),
                      ComposableShape::<Point3, Vector3>::id_static()),
                     Box::new(ComposableShape::<Point3, Vector3>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point3, Vector3>::id_static(), VoidShape::id_static()),
+                            Box::new(intersect_void));
+        intersectors.insert((ParticipatingMedium::<Point3, Vector3>::id_static(), Sphere::<Point3, Vector3>::id_static()),
+                            Box::new(Sphere::<Point3, Vector3>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point3, Vector3>::id_static(), Hyperplane::<Point3, Vector3>::id_static()),
+                            Box::new(Hyperplane::<Point3, Vector3>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point3, Vector3>::id_static(), HalfSpace::<Point3, Vector3>::id_static()),
+                            Box::new(HalfSpace::<Point3, Vector3>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point3, Vector3>::id_static(), Cylinder::<Point3, Vector3>::id_static()),
+                            Box::new(Cylinder::<Point3, Vector3>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point3, Vector3>::id_static(), ConvexPolytope::<Point3, Vector3>::id_static()),
+                            Box::new(ConvexPolytope::<Point3, Vector3>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point3, Vector3>::id_static(),
+                     ComposableShape::<Point3, Vector3>::id_static()),
+                    Box::new(ComposableShape::<Point3, Vector3>::intersect_linear));
 
         Universe3 {
             camera: Arc::new(RwLock::new(camera)),
             entities: Vec::new(),
             intersections: intersectors,
             background: Box::new(MappedTextureTransparent::new()),
+            tone_mapper: ToneMapper::default(),
         }
     }
 }
@@ -111,4 +133,12 @@ impl Universe for Universe3 {
     fn set_background(&mut self, background: Box<MappedTexture<Self::P, Self::V>>) {
         self.background = background;
     }
+
+    fn tone_mapper(&self) -> &ToneMapper {
+        &self.tone_mapper
+    }
+
+    fn set_tone_mapper(&mut self, tone_mapper: ToneMapper) {
+        self.tone_mapper = tone_mapper;
+    }
 }
@@ -17,6 +17,7 @@ use num::traits::NumCast;
 use simulation::SimulationContext;
 use std::collections::HashSet;
 use std::time::Duration;
+use universe::Collision;
 use universe::Universe;
 use universe::d4::Point4;
 use universe::d4::Universe4;
@@ -27,10 +28,49 @@ use universe::entity::Entity;
 use universe::entity::Locatable;
 use universe::entity::Rotatable;
 use universe::entity::Traceable;
+use universe::entity::lens::LensSample;
+use universe::entity::lens::ThinLens;
+use universe::entity::stereo;
+use universe::entity::stereo::Eye;
+use universe::entity::stereo::Stereo;
 use util::AngleBetween;
 use util::CustomFloat;
 use util;
 
+/// The speed the plane of the `RetinaView::Rotating` view rotates with, in radians per second
+const RETINA_ROTATION_SPEED: F = 0.5;
+/// The distance kept from solid entities when collisions are enabled
+const COLLISION_RADIUS: F = 0.5;
+
+/// How the rays of the camera are cast through its 3D retina, spanned by the right, up and ana
+/// directions of the camera. The retina coordinates are given in units of half the diagonal
+/// of the shown image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection4 {
+    /// A pinhole camera, the rays diverge from the location of the camera
+    Perspective,
+    /// Parallel rays cast along `forward` from a retina whose image diagonal has the given
+    /// length, like `Projection3::Orthographic`, so that the sizes of the entities do not
+    /// change with their distance along any of the axes, including ana
+    Orthographic(F),
+    /// The retina is mapped to the directions on the 3-sphere by the inverse stereographic
+    /// projection, it shows the whole space around the camera with the opposite of `forward`
+    /// at the infinity of the retina
+    Stereographic,
+}
+
+/// How the 3D image on the retina of the camera is shown on the 2D screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetinaView {
+    /// The plane of the retina spanned by the right and up directions
+    Slice,
+    /// A grid of planes parallel to `Slice` ordered by their ana coordinate, the number of
+    /// slices is rounded up to fill the grid
+    Slices(u32),
+    /// The plane of `Slice` rotating around the up axis, sweeping through the whole retina
+    Rotating,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub struct FreeCamera4 {
     location: Point4,
@@ -41,6 +81,19 @@ pub struct FreeCamera4 {
     speed: F,
     fov: u8,
     max_depth: u32,
+    /// The offset of the sliced hyperplane along `to_ana`, `None` if the universe is not sliced
+    cross_section: Option<F>,
+    /// Whether the key toggling the cross section was held during the last update
+    cross_section_key_held: bool,
+    projection: Projection4,
+    retina_view: RetinaView,
+    /// The current angle of the `RetinaView::Rotating` view
+    retina_angle: F,
+    /// The lens focusing the rays, `None` for a pinhole camera
+    lens: Option<ThinLens>,
+    stereo: Option<Stereo>,
+    /// Stops the camera in front of solid entities, `None` to fly through them
+    collision: Option<Collision>,
 }
 
 impl FreeCamera4 {
@@ -55,6 +108,14 @@ impl FreeCamera4 {
             speed: 10.0,
             fov: 90,
             max_depth: 10,
+            cross_section: None,
+            cross_section_key_held: false,
+            projection: Projection4::Perspective,
+            retina_view: RetinaView::Slice,
+            retina_angle: <F as Zero>::zero(),
+            lens: None,
+            stereo: None,
+            collision: None,
         }
     }
 
@@ -65,6 +126,65 @@ impl FreeCamera4 {
         }
     }
 
+    /// Shows the 3D slice of the universe at the given offset along `to_ana` from the location.
+    pub fn new_cross_section(location: Point4, offset: F) -> Self {
+        FreeCamera4 {
+            location: location,
+            cross_section: Some(offset),
+            .. Self::new()
+        }
+    }
+
+    pub fn with_projection(mut self, projection: Projection4) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_retina_view(mut self, retina_view: RetinaView) -> Self {
+        self.retina_view = retina_view;
+        self
+    }
+
+    pub fn with_lens(mut self, lens: ThinLens) -> Self {
+        self.lens = Some(lens);
+        self
+    }
+
+    pub fn with_stereo(mut self, stereo: Stereo) -> Self {
+        self.stereo = Some(stereo);
+        self
+    }
+
+    pub fn with_collision(mut self, collision: bool) -> Self {
+        self.collision = if collision { Some(Collision::new(COLLISION_RADIUS)) } else { None };
+        self
+    }
+
+    /// Toggles the cross section with [`X`] and moves the sliced hyperplane
+    /// along `to_ana` with [`R`] and [`F`].
+    fn update_cross_section(&mut self, delta_millis: F, context: &SimulationContext) {
+        let pressed_keys: &HashSet<VirtualKeyCode> = context.pressed_keys();
+        let key_held = pressed_keys.contains(&VirtualKeyCode::X);
+
+        if key_held && !self.cross_section_key_held {
+            self.cross_section = match self.cross_section {
+                Some(_) => None,
+                None => Some(<F as Zero>::zero()),
+            };
+        }
+
+        self.cross_section_key_held = key_held;
+
+        if let Some(ref mut offset) = self.cross_section {
+            let distance = self.speed * delta_millis;
+
+            pressed_keys.contains(&VirtualKeyCode::R).as_option()
+                .map(|()| *offset += distance);
+            pressed_keys.contains(&VirtualKeyCode::F).as_option()
+                .map(|()| *offset -= distance);
+        }
+    }
+
     fn update_rotation(&mut self, delta_millis: F, context: &SimulationContext) {
         let pressed_keys: &HashSet<VirtualKeyCode> = context.pressed_keys();
         let mut angle: F = <F as Zero>::zero();
@@ -126,6 +246,71 @@ impl FreeCamera4 {
         }
     }
 
+    /// Finds the point on the retina seen at the screen coordinates, see `Projection4`.
+    /// The ana coordinate is kept at zero in the cross section, so that the rays do not leave it.
+    fn retina_point(&self,
+                    screen_x: i32,
+                    screen_y: i32,
+                    screen_width: i32,
+                    screen_height: i32)
+                    -> (F, F, F) {
+        let (columns, rows) = match self.retina_view {
+            RetinaView::Slices(count) => {
+                let columns = (<F as NumCast>::from(count.max(1)).unwrap().sqrt().ceil()) as i32;
+                let rows = (count.max(1) as i32 + columns - 1) / columns;
+
+                (columns, rows)
+            }
+            _ => (1, 1),
+        };
+        let tile_width = (screen_width / columns).max(1);
+        let tile_height = (screen_height / rows).max(1);
+        let column = (screen_x / tile_width).min(columns - 1);
+        // Slices are ordered from the top left corner of the screen
+        let row = rows - 1 - (screen_y / tile_height).min(rows - 1);
+        let tile_x = screen_x - column * tile_width;
+        let tile_y = screen_y - (rows - 1 - row) * tile_height;
+        let rel_x: F = <F as NumCast>::from(tile_x - tile_width / 2).unwrap() +
+                       <F as NumCast>::from(1 - tile_width % 2).unwrap() / 2.0;
+        let rel_y: F = <F as NumCast>::from(tile_y - tile_height / 2).unwrap() +
+                       <F as NumCast>::from(1 - tile_height % 2).unwrap() / 2.0;
+        let tile_width: F = <F as NumCast>::from(tile_width).unwrap();
+        let tile_height: F = <F as NumCast>::from(tile_height).unwrap();
+        let half_diagonal = (tile_width * tile_width + tile_height * tile_height).sqrt() / 2.0;
+        let x = rel_x / half_diagonal;
+        let y = rel_y / half_diagonal;
+
+        if self.cross_section.is_some() {
+            return (x, y, <F as Zero>::zero());
+        }
+
+        match self.retina_view {
+            RetinaView::Slice => (x, y, <F as Zero>::zero()),
+            RetinaView::Slices(_) => {
+                let index: F = <F as NumCast>::from(row * columns + column).unwrap();
+                let count: F = <F as NumCast>::from(rows * columns).unwrap();
+
+                (x, y, (index + 0.5) * 2.0 / count - 1.0)
+            }
+            RetinaView::Rotating => {
+                (x * self.retina_angle.cos(), y, x * self.retina_angle.sin())
+            }
+        }
+    }
+
+    /// The location the rays are cast from, moved into the cross section
+    fn eye(&self) -> Point4 {
+        match self.cross_section {
+            Some(offset) => self.location + self.to_ana() * offset,
+            None => self.location,
+        }
+    }
+
+    /// Converts the retina coordinates to a vector in the hyperplane perpendicular to `forward`
+    fn retina_vector(&self, (x, y, w): (F, F, F)) -> Vector4 {
+        -self.left * x + self.up * y + self.to_ana() * w
+    }
+
     /// Calculates the direction in the fourth axis.
     /// `Ana` stands for _north_ in this axis, `kata` is used for _south_.
     /// The method name `to_ana` stands for positive motion along this axis,
@@ -149,7 +334,18 @@ impl Camera<Point4, Vector4, Universe4> for FreeCamera4 {
                      screen_width: i32,
                      screen_height: i32)
                      -> Point4 {
-        self.location
+        let location = self.eye();
+
+        match self.projection {
+            Projection4::Orthographic(size) => {
+                let retina_point = self.retina_point(screen_x, screen_y,
+                                                     screen_width, screen_height);
+
+                // The retina coordinates are in units of half the diagonal
+                location + self.retina_vector(retina_point) * (size / 2.0)
+            }
+            _ => location,
+        }
     }
 
     fn get_ray_vector(&self,
@@ -158,31 +354,93 @@ impl Camera<Point4, Vector4, Universe4> for FreeCamera4 {
                       screen_width: i32,
                       screen_height: i32)
                       -> Vector4 {
-        let rel_x: F = <F as NumCast>::from(screen_x - screen_width / 2).unwrap() +
-                       <F as NumCast>::from(1 - screen_width % 2).unwrap() / 2.0;
-        let rel_y: F = <F as NumCast>::from(screen_y - screen_height / 2).unwrap() +
-                       <F as NumCast>::from(1 - screen_height % 2).unwrap() / 2.0;
-        let screen_width: F = <F as NumCast>::from(screen_width).unwrap();
-        let screen_height: F = <F as NumCast>::from(screen_height).unwrap();
-        let right = -self.left;
         let fov_rad: F = <F as BaseFloat>::pi() * <F as NumCast>::from(self.fov).unwrap() / 180.0;
-        let distance_from_screen_center: F =
-            (screen_width * screen_width + screen_height * screen_height).sqrt() /
-            (<F as NumCast>::from(2.0).unwrap() * (fov_rad / 2.0).tan());
-        let screen_center_point_4d = self.location + self.forward * distance_from_screen_center;
-        let screen_point_4d = screen_center_point_4d + (self.up * rel_y) + (right * rel_x);
+        let retina_point = self.retina_point(screen_x, screen_y, screen_width, screen_height);
+        let retina_vector = self.retina_vector(retina_point);
 
-        (screen_point_4d - self.location).normalize()
+        match self.projection {
+            Projection4::Perspective => {
+                (self.forward + retina_vector * (fov_rad / 2.0).tan()).normalize()
+            }
+            Projection4::Orthographic(_) => self.forward,
+            Projection4::Stereographic => {
+                // The corners of the image are seen at half of the field of view from `forward`
+                let point = retina_vector * (fov_rad / 4.0).tan();
+                let norm_squared = point.norm_squared();
+
+                (self.forward * (<F as One>::one() - norm_squared) + point * 2.0) /
+                (<F as One>::one() + norm_squared)
+            }
+        }
+    }
+
+    fn get_ray(&self,
+               screen_x: i32,
+               screen_y: i32,
+               screen_width: i32,
+               screen_height: i32,
+               lens_sample: LensSample,
+               eye: Option<Eye>)
+               -> (Point4, Vector4) {
+        let (eye, screen_x, screen_y, screen_width, screen_height) = match self.stereo {
+            Some(ref stereo) => stereo.viewport(eye, screen_x, screen_y, screen_width, screen_height),
+            None => (None, screen_x, screen_y, screen_width, screen_height),
+        };
+        let mut point = self.get_ray_point(screen_x, screen_y, screen_width, screen_height);
+        let vector = self.get_ray_vector(screen_x, screen_y, screen_width, screen_height);
+
+        if let (Some(stereo), Some(eye)) = (self.stereo, eye) {
+            // The stereographic retina sees all around the camera
+            let left = if self.projection == Projection4::Stereographic {
+                stereo::omnidirectional_left(&vector, &self.forward, &self.left)
+            } else {
+                self.left
+            };
+
+            point = point + left * stereo.eye_offset(eye);
+        }
+
+        match self.lens {
+            // The aperture is a ball, so the defocus also blurs along ana
+            Some(ref lens) => lens.focus(&point, &vector, &self.forward,
+                                         &[-self.left, self.up, self.to_ana()], lens_sample),
+            None => (point, vector),
+        }
     }
 
     fn max_depth(&self) -> u32 {
         self.max_depth
     }
 
+    fn stereo(&self) -> Option<Stereo> {
+        self.stereo
+    }
+
+    fn cross_section(&self) -> Option<Vector4> {
+        self.cross_section.map(|_| self.to_ana())
+    }
+
     fn update(&mut self, delta_time: &Duration, context: &SimulationContext, universe: &Universe4) {
         let delta_millis = <F as NumCast>::from((*delta_time * 1000u32).as_secs()).unwrap() / 1000.0;
 
         self.update_rotation(delta_millis, context);
+        self.update_cross_section(delta_millis, context);
+
+        if self.retina_view == RetinaView::Rotating {
+            self.retina_angle = (self.retina_angle + RETINA_ROTATION_SPEED * delta_millis)
+                % <F as BaseFloat>::two_pi();
+        }
+
+        if let Some(mut lens) = self.lens {
+            let center_distance = if lens.autofocus {
+                universe.trace_distance_unknown(delta_time, &self.eye(), &self.forward, &|_| true)
+            } else {
+                None
+            };
+
+            lens.update(delta_millis, context.pressed_keys(), center_distance);
+            self.lens = Some(lens);
+        }
 
         let pressed_keys: &HashSet<VirtualKeyCode> = context.pressed_keys();
         let mut distance = self.speed * delta_millis;
@@ -221,6 +479,7 @@ impl Camera<Point4, Vector4, Universe4> for FreeCamera4 {
                                                   &distance,
                                                   &self.location,
                                                   &direction,
+                                                  self.collision,
                                                   context.debugging) {
                 let rotation_scale = direction.angle_between(&new_direction);
 
@@ -276,3 +535,43 @@ impl Rotatable<Point4, Vector4> for FreeCamera4 {
         self.forward = rotation;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projections_look_forward() {
+        for projection in &[Projection4::Perspective,
+                            Projection4::Orthographic(2.0),
+                            Projection4::Stereographic] {
+            let camera = FreeCamera4::new().with_projection(*projection);
+            let vector = camera.get_ray_vector(50, 50, 101, 101);
+
+            assert!(vector.approx_eq(&camera.forward));
+            assert!(ApproxEq::approx_eq(&camera.get_ray_vector(0, 7, 101, 101).norm(),
+                                        &1.0));
+        }
+    }
+
+    #[test]
+    fn orthographic_size_spans_diagonal() {
+        // The diagonal of the image is 5 pixels long, so each pixel is a unit wide
+        let camera = FreeCamera4::new()
+            .with_projection(Projection4::Orthographic(5.0))
+            .with_retina_view(RetinaView::Slice);
+        let offset = camera.get_ray_point(3, 1, 4, 3) - camera.location;
+
+        assert!(ApproxEq::approx_eq(&offset.norm(), &1.5));
+    }
+
+    #[test]
+    fn slices_span_retina() {
+        let camera = FreeCamera4::new().with_retina_view(RetinaView::Slices(3));
+        let (_, _, first) = camera.retina_point(0, 99, 100, 100);
+        let (_, _, last) = camera.retina_point(99, 0, 100, 100);
+
+        assert!(ApproxEq::approx_eq(&first, &-0.75));
+        assert!(ApproxEq::approx_eq(&last, &0.75));
+    }
+}
@@ -28,6 +28,8 @@ pub struct Entity4Impl {
     shape: Arc<Shape4>,
     material: Arc<Material4>,
     surface: Option<Arc<Surface4>>,
+    priority: i32,
+    solid: bool,
 }
 
 impl Entity4Impl {
@@ -39,6 +41,8 @@ impl Entity4Impl {
             shape: shape.into(),
             material: material.into(),
             surface: surface.map(|surface| surface.into()),
+            priority: 0,
+            solid: false,
         }
     }
 
@@ -54,6 +58,16 @@ impl Entity4Impl {
                -> Self {
         Self::new(shape, material, None)
     }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_solid(mut self, solid: bool) -> Self {
+        self.solid = solid;
+        self
+    }
 }
 
 impl Entity<Point4, Vector4> for Entity4Impl {
@@ -78,4 +92,12 @@ impl Traceable<Point4, Vector4> for Entity4Impl {
     fn surface(&self) -> Option<&Surface4> {
         self.surface.as_ref().map(|x| &**x)
     }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn is_solid(&self) -> bool {
+        self.solid
+    }
 }
@@ -1,76 +1,168 @@
 use ::F;
 use universe::d4::Point4;
 use universe::d4::Vector4;
-use num::traits::NumCast;
 use num::Zero;
 use num::One;
-use na;
-use na::Norm;
 use util::CustomFloat;
 use universe::entity::shape::Shape;
-use universe::entity::shape::Hyperplane;
-use universe::entity::shape::HalfSpace;
-use universe::entity::shape::ComposableShape;
-use universe::entity::shape::SetOperation;
+use universe::entity::shape::ConvexPolytope;
+use universe::entity::polytope::RegularPolytope;
+use universe::entity::polytope::Parity;
+use universe::entity::polytope::golden_ratio;
+use universe::entity::polytope::signed_permutations;
+use util::cast;
 
 pub type Shape4 = Shape<Point4, Vector4>;
+pub type RegularPolytope4 = RegularPolytope<Point4, Vector4>;
 
-pub fn hypercuboid(center: Point4, abcd: Vector4) -> ComposableShape<Point4, Vector4> {
-    let half_abcd: Vector4 = abcd / <F as NumCast>::from(2.0).unwrap();
-    let x = Vector4::new(<F as One>::one(), <F as Zero>::zero(), <F as Zero>::zero(), <F as Zero>::zero());
-    let y = Vector4::new(<F as Zero>::zero(), <F as One>::one(), <F as Zero>::zero(), <F as Zero>::zero());
-    let z = Vector4::new(<F as Zero>::zero(), <F as Zero>::zero(), <F as One>::one(), <F as Zero>::zero());
-    let w = Vector4::new(<F as Zero>::zero(), <F as Zero>::zero(), <F as Zero>::zero(), <F as One>::one());
-    let shapes: Vec<Box<Shape<Point4, Vector4>>> = vec![
-                            Box::new(HalfSpace::new_with_point(Hyperplane::new_with_point(x.normalize(),
-                                                                             &na::translate(&(x *
-                                                                                 half_abcd),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_point(x.normalize(),
-                                                                             &na::translate(&-(x *
-                                                                                  half_abcd),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_point(y.normalize(),
-                                                                             &na::translate(&(y *
-                                                                                 half_abcd),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_point(y.normalize(),
-                                                                             &na::translate(&-(y *
-                                                                                  half_abcd),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_point(z.normalize(),
-                                                                             &na::translate(&(z *
-                                                                                 half_abcd),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_point(z.normalize(),
-                                                                             &na::translate(&-(z *
-                                                                                  half_abcd),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_point(w.normalize(),
-                                                                             &na::translate(&(w *
-                                                                                 half_abcd),
-                                                                               &center),
-                                                    ),
-                                                    &center)),
-                             Box::new(HalfSpace::new_with_point(Hyperplane::new_with_point(w.normalize(),
-                                                                             &na::translate(&-(w *
-                                                                                  half_abcd),
-                                                                               &center),
-                                                    ),
-                                                    &center))
-                             ];
-    ComposableShape::of(shapes,
-                        SetOperation::Intersection)
+pub fn hypercuboid(center: Point4, abcd: Vector4) -> ConvexPolytope<Point4, Vector4> {
+    ConvexPolytope::new_box(&center, &abcd)
+}
+
+/// The vertices of a 600-cell, the parity of the permutations selects one of its two
+/// orientations with the same vertices on the axes
+fn six_hundred_cell_vertices(parity: Parity) -> Vec<Vector4> {
+    let phi = golden_ratio();
+    let mut vertices = signed_permutations(&[cast(2.0), cast(0.0), cast(0.0), cast(0.0)],
+                                           Parity::Any);
+
+    vertices.extend(signed_permutations(&[<F as One>::one(); 4], Parity::Any));
+    vertices.extend(signed_permutations(&[phi, <F as One>::one(), phi.recip(), <F as Zero>::zero()],
+                                        parity));
+
+    vertices
+}
+
+/// The vertices of a 120-cell, the parity of the permutations selects one of its two
+/// orientations with the same vertices in the planes of the axes
+fn hundred_twenty_cell_vertices(parity: Parity) -> Vec<Vector4> {
+    let phi = golden_ratio();
+    let zero = <F as Zero>::zero();
+    let one = <F as One>::one();
+    let mut vertices: Vec<Vector4> = Vec::new();
+
+    for coordinates in &[[zero, zero, cast(2.0), cast(2.0)],
+                         [one, one, one, cast(5.0).sqrt()],
+                         [phi.powi(-2), phi, phi, phi],
+                         [phi.recip(), phi.recip(), phi.recip(), phi * phi]] {
+        vertices.extend(signed_permutations(coordinates, Parity::Any));
+    }
+
+    for coordinates in &[[zero, phi.powi(-2), one, phi * phi],
+                         [zero, phi.recip(), phi, cast(5.0).sqrt()],
+                         [phi.recip(), one, phi, cast(2.0)]] {
+        vertices.extend(signed_permutations(coordinates, parity));
+    }
+
+    vertices
+}
+
+/// The regular polytope with 5 tetrahedral cells, the 4D simplex
+pub fn five_cell() -> RegularPolytope4 {
+    let one = <F as One>::one();
+    let w = -cast(5.0).sqrt().recip();
+    let vertices = vec![Vector4::new(one, one, one, w),
+                        Vector4::new(one, -one, -one, w),
+                        Vector4::new(-one, one, -one, w),
+                        Vector4::new(-one, -one, one, w),
+                        Vector4::new(<F as Zero>::zero(),
+                                     <F as Zero>::zero(),
+                                     <F as Zero>::zero(),
+                                     cast(-4.0) * w)];
+    // Each cell is opposite to a vertex
+    let normals = vertices.iter().map(|vertex| -*vertex).collect();
+
+    RegularPolytope::new(vertices, normals)
+}
+
+/// The regular polytope with 8 cubic cells, also known as the 8-cell
+pub fn tesseract() -> RegularPolytope4 {
+    RegularPolytope::new(signed_permutations(&[<F as One>::one(); 4], Parity::Any),
+                         signed_permutations(&[cast(1.0), cast(0.0), cast(0.0), cast(0.0)],
+                                             Parity::Any))
+}
+
+/// The regular polytope with 16 tetrahedral cells, its vertices lie on the axes
+pub fn sixteen_cell() -> RegularPolytope4 {
+    RegularPolytope::new(signed_permutations(&[cast(1.0), cast(0.0), cast(0.0), cast(0.0)],
+                                             Parity::Any),
+                         signed_permutations(&[<F as One>::one(); 4], Parity::Any))
+}
+
+/// The regular polytope with 24 octahedral cells
+pub fn twenty_four_cell() -> RegularPolytope4 {
+    let mut normals = signed_permutations(&[cast(1.0), cast(0.0), cast(0.0), cast(0.0)],
+                                          Parity::Any);
+
+    normals.extend(signed_permutations(&[<F as One>::one(); 4], Parity::Any));
+
+    RegularPolytope::new(signed_permutations(&[cast(1.0), cast(1.0), cast(0.0), cast(0.0)],
+                                             Parity::Any),
+                         normals)
+}
+
+/// The regular polytope with 120 dodecahedral cells. The normals of its cells point
+/// to the vertices of a 600-cell.
+pub fn hundred_twenty_cell() -> RegularPolytope4 {
+    RegularPolytope::new(hundred_twenty_cell_vertices(Parity::Even),
+                         six_hundred_cell_vertices(Parity::Odd))
+}
+
+/// The regular polytope with 600 tetrahedral cells. The normals of its cells point
+/// to the vertices of a 120-cell.
+pub fn six_hundred_cell() -> RegularPolytope4 {
+    RegularPolytope::new(six_hundred_cell_vertices(Parity::Even),
+                         hundred_twenty_cell_vertices(Parity::Odd))
+}
+
+pub const REGULAR_POLYTOPES: [&str; 6] =
+    ["5-cell", "tesseract", "16-cell", "24-cell", "120-cell", "600-cell"];
+
+/// Looks up a regular convex 4-polytope by one of the `REGULAR_POLYTOPES` names,
+/// the tesseract is also known as the `8-cell`.
+pub fn regular_polytope(name: &str) -> Option<RegularPolytope4> {
+    match name {
+        "5-cell" => Some(five_cell()),
+        "tesseract" | "8-cell" => Some(tesseract()),
+        "16-cell" => Some(sixteen_cell()),
+        "24-cell" => Some(twenty_four_cell()),
+        "120-cell" => Some(hundred_twenty_cell()),
+        "600-cell" => Some(six_hundred_cell()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polytopes_have_the_right_number_of_elements() {
+        // Vertices, edges and cells
+        let counts = [(5, 10, 5), (16, 32, 8), (8, 24, 16), (24, 96, 24), (600, 1200, 120),
+                      (120, 720, 600)];
+
+        for (name, &(vertices, edges, cells)) in REGULAR_POLYTOPES.iter().zip(counts.iter()) {
+            let polytope = regular_polytope(name).unwrap();
+
+            assert_eq!(polytope.vertices.len(), vertices, "{}", name);
+            assert_eq!(polytope.edges().len(), edges, "{}", name);
+            assert_eq!(polytope.facet_normals.len(), cells, "{}", name);
+        }
+    }
+
+    #[test]
+    fn polytope_vertices_are_on_the_boundary() {
+        let center = Point4::new(1.0, 2.0, 3.0, 4.0);
+
+        for name in &REGULAR_POLYTOPES {
+            let polytope = regular_polytope(name).unwrap();
+            let solid = polytope.solid(&center, 2.0);
+
+            for vertex in &polytope.vertices {
+                assert!(solid.is_point_inside(&(center + *vertex * 1.999)), "{}", name);
+                assert!(!solid.is_point_inside(&(center + *vertex * 2.001)), "{}", name);
+            }
+        }
+    }
 }
@@ -1,10 +1,17 @@
+use ::F;
 use universe::d3::Point3;
+use universe::d3::Vector3;
 use universe::d4::Point4;
 use universe::d4::Vector4;
 use util::CustomFloat;
 use util::Derank;
 use universe::entity::surface::Surface;
 use universe::entity::surface::UVFn;
+use universe::entity::environment_map::EnvironmentSampler;
+use na::Norm;
+use na::BaseFloat;
+use na::Point2;
+use num::Zero;
 
 pub type Surface4 = Surface<Point4, Vector4>;
 
@@ -13,3 +20,48 @@ pub fn uv_derank(uvfn: Box<UVFn<Point3>>) -> Box<UVFn<Point4>> {
         uvfn(&point.derank())
     })
 }
+
+/// Maps the point using two of the hyperspherical coordinates around the center.
+/// The `u` coordinate is the longitude in the `xy` plane and the `v` coordinate is the angle
+/// from the `w` axis, so that each sphere of points with the same angle between the `xy` plane
+/// and the `z` axis is covered by the whole texture.
+pub fn uv_hyperspherical(center: Point4) -> Box<UVFn<Point4>> {
+    Box::new(move |point: &Point4| {
+        let point = (*point - center).normalize();
+        let longitude = point.y.atan2(point.x);
+        let polar = point.w.max(-1.0).min(1.0).acos();
+
+        Point2::new(0.5 + longitude / <F as BaseFloat>::two_pi(),
+                    polar / <F as BaseFloat>::pi())
+    })
+}
+
+/// Projects the direction to 3D by omitting the `w` coordinate. The directions along the
+/// `w` axis are mapped to the direction `(0, 0, 1)`.
+pub fn environment_derank(sampler: Box<EnvironmentSampler<Vector3>>)
+        -> Box<EnvironmentSampler<Vector4>> {
+    Box::new(move |direction: &Vector4| {
+        let deranked = Vector3::new(direction.x, direction.y, direction.z);
+
+        if deranked.norm() <= <F as Zero>::zero() {
+            sampler(&Vector3::new(0.0, 0.0, 1.0))
+        } else {
+            sampler(&deranked.normalize())
+        }
+    })
+}
+
+/// Maps the 3-sphere of 4D directions onto the 2-sphere of 3D directions using
+/// the Hopf fibration. Unlike `environment_derank`, the mapping is continuous
+/// for all directions, each 3D direction corresponds to a great circle of 4D directions.
+pub fn environment_hopf(sampler: Box<EnvironmentSampler<Vector3>>)
+        -> Box<EnvironmentSampler<Vector4>> {
+    Box::new(move |direction: &Vector4| {
+        let (x, y, z, w) = (direction.x, direction.y, direction.z, direction.w);
+        let projected = Vector3::new(2.0 * (x * z + y * w),
+                                     2.0 * (y * z - x * w),
+                                     x * x + y * y - z * z - w * w);
+
+        sampler(&projected.normalize())
+    })
+}
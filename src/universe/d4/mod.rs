@@ -13,6 +13,7 @@ use universe::d4::entity::Entity4;
 use universe::entity::material::*;
 use universe::entity::shape::*;
 use universe::Universe;
+use universe::tone_mapping::ToneMapper;
 use util::CustomFloat;
 use util::HasId;
 use core::ops::Deref;
@@ -26,6 +27,9 @@ pub struct Universe4 {
     pub entities: Vec<Box<Entity4>>,
     pub intersections: GeneralIntersectors<Point4, Vector4>,
     pub background: Box<MappedTexture<Point4, Vector4>>,
+    pub tone_mapper: ToneMapper,
+    /// The cross section of the camera, taken from it once per frame
+    pub cross_section: Option<Vector4>,
 }
 
 impl Universe4 {
@@ -42,6 +46,8 @@ impl Universe4 {
                             Box::new(HalfSpace::<Point4, Vector4>::intersect_linear));
         intersectors.insert((Vacuum::id_static(), Cylinder::<Point4, Vector4>::id_static()),
                             Box::new(Cylinder::<Point4, Vector4>::intersect_linear));
+        intersectors.insert((Vacuum::id_static(), ConvexPolytope::<Point4, Vector4>::id_static()),
+                            Box::new(ConvexPolytope::<Point4, Vector4>::intersect_linear));
         intersectors.insert((Vacuum::id_static(),
                      ComposableShape::<Point4, Vector4>::id_static()),
                     Box::new(ComposableShape::<Point4, Vector4>::intersect_linear));
@@ -55,15 +61,36 @@ impl Universe4 {
                             Box::new(HalfSpace::<Point4, Vector4>::intersect_linear));
         intersectors.insert((LinearSpace::<Point4, Vector4>::id_static(), Cylinder::<Point4, Vector4>::id_static()),
                             Box::new(Cylinder::<Point4, Vector4>::intersect_linear));
+        intersectors.insert((LinearSpace::<Point4, Vector4>::id_static(), ConvexPolytope::<Point4, Vector4>::id_static()),
+                            Box::new(ConvexPolytope::<Point4, Vector4>::intersect_linear));
         intersectors.insert((LinearSpace::<Point4, Vector4>::id_static(),
                      ComposableShape::<Point4, Vector4>::id_static()),
                     Box::new(ComposableShape::<Point4, Vector4>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point4, Vector4>::id_static(), VoidShape::id_static()),
+                            Box::new(intersect_void));
+        intersectors.insert((ParticipatingMedium::<Point4, Vector4>::id_static(), Sphere::<Point4, Vector4>::id_static()),
+                            Box::new(Sphere::<Point4, Vector4>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point4, Vector4>::id_static(), Hyperplane::<Point4, Vector4>::id_static()),
+                            Box::new(Hyperplane::<Point4, Vector4>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point4, Vector4>::id_static(), HalfSpace::<Point4, Vector4>::id_static()),
+                            Box::new(HalfSpace::<Point4, Vector4>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point4, Vector4>::id_static(), Cylinder::<Point4, Vector4>::id_static()),
+                            Box::new(Cylinder::<Point4, Vector4>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point4, Vector4>::id_static(), ConvexPolytope::<Point4, Vector4>::id_static()),
+                            Box::new(ConvexPolytope::<Point4, Vector4>::intersect_linear));
+        intersectors.insert((ParticipatingMedium::<Point4, Vector4>::id_static(),
+                     ComposableShape::<Point4, Vector4>::id_static()),
+                    Box::new(ComposableShape::<Point4, Vector4>::intersect_linear));
+
+        let cross_section = camera.cross_section();
 
         Universe4 {
+            cross_section: cross_section,
             camera: Arc::new(RwLock::new(camera)),
             entities: Vec::new(),
             intersections: intersectors,
             background: Box::new(MappedTextureTransparent::new()),
+            tone_mapper: ToneMapper::default(),
         }
     }
 }
@@ -111,4 +138,20 @@ impl Universe for Universe4 {
     fn set_background(&mut self, background: Box<MappedTexture<Self::P, Self::V>>) {
         self.background = background;
     }
+
+    fn tone_mapper(&self) -> &ToneMapper {
+        &self.tone_mapper
+    }
+
+    fn set_tone_mapper(&mut self, tone_mapper: ToneMapper) {
+        self.tone_mapper = tone_mapper;
+    }
+
+    fn cross_section(&self) -> Option<Vector4> {
+        self.cross_section
+    }
+
+    fn set_cross_section(&mut self, cross_section: Option<Vector4>) {
+        self.cross_section = cross_section;
+    }
 }
@@ -0,0 +1,192 @@
+use ::F;
+use num::Float;
+use num::Zero;
+use palette::Rgb;
+use universe::aov::AovImage;
+use universe::aov::AovSample;
+
+/// The B3 spline used by the À-Trous wavelet transform
+const KERNEL: [F; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// An edge-avoiding À-Trous wavelet filter (Dammertz et al. 2010). The beauty image is
+/// blurred with a kernel that grows each iteration, while the color, normal, albedo and depth
+/// buffers stop the blur at edges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    /// The number of passes, the kernel spans `4 * 2^iterations + 1` pixels
+    pub iterations: u32,
+    /// The color difference tolerated in the first pass, halved each pass
+    pub sigma_color: F,
+    pub sigma_normal: F,
+    pub sigma_albedo: F,
+    /// The tolerated depth difference relative to the depth of the filtered pixel
+    pub sigma_depth: F,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 4,
+            sigma_color: 0.5,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.1,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+fn distance_squared(a: &[F], b: &[F]) -> F {
+    a.iter().zip(b.iter()).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+fn color_array(color: &Rgb<F>) -> [F; 3] {
+    [color.red, color.green, color.blue]
+}
+
+impl Denoiser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    fn depth_weight(&self, center: F, other: F, step: F) -> F {
+        match (center.is_infinite(), other.is_infinite()) {
+            (true, true) => 1.0,
+            (false, false) => {
+                let scale = self.sigma_depth * center.max(<F as Float>::epsilon()) * step;
+
+                (-(center - other).abs() / scale).exp()
+            }
+            _ => <F as Zero>::zero(),
+        }
+    }
+
+    fn edge_weight(&self,
+                   center: &AovSample,
+                   center_color: &Rgb<F>,
+                   other: &AovSample,
+                   other_color: &Rgb<F>,
+                   sigma_color: F,
+                   step: F)
+                   -> F {
+        let color = distance_squared(&color_array(center_color), &color_array(other_color))
+            / (sigma_color * sigma_color);
+        let normal = distance_squared(&center.normal, &other.normal)
+            / (self.sigma_normal * self.sigma_normal);
+        let albedo = distance_squared(&color_array(&center.albedo), &color_array(&other.albedo))
+            / (self.sigma_albedo * self.sigma_albedo);
+
+        (-(color + normal + albedo)).exp() * self.depth_weight(center.depth, other.depth, step)
+    }
+
+    /// Filters the beauty layer of the image, the other layers guide the filter.
+    /// The colors are returned in the same order as the samples of the image.
+    pub fn denoise(&self, image: &AovImage) -> Vec<Rgb<F>> {
+        let (width, height) = (image.width as i64, image.height as i64);
+        let mut colors: Vec<Rgb<F>> = image.samples.iter().map(|sample| sample.beauty).collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1i64 << iteration;
+            let sigma_color = self.sigma_color / (step as F);
+            let mut filtered = Vec::with_capacity(colors.len());
+
+            for y in 0..height {
+                for x in 0..width {
+                    let index = (y * width + x) as usize;
+                    let center = &image.samples[index];
+                    let center_color = &colors[index];
+                    let mut sum = [<F as Zero>::zero(); 3];
+                    let mut weight_sum = <F as Zero>::zero();
+
+                    for (kernel_y, kernel_weight_y) in KERNEL.iter().enumerate() {
+                        let other_y = y + (kernel_y as i64 - 2) * step;
+
+                        if other_y < 0 || other_y >= height {
+                            continue;
+                        }
+
+                        for (kernel_x, kernel_weight_x) in KERNEL.iter().enumerate() {
+                            let other_x = x + (kernel_x as i64 - 2) * step;
+
+                            if other_x < 0 || other_x >= width {
+                                continue;
+                            }
+
+                            let other_index = (other_y * width + other_x) as usize;
+                            let other_color = &colors[other_index];
+                            let weight = kernel_weight_x * kernel_weight_y
+                                * self.edge_weight(center, center_color,
+                                                   &image.samples[other_index], other_color,
+                                                   sigma_color, step as F);
+
+                            for (channel, value) in color_array(other_color).iter().enumerate() {
+                                sum[channel] += value * weight;
+                            }
+
+                            weight_sum += weight;
+                        }
+                    }
+
+                    // The center pixel always has a positive weight
+                    filtered.push(Rgb::new(sum[0] / weight_sum,
+                                           sum[1] / weight_sum,
+                                           sum[2] / weight_sum));
+                }
+            }
+
+            colors = filtered;
+        }
+
+        colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use na::ApproxEq;
+    use palette::Rgb;
+    use universe::aov::AovImage;
+    use universe::aov::AovLayer;
+    use universe::aov::AovSample;
+    use super::*;
+
+    fn image(colors: &[F], albedos: &[F]) -> AovImage {
+        AovImage {
+            width: colors.len() as u32,
+            height: 1,
+            layers: vec![AovLayer::Beauty],
+            samples: colors.iter()
+                .zip(albedos.iter())
+                .map(|(color, albedo)| AovSample {
+                    beauty: Rgb::new(*color, *color, *color),
+                    albedo: Rgb::new(*albedo, *albedo, *albedo),
+                    ..AovSample::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn smooths_noise() {
+        let image = image(&[0.4, 0.6, 0.4, 0.6, 0.4, 0.6], &[0.5; 6]);
+        let colors = Denoiser::new().denoise(&image);
+
+        // The deviation of the input is 0.1
+        for color in &colors {
+            assert!((color.red - 0.5).abs() < 0.08);
+        }
+    }
+
+    #[test]
+    fn keeps_albedo_edges() {
+        let image = image(&[0.0, 0.0, 0.0, 1.0, 1.0, 1.0], &[0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+        let colors = Denoiser::new().denoise(&image);
+
+        assert!(colors[2].red.approx_eq(&0.0));
+        assert!(colors[3].red.approx_eq(&1.0));
+    }
+}
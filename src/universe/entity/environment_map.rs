@@ -0,0 +1,141 @@
+use ::F;
+use std::marker::PhantomData;
+use palette::Rgba;
+use rand::Rng;
+use rand::SeedableRng;
+use rand::XorShiftRng;
+use na::BaseFloat;
+use na::Dimension;
+use na::Dot;
+use na::Norm;
+use na::Repeat;
+use num::NumCast;
+use num::Zero;
+use num::One;
+use universe::entity::shape::TracingContext;
+use universe::entity::surface::MappedTexture;
+use universe::entity::surface::SurfaceColorProvider;
+use util::CustomPoint;
+use util::CustomVector;
+
+/// Computes the color of the light coming from the given direction, which is normalized.
+pub type EnvironmentSampler<V> = (Fn(&V) -> Rgba<F>) + Send + Sync;
+
+/// A background of a universe, the colors are looked up by the direction of the ray.
+pub struct EnvironmentMap<P: CustomPoint<V>, V: CustomVector<P>> {
+    pub sampler: Box<EnvironmentSampler<V>>,
+    marker_point: PhantomData<P>,
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> EnvironmentMap<P, V> {
+    pub fn new(sampler: Box<EnvironmentSampler<V>>) -> Self {
+        EnvironmentMap {
+            sampler: sampler,
+            marker_point: PhantomData,
+        }
+    }
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> MappedTexture<P, V> for EnvironmentMap<P, V> {
+    fn get_color(&self, point: &P) -> Rgba<F> {
+        (self.sampler)(&point.as_vector().normalize())
+    }
+}
+
+/// Rotates the environment by the angle in the plane given by two vectors,
+/// from the first one towards the second one.
+pub fn environment_rotate<P: CustomPoint<V>, V: CustomVector<P>>
+    (sampler: Box<EnvironmentSampler<V>>, from: V, to: V, angle: F)
+     -> Box<EnvironmentSampler<V>> {
+    Box::new(move |direction: &V| {
+        let mut data = [*direction];
+
+        from.general_rotation(&to, -angle, &mut data);
+
+        sampler(&data[0])
+    })
+}
+
+/// Multiplies the colors of the environment, useful to adjust the brightness of HDR images.
+pub fn environment_intensity<V>(sampler: Box<EnvironmentSampler<V>>, intensity: F)
+        -> Box<EnvironmentSampler<V>> {
+    Box::new(move |direction: &V| {
+        let color = sampler(direction);
+
+        Rgba::new(color.color.red * intensity,
+                  color.color.green * intensity,
+                  color.color.blue * intensity,
+                  color.alpha)
+    })
+}
+
+/// The seed of the directions sampling the environment for image-based lighting, fixed so that
+/// the same scene is always rendered the same
+const ILLUMINATION_SEED: [u32; 4] = [0x193a_6754, 0xa8a7_d469, 0x9783_0e05, 0x113b_a7bb];
+
+/// Generates a uniformly distributed direction in any number of dimensions.
+fn random_direction<P: CustomPoint<V>, V: CustomVector<P>, R: Rng>(rng: &mut R) -> V {
+    let dimension = <V as Dimension>::dimension(None);
+
+    loop {
+        let mut result = <V as Repeat<F>>::repeat(<F as Zero>::zero());
+
+        // Normally distributed coordinates given by the Box-Muller transform
+        for axis in 0..dimension {
+            let uniform_a: F = <F as One>::one() - rng.gen::<F>();
+            let uniform_b: F = rng.gen::<F>();
+
+            result[axis] = (<F as NumCast>::from(-2.0).unwrap() * uniform_a.ln()).sqrt()
+                * (<F as BaseFloat>::two_pi() * uniform_b).cos();
+        }
+
+        if result.norm() > <F as Zero>::zero() {
+            return result.normalize();
+        }
+    }
+}
+
+/// Image-based diffuse lighting -- the albedo is multiplied by the cosine-weighted average
+/// of the environment over the hemisphere facing the normal.
+/// The environment is sampled once in the given number of seeded random directions, when the
+/// provider is created. A uniformly colored environment yields the albedo itself.
+pub fn surface_color_illumination_environment<P: CustomPoint<V>, V: CustomVector<P>>
+    (sampler: Box<EnvironmentSampler<V>>, albedo: Rgba<F>, samples: u32)
+     -> Box<SurfaceColorProvider<P, V>> {
+    let mut rng = XorShiftRng::from_seed(ILLUMINATION_SEED);
+    let samples: Vec<(V, Rgba<F>)> = (0..samples)
+        .map(|_| {
+            let direction = random_direction::<P, V, _>(&mut rng);
+            let color = sampler(&direction);
+
+            (direction, color)
+        })
+        .collect();
+
+    Box::new(move |context: &TracingContext<P, V>| {
+        let normal = context.intersection_normal_closer;
+        let mut sum: [F; 3] = [<F as Zero>::zero(); 3];
+        let mut weight_sum = <F as Zero>::zero();
+
+        for &(ref direction, ref color) in samples.iter() {
+            let weight = direction.dot(&normal);
+
+            if weight > <F as Zero>::zero() {
+                sum[0] += color.color.red * weight;
+                sum[1] += color.color.green * weight;
+                sum[2] += color.color.blue * weight;
+                weight_sum += weight;
+            }
+        }
+
+        if weight_sum <= <F as Zero>::zero() {
+            return Rgba::new(<F as Zero>::zero(), <F as Zero>::zero(), <F as Zero>::zero(),
+                             albedo.alpha);
+        }
+
+        Rgba::new(albedo.color.red * sum[0] / weight_sum,
+                  albedo.color.green * sum[1] / weight_sum,
+                  albedo.color.blue * sum[2] / weight_sum,
+                  albedo.alpha)
+    })
+}
@@ -0,0 +1,332 @@
+use std::cmp;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use flate2::read::ZlibDecoder;
+
+pub const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+/// Single-part scanline image, without any extended attributes
+pub const EXR_VERSION: [u8; 4] = [2, 0, 0, 0];
+pub const EXR_PIXEL_TYPE_UINT: i32 = 0;
+pub const EXR_PIXEL_TYPE_HALF: i32 = 1;
+pub const EXR_PIXEL_TYPE_FLOAT: i32 = 2;
+/// The flags of the version field marking tiled, deep and multi-part files
+pub const EXR_VERSION_UNSUPPORTED_FLAGS: u8 = 0x02 | 0x08 | 0x10;
+pub const EXR_COMPRESSION_NONE: u8 = 0;
+/// Zlib compression of a single scanline per chunk
+pub const EXR_COMPRESSION_ZIPS: u8 = 2;
+/// Zlib compression of 16 scanlines per chunk
+pub const EXR_COMPRESSION_ZIP: u8 = 3;
+pub const EXR_LINE_ORDER_INCREASING_Y: u8 = 0;
+
+/// Whether the path has the `.exr` extension of OpenEXR images
+pub fn is_exr(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension.to_string_lossy().to_lowercase() == "exr")
+}
+
+fn invalid_exr(description: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid OpenEXR image: {}", description))
+}
+
+/// A cursor over the bytes of an OpenEXR file
+struct ExrReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ExrReader<'a> {
+    fn bytes(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.position + length > self.data.len() {
+            return Err(invalid_exr("unexpected end of file"));
+        }
+
+        let bytes = &self.data[self.position..self.position + length];
+
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let bytes = self.bytes(2)?;
+
+        Ok(u16::from(bytes[0]) | u16::from(bytes[1]) << 8)
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let bytes = self.bytes(4)?;
+
+        Ok(u32::from(bytes[0]) | u32::from(bytes[1]) << 8
+           | u32::from(bytes[2]) << 16 | u32::from(bytes[3]) << 24)
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let low = self.read_u32()?;
+        let high = self.read_u32()?;
+
+        Ok(u64::from(low) | u64::from(high) << 32)
+    }
+
+    /// Reads a null-terminated string
+    fn read_name(&mut self) -> io::Result<String> {
+        let length = self.data.get(self.position..)
+            .unwrap_or(&[])
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| invalid_exr("unterminated name"))?;
+        let name = String::from_utf8_lossy(self.bytes(length)?).into_owned();
+
+        self.position += 1;
+        Ok(name)
+    }
+}
+
+/// Converts a 16-bit floating point number to `f32`
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let mantissa = bits & 0x3ff;
+
+    match exponent {
+        0 => sign * f32::from(mantissa) * 2f32.powi(-24),
+        0x1f if mantissa == 0 => sign * ::std::f32::INFINITY,
+        0x1f => ::std::f32::NAN,
+        _ => sign * (1.0 + f32::from(mantissa) / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// The size of a single value of a channel in bytes
+fn pixel_type_size(pixel_type: i32) -> io::Result<usize> {
+    match pixel_type {
+        EXR_PIXEL_TYPE_UINT | EXR_PIXEL_TYPE_FLOAT => Ok(4),
+        EXR_PIXEL_TYPE_HALF => Ok(2),
+        _ => Err(invalid_exr("unknown pixel type")),
+    }
+}
+
+/// Inflates the pixel data of a ZIP or ZIPS chunk, then reverts the delta predictor
+/// and the split of the even and odd bytes applied before the compression.
+fn decompress_zip(compressed: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut predicted = Vec::with_capacity(size);
+
+    ZlibDecoder::new(compressed).read_to_end(&mut predicted)
+        .map_err(|_| invalid_exr("corrupted ZIP compressed data"))?;
+
+    for index in 1..predicted.len() {
+        predicted[index] = predicted[index - 1].wrapping_add(predicted[index]).wrapping_sub(128);
+    }
+
+    let (even, odd) = predicted.split_at((predicted.len() + 1) / 2);
+    let mut data = Vec::with_capacity(predicted.len());
+
+    for index in 0..even.len() {
+        data.push(even[index]);
+
+        if let Some(byte) = odd.get(index) {
+            data.push(*byte);
+        }
+    }
+
+    Ok(data)
+}
+
+/// An image read from an OpenEXR file, with the pixels in rows starting at the top.
+pub struct ExrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+/// Reads the `R`, `G`, `B` and `A` channels of an uncompressed, ZIP or ZIPS compressed
+/// single-part scanline OpenEXR image, such as the ones written by `write_exr`. Missing colors
+/// are black and a missing alpha is opaque. Other compressions, tiled and deep images are
+/// rejected with an error.
+pub fn read_exr<R: Read>(reader: &mut R) -> io::Result<ExrImage> {
+    let mut data = Vec::new();
+
+    reader.read_to_end(&mut data)?;
+
+    let mut reader = ExrReader { data: &data, position: 0 };
+
+    if reader.bytes(4)? != &EXR_MAGIC[..] {
+        return Err(invalid_exr("not an OpenEXR file"));
+    }
+
+    let version = reader.bytes(4)?;
+
+    if version[0] != EXR_VERSION[0] || version[1] & EXR_VERSION_UNSUPPORTED_FLAGS != 0 {
+        return Err(invalid_exr("only single-part scanline images are supported"));
+    }
+
+    // The name, pixel type and index among the RGBA components of each channel
+    let mut channels: Vec<(String, i32, Option<usize>)> = Vec::new();
+    let mut window: Option<(i32, i32, i32, i32)> = None;
+    let mut compression = EXR_COMPRESSION_NONE;
+
+    loop {
+        let name = reader.read_name()?;
+
+        if name.is_empty() {
+            break;
+        }
+
+        let kind = reader.read_name()?;
+        let size = reader.read_i32()?;
+
+        if size < 0 {
+            return Err(invalid_exr("negative attribute size"));
+        }
+
+        let end = reader.position + size as usize;
+
+        match (&name[..], &kind[..]) {
+            ("channels", "chlist") => {
+                loop {
+                    let channel_name = reader.read_name()?;
+
+                    if channel_name.is_empty() {
+                        break;
+                    }
+
+                    let pixel_type = reader.read_i32()?;
+
+                    // pLinear and reserved bytes
+                    reader.bytes(4)?;
+
+                    if reader.read_i32()? != 1 || reader.read_i32()? != 1 {
+                        return Err(invalid_exr("subsampled channels are not supported"));
+                    }
+
+                    let component = ["R", "G", "B", "A"].iter()
+                        .position(|component| *component == channel_name);
+
+                    channels.push((channel_name, pixel_type, component));
+                }
+            }
+            ("compression", "compression") => {
+                compression = reader.read_u8()?;
+            }
+            ("dataWindow", "box2i") => {
+                window = Some((reader.read_i32()?, reader.read_i32()?,
+                               reader.read_i32()?, reader.read_i32()?));
+            }
+            _ => (),
+        }
+
+        reader.position = end;
+    }
+
+    let (min_x, min_y, max_x, max_y) = window.ok_or_else(|| invalid_exr("missing data window"))?;
+
+    if max_x < min_x || max_y < min_y {
+        return Err(invalid_exr("empty data window"));
+    }
+
+    if !channels.iter().any(|channel| channel.2.map_or(false, |component| component < 3)) {
+        return Err(invalid_exr("no R, G or B channel"));
+    }
+
+    let lines_per_chunk = match compression {
+        EXR_COMPRESSION_NONE | EXR_COMPRESSION_ZIPS => 1,
+        EXR_COMPRESSION_ZIP => 16,
+        _ => {
+            return Err(invalid_exr("only uncompressed, ZIP and ZIPS compressed images are \
+                                    supported"));
+        }
+    };
+    let width = (max_x - min_x + 1) as u32;
+    let height = (max_y - min_y + 1) as u32;
+    let mut line_size = 0;
+
+    for &(_, pixel_type, _) in &channels {
+        line_size += pixel_type_size(pixel_type)? * width as usize;
+    }
+
+    let mut pixels = vec![[0.0, 0.0, 0.0, 1.0]; width as usize * height as usize];
+    let chunk_count = (height + lines_per_chunk - 1) / lines_per_chunk;
+    let mut offsets = Vec::with_capacity(chunk_count as usize);
+
+    for _ in 0..chunk_count {
+        offsets.push(reader.read_u64()?);
+    }
+
+    for offset in offsets {
+        reader.position = offset as usize;
+
+        let first_y = reader.read_i32()? - min_y;
+
+        if first_y < 0 || first_y >= height as i32 {
+            return Err(invalid_exr("scanline outside of the data window"));
+        }
+
+        let lines = cmp::min(lines_per_chunk, height - first_y as u32) as usize;
+        let size = reader.read_i32()?;
+
+        if size < 0 {
+            return Err(invalid_exr("negative chunk size"));
+        }
+
+        let stored = reader.bytes(size as usize)?;
+        // Chunks that would not get any smaller are stored uncompressed
+        let data = if stored.len() == lines * line_size {
+            stored.to_vec()
+        } else if compression == EXR_COMPRESSION_NONE {
+            return Err(invalid_exr("wrong chunk size"));
+        } else {
+            decompress_zip(stored, lines * line_size)?
+        };
+
+        if data.len() != lines * line_size {
+            return Err(invalid_exr("wrong chunk size"));
+        }
+
+        let mut chunk = ExrReader { data: &data, position: 0 };
+
+        // Each scanline stores all the values of one channel after another
+        for line in 0..lines {
+            let row = (first_y as usize + line) * width as usize;
+
+            for &(_, pixel_type, component) in &channels {
+                for x in 0..width as usize {
+                    let value = match pixel_type {
+                        EXR_PIXEL_TYPE_UINT => chunk.read_u32()? as f32,
+                        EXR_PIXEL_TYPE_HALF => half_to_f32(chunk.read_u16()?),
+                        EXR_PIXEL_TYPE_FLOAT => f32::from_bits(chunk.read_u32()?),
+                        _ => return Err(invalid_exr("unknown pixel type")),
+                    };
+
+                    if let Some(component) = component {
+                        pixels[row + x][component] = value;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(ExrImage {
+        width: width,
+        height: height,
+        pixels: pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert!(half_to_f32(0x7c00).is_infinite());
+    }
+}
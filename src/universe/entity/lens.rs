@@ -0,0 +1,339 @@
+use ::F;
+use glium::glutin::VirtualKeyCode;
+use na;
+use na::BaseFloat;
+use num::One;
+use num::Zero;
+use num::traits::NumCast;
+use palette::Rgb;
+use std::collections::HashSet;
+use util::CustomPoint;
+use util::CustomVector;
+
+/// How quickly the focal distance follows the autofocus, the fraction of the remaining
+/// distance pulled per second
+const FOCUS_PULL_SPEED: F = 4.0;
+/// The lowest cosine between a ray and the forward direction focused on the sharp plane,
+/// the focus of the rays looking further sideways is blended towards a sphere, which it
+/// reaches at the right angle
+const FOCUS_PLANE_MIN_DEPTH: F = 0.3;
+
+/// The point of the unit cube the lens is sampled at, the third coordinate is only used by
+/// the apertures spanned by three axes
+pub type LensSample = (F, F, F);
+
+/// The sample passing through the center of the lens
+pub const LENS_CENTER: LensSample = (0.5, 0.5, 0.5);
+
+/// The shape of the opening of the lens, which is also the shape of the out of focus highlights
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aperture {
+    Circle,
+    /// A regular polygon made of the given number of blades
+    Polygon(u32),
+}
+
+/// A thin lens focusing the rays of a camera, the points at the focal distance along the
+/// forward direction are sharp and the others are blurred by the size of the aperture.
+/// The aperture lies in the plane of the right and up directions of the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThinLens {
+    /// The radius of the aperture, `0` is a pinhole
+    pub radius: F,
+    pub aperture: Aperture,
+    /// The distance of the sharp plane along the forward direction
+    pub focal_distance: F,
+    /// Whether the focal distance is pulled towards the surface in the center of the screen
+    pub autofocus: bool,
+}
+
+impl ThinLens {
+    pub fn new(radius: F, focal_distance: F) -> Self {
+        ThinLens {
+            radius: radius,
+            aperture: Aperture::Circle,
+            focal_distance: focal_distance,
+            autofocus: false,
+        }
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    pub fn with_autofocus(mut self, autofocus: bool) -> Self {
+        self.autofocus = autofocus;
+        self
+    }
+
+    /// Maps the sample from the unit cube to the point of the aperture spanned by the given
+    /// number of axes. Across three axes, e.g. in 4D, the circle becomes a ball and the polygon
+    /// a prism along the third axis.
+    pub fn aperture_point(&self, (u, v, w): LensSample, axes: usize) -> [F; 3] {
+        let (x, y, z) = match self.aperture {
+            Aperture::Circle if axes >= 3 => uniform_ball(u, v, w),
+            Aperture::Circle => {
+                let (x, y) = concentric_disk(u, v);
+
+                (x, y, <F as Zero>::zero())
+            }
+            Aperture::Polygon(blades) => {
+                let (x, y) = polygon(u, v, blades);
+                let z = if axes >= 3 { w * 2.0 - 1.0 } else { <F as Zero>::zero() };
+
+                (x, y, z)
+            }
+        };
+
+        [x * self.radius, y * self.radius, z * self.radius]
+    }
+
+    /// Bends the pinhole ray so that it passes through the sampled point of the aperture and
+    /// meets the pinhole ray at the focal distance. The aperture is spanned by the given axes
+    /// perpendicular to the forward direction, two in 3D and three in 4D.
+    pub fn focus<P: CustomPoint<V>, V: CustomVector<P>>(&self,
+                                                       point: &P,
+                                                       direction: &V,
+                                                       forward: &V,
+                                                       axes: &[V],
+                                                       sample: LensSample)
+                                                       -> (P, V) {
+        let distance = self.focus_distance(na::dot(direction, forward));
+        let focus = (*direction * distance).translate(point);
+        let coordinates = self.aperture_point(sample, axes.len());
+        let offset = axes.iter()
+            .zip(coordinates.iter())
+            .fold(na::zero::<V>(), |offset, (axis, coordinate)| offset + *axis * *coordinate);
+        let origin = offset.translate(point);
+
+        (origin, (focus - origin).normalize())
+    }
+
+    /// The distance along the ray with the given cosine to the forward direction to the point
+    /// it is focused on. Rays looking sideways or behind are focused on a sphere of the focal
+    /// distance, blended smoothly with the sharp plane to leave no seam in panoramic frames.
+    fn focus_distance(&self, depth: F) -> F {
+        let blend = (depth / FOCUS_PLANE_MIN_DEPTH).max(<F as Zero>::zero()).min(<F as One>::one());
+        let blend = blend * blend * (3.0 - 2.0 * blend);
+        let blended_depth = <F as One>::one() + (depth - <F as One>::one()) * blend;
+
+        self.focal_distance / blended_depth
+    }
+
+    /// Pulls the focus towards the distance of the surface in the center of the screen,
+    /// or moves it with [`[`] and [`]`] without the autofocus.
+    pub fn update(&mut self,
+                  delta_seconds: F,
+                  pressed_keys: &HashSet<VirtualKeyCode>,
+                  center_distance: Option<F>) {
+        if self.autofocus {
+            if let Some(target) = center_distance {
+                let pull = (delta_seconds * FOCUS_PULL_SPEED).min(<F as One>::one());
+
+                self.focal_distance += (target - self.focal_distance) * pull;
+            }
+        } else {
+            let scale = <F as One>::one() + delta_seconds;
+
+            if pressed_keys.contains(&VirtualKeyCode::RBracket) {
+                self.focal_distance *= scale;
+            }
+
+            if pressed_keys.contains(&VirtualKeyCode::LBracket) {
+                self.focal_distance /= scale;
+            }
+        }
+    }
+}
+
+/// Shirley's concentric mapping of the unit square onto the unit disk, which keeps
+/// the samples evenly spread
+fn concentric_disk(u: F, v: F) -> (F, F) {
+    let x = u * 2.0 - 1.0;
+    let y = v * 2.0 - 1.0;
+
+    if x == <F as Zero>::zero() && y == <F as Zero>::zero() {
+        return (x, y);
+    }
+
+    let (radius, angle) = if x.abs() > y.abs() {
+        (x, <F as BaseFloat>::frac_pi_4() * (y / x))
+    } else {
+        (y, <F as BaseFloat>::frac_pi_2() - <F as BaseFloat>::frac_pi_4() * (x / y))
+    };
+
+    (radius * angle.cos(), radius * angle.sin())
+}
+
+/// Maps the unit square onto the regular polygon with the given number of blades inscribed
+/// in the unit circle
+fn polygon(u: F, v: F, blades: u32) -> (F, F) {
+    let blades = blades.max(3);
+    let blades_float: F = <F as NumCast>::from(blades).unwrap();
+    let blade = ((u * blades_float) as u32).min(blades - 1);
+    let blade_float: F = <F as NumCast>::from(blade).unwrap();
+    let u = u * blades_float - blade_float;
+    let angle = <F as BaseFloat>::two_pi() / blades_float;
+    let (from, to) = (angle * blade_float, angle * (blade_float + 1.0));
+    // Uniformly samples the triangle between the center and the edge of the blade
+    let scale = u.sqrt();
+    let (a, b) = (scale * (<F as One>::one() - v), scale * v);
+
+    (a * from.cos() + b * to.cos(), a * from.sin() + b * to.sin())
+}
+
+/// Maps the unit cube onto the unit ball, keeping the samples uniformly spread
+fn uniform_ball(u: F, v: F, w: F) -> (F, F, F) {
+    let z = v * 2.0 - 1.0;
+    let ring = (<F as One>::one() - z * z).max(<F as Zero>::zero()).sqrt();
+    let angle = u * <F as BaseFloat>::two_pi();
+    let radius = w.cbrt();
+
+    (radius * ring * angle.cos(), radius * ring * angle.sin(), radius * z)
+}
+
+/// The radical inverse of the index in the base, the coordinates of the Halton sequence
+fn radical_inverse(mut index: u32, base: u32) -> F {
+    let base_float: F = <F as NumCast>::from(base).unwrap();
+    let mut result = <F as Zero>::zero();
+    let mut scale = <F as One>::one() / base_float;
+
+    while index > 0 {
+        result += <F as NumCast>::from(index % base).unwrap() * scale;
+        index /= base;
+        scale /= base_float;
+    }
+
+    result
+}
+
+/// Hashes the pixel to a point of the unit cube
+fn pixel_offset((x, y): (i32, i32)) -> LensSample {
+    let mut hash: u64 = ((x as u32 as u64) << 32 | y as u32 as u64)
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let mut next = || {
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        hash ^= hash >> 31;
+
+        <F as NumCast>::from(hash >> 11).unwrap() / <F as NumCast>::from(1u64 << 53).unwrap()
+    };
+    let (u, v) = (next(), next());
+
+    (u, v, next())
+}
+
+/// The point of the unit cube the lens is sampled at by the sample of the pixel. The samples
+/// are spread by the Halton sequence and shifted by the hash of the pixel, a Cranley-Patterson
+/// rotation, so that neighbouring pixels sample different points of the lens and the defocus
+/// converges to a blur instead of copies of the image.
+pub fn lens_sample(index: u32, pixel: (i32, i32)) -> LensSample {
+    let (offset_u, offset_v, offset_w) = pixel_offset(pixel);
+    let u = radical_inverse(index + 1, 2) + offset_u;
+    let v = radical_inverse(index + 1, 3) + offset_v;
+    let w = radical_inverse(index + 1, 5) + offset_w;
+
+    (u.fract(), v.fract(), w.fract())
+}
+
+/// Averages the colors traced through the given number of points of the lens of the pixel
+pub fn integrate<T: Fn(LensSample) -> Rgb<F>>(samples: u32, pixel: (i32, i32), trace: T) -> Rgb<F> {
+    let samples = samples.max(1);
+    let mut sum: [F; 3] = [<F as Zero>::zero(); 3];
+
+    for index in 0..samples {
+        let color = trace(lens_sample(index, pixel));
+
+        sum[0] += color.red;
+        sum[1] += color.green;
+        sum[2] += color.blue;
+    }
+
+    let samples: F = <F as NumCast>::from(samples).unwrap();
+
+    Rgb::new(sum[0] / samples, sum[1] / samples, sum[2] / samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Norm;
+    use na::Translate;
+    use universe::d3::Point3;
+    use universe::d3::Vector3;
+
+    #[test]
+    fn apertures_stay_within_radius() {
+        for aperture in &[Aperture::Circle, Aperture::Polygon(6)] {
+            let lens = ThinLens::new(0.5, 10.0).with_aperture(*aperture);
+
+            for index in 0..64 {
+                let point = lens.aperture_point(lens_sample(index, (3, 7)), 2);
+
+                assert!((point[0] * point[0] + point[1] * point[1]).sqrt() <= 0.5 + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn rays_meet_at_focal_distance() {
+        let lens = ThinLens::new(0.5, 10.0);
+        let location = Point3::new(1.0, 2.0, 3.0);
+        let direction = Vector3::new(1.0, 0.2, 0.0).normalize();
+        let forward = Vector3::x();
+        let sharp = (direction * (10.0 / direction.x)).translate(&location);
+
+        for index in 0..8 {
+            let (origin, vector) = lens.focus(&location, &direction, &forward,
+                                              &[-Vector3::y(), Vector3::z()],
+                                              lens_sample(index, (3, 7)));
+            let distance = (sharp.x - origin.x) / vector.x;
+            let hit = (vector * distance).translate(&origin);
+
+            assert!(na::distance(&hit, &sharp) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn focus_blends_from_plane_to_sphere() {
+        let lens = ThinLens::new(0.5, 10.0);
+        let mut previous = lens.focus_distance(-1.0);
+
+        assert!((lens.focus_distance(0.5) - 20.0).abs() < 1e-9);
+        assert!((lens.focus_distance(0.0) - 10.0).abs() < 1e-9);
+        assert!((previous - 10.0).abs() < 1e-9);
+
+        for index in 1..2001 {
+            let distance = lens.focus_distance(index as F / 1000.0 - 1.0);
+
+            assert!((distance - previous).abs() < 0.5);
+            previous = distance;
+        }
+    }
+
+    #[test]
+    fn pixels_sample_different_points() {
+        assert_eq!(lens_sample(2, (10, 20)), lens_sample(2, (10, 20)));
+        assert!(lens_sample(2, (10, 20)) != lens_sample(2, (11, 20)));
+    }
+
+    #[test]
+    fn ball_apertures_span_three_axes() {
+        let lens = ThinLens::new(0.5, 10.0);
+        let mut extent: [F; 3] = [0.0; 3];
+
+        for index in 0..256 {
+            let point = lens.aperture_point(lens_sample(index, (0, 0)), 3);
+            let (x, y, z) = (point[0], point[1], point[2]);
+
+            assert!((x * x + y * y + z * z).sqrt() <= 0.5 + 1e-9);
+
+            for axis in 0..3 {
+                extent[axis] = extent[axis].max(point[axis].abs());
+            }
+        }
+
+        assert!(extent.iter().all(|extent| *extent > 0.4));
+    }
+}
@@ -4,13 +4,22 @@ use std::fmt::Display;
 use std::any::TypeId;
 use std::any::Any;
 use std::collections::HashMap;
+use std::time::Duration;
+use palette::Rgb;
+use palette::Rgba;
+use noise::{Perlin, NoiseModule, Seedable};
 use util::CustomFloat;
 use util::CustomPoint;
 use util::CustomVector;
 use util::HasId;
 use na::Dimension;
+use na::Norm;
+use na::Dot;
+use na::Cast;
 use meval::{Expr, Context as MevalContext};
 use num::NumCast;
+use num::Zero;
+use num::One;
 use ::F;
 
 pub trait Material<P: CustomPoint<V>, V: CustomVector<P>>
@@ -19,6 +28,20 @@ pub trait Material<P: CustomPoint<V>, V: CustomVector<P>>
     fn enter(&self, location: &P, direction: &mut V);
     fn exit(&self, location: &P, direction: &mut V);
     fn trace_path(&self, location: &P, direction: &V, distance: &F) -> (P, V);
+
+    /// Modifies the color of the light travelling through this material along a segment
+    /// of the ray. The segment starts at `location` and continues in `direction` for
+    /// `distance`, or indefinitely, if the ray did not hit anything.
+    #[allow(unused_variables)]
+    fn attenuate(&self,
+                 time: &Duration,
+                 location: &P,
+                 direction: &V,
+                 distance: Option<F>,
+                 color: Rgba<F>)
+                 -> Rgba<F> {
+        color
+    }
 }
 
 #[macro_export]
@@ -161,3 +184,268 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> LinearSpace<P, V> {
         }
     }
 }
+
+/// Computes the density of a participating medium at the given location.
+pub type DensityProvider<P> = (Fn(&P, &Duration) -> F) + Send + Sync;
+
+#[allow(unused_variables)]
+pub fn density_uniform<P: CustomPoint<V>, V: CustomVector<P>>(density: F) -> Box<DensityProvider<P>> {
+    Box::new(move |location: &P, time: &Duration| density)
+}
+
+/// The number of coordinates passed to the noise functions
+pub const NOISE_DIMENSION: usize = 4;
+
+/// Scales the location by `1 / size` and, if there are fewer than four dimensions, appends
+/// the time multiplied by `speed` as the next coordinate.
+pub fn noise_coordinates<P: CustomPoint<V>, V: CustomVector<P>>(location: &P,
+                                                                time: &Duration,
+                                                                size: F,
+                                                                speed: F)
+                                                                -> [F; NOISE_DIMENSION] {
+    let time_millis: F = <F as NumCast>::from((*time * 1000).as_secs() as f64 / 1000.0).unwrap();
+    let mut coordinates: [F; NOISE_DIMENSION] = [<F as Zero>::zero(); NOISE_DIMENSION];
+    let dimension = <P as Dimension>::dimension(None);
+
+    for (index, coordinate) in coordinates.iter_mut().enumerate() {
+        if index < dimension {
+            *coordinate = location[index] / size;
+        } else if index == dimension {
+            *coordinate = time_millis * speed;
+        }
+    }
+
+    coordinates
+}
+
+/// Samples Perlin noise at the `noise_coordinates` of the location.
+pub fn density_perlin<P: CustomPoint<V>, V: CustomVector<P>>(seed: usize,
+                                                             size: F,
+                                                             speed: F,
+                                                             density: F)
+                                                             -> Box<DensityProvider<P>> {
+    let perlin = Perlin::new().set_seed(seed);
+
+    Box::new(move |location: &P, time: &Duration| {
+        let value: F = perlin.get(noise_coordinates(location, time, size, speed));
+        let half: F = <F as NumCast>::from(0.5).unwrap();
+
+        density * (value * half + half).max(<F as Zero>::zero())
+    })
+}
+
+/// A material absorbing and scattering the light passing through it, such as fog or smoke.
+/// Absorption follows the Beer-Lambert law, single scattering of a directional light is
+/// approximated by ray marching. Occlusion of the light by other entities is not considered.
+pub struct ParticipatingMedium<P: CustomPoint<V>, V: CustomVector<P>> {
+    /// The absorption coefficients of each color channel per unit of density and distance
+    pub absorption: Rgb<F>,
+    /// The scattering coefficients of each color channel per unit of density and distance
+    pub scattering: Rgb<F>,
+    pub density: Box<DensityProvider<P>>,
+    /// The direction the light is travelling in, if the medium is lit
+    pub light_direction: Option<V>,
+    pub light_color: Rgb<F>,
+    /// The Henyey-Greenstein asymmetry parameter, `0` is isotropic,
+    /// positive values scatter forward, negative values backward
+    pub anisotropy: F,
+    pub steps: u32,
+    /// The distance after which the medium is no longer sampled
+    pub max_distance: F,
+}
+
+material!(ParticipatingMedium<P: CustomPoint<V>, V: CustomVector<P>>);
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> ParticipatingMedium<P, V> {
+    pub fn new(absorption: Rgb<F>, scattering: Rgb<F>, density: Box<DensityProvider<P>>) -> Self {
+        ParticipatingMedium {
+            absorption: absorption,
+            scattering: scattering,
+            density: density,
+            light_direction: None,
+            light_color: Rgb::new(<F as Zero>::zero(), <F as Zero>::zero(), <F as Zero>::zero()),
+            anisotropy: <F as Zero>::zero(),
+            steps: 16,
+            max_distance: <F as NumCast>::from(1000.0).unwrap(),
+        }
+    }
+
+    pub fn with_light(mut self, light_direction: V, light_color: Rgb<F>) -> Self {
+        self.light_direction = Some(light_direction);
+        self.light_color = light_color;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: F) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn with_steps(mut self, steps: u32) -> Self {
+        assert!(steps > 0, "At least one step is required to sample the medium.");
+        self.steps = steps;
+        self
+    }
+
+    pub fn with_max_distance(mut self, max_distance: F) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    /// The Henyey-Greenstein phase function, scaled so that isotropic scattering equals `1`.
+    fn phase(&self, direction: &V) -> F {
+        let light_direction = if let Some(light_direction) = self.light_direction {
+            light_direction
+        } else {
+            return <F as Zero>::zero();
+        };
+
+        // The light reaching the observer travels against the direction of the ray
+        let cos_theta = light_direction.normalize().dot(&-direction.normalize());
+        let g = self.anisotropy;
+        let one = <F as One>::one();
+        let denominator = (one + g * g - (one + one) * g * cos_theta).powf(Cast::from(1.5));
+
+        (one - g * g) / denominator
+    }
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> Debug for ParticipatingMedium<P, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ParticipatingMedium")
+            .field("absorption", &self.absorption)
+            .field("scattering", &self.scattering)
+            .field("light_direction", &self.light_direction)
+            .field("light_color", &self.light_color)
+            .field("anisotropy", &self.anisotropy)
+            .field("steps", &self.steps)
+            .field("max_distance", &self.max_distance)
+            .finish()
+    }
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> Material<P, V> for ParticipatingMedium<P, V> {
+    #[allow(unused_variables)]
+    fn enter(&self, location: &P, direction: &mut V) {
+        // Empty
+    }
+
+    #[allow(unused_variables)]
+    fn exit(&self, location: &P, direction: &mut V) {
+        // Empty
+    }
+
+    fn trace_path(&self, location: &P, direction: &V, distance: &F) -> (P, V) {
+        (*location + *direction * *distance, *direction)
+    }
+
+    fn attenuate(&self,
+                 time: &Duration,
+                 location: &P,
+                 direction: &V,
+                 distance: Option<F>,
+                 color: Rgba<F>)
+                 -> Rgba<F> {
+        let direction_norm = direction.norm();
+
+        if direction_norm <= <F as Zero>::zero() {
+            return color;
+        }
+
+        // `distance` is measured in multiples of the direction vector
+        let length = distance.map_or(self.max_distance, |distance| {
+            (distance * direction_norm).min(self.max_distance)
+        });
+        let step_length = length / <F as NumCast>::from(self.steps).unwrap();
+        let step = *direction * (step_length / direction_norm);
+        let half: F = <F as NumCast>::from(0.5).unwrap();
+        let phase = self.phase(direction);
+        let absorption = [self.absorption.red, self.absorption.green, self.absorption.blue];
+        let scattering = [self.scattering.red, self.scattering.green, self.scattering.blue];
+        let light = [self.light_color.red * phase,
+                     self.light_color.green * phase,
+                     self.light_color.blue * phase];
+        let mut transmittance: [F; 3] = [<F as One>::one(); 3];
+        let mut scattered: [F; 3] = [<F as Zero>::zero(); 3];
+
+        for index in 0..self.steps {
+            let offset = <F as NumCast>::from(index).unwrap() + half;
+            let point = *location + step * offset;
+            let density = (self.density)(&point, time).max(<F as Zero>::zero());
+
+            if density <= <F as Zero>::zero() {
+                continue;
+            }
+
+            for channel in 0..3 {
+                let extinction = density * (absorption[channel] + scattering[channel]);
+
+                if extinction <= <F as Zero>::zero() {
+                    continue;
+                }
+
+                let step_transmittance = (-extinction * step_length).exp();
+
+                // Integrate the in-scattered light over the step analytically
+                scattered[channel] += transmittance[channel] * density * scattering[channel]
+                    * light[channel] * (<F as One>::one() - step_transmittance) / extinction;
+                transmittance[channel] *= step_transmittance;
+            }
+        }
+
+        let mean_transmittance = (transmittance[0] + transmittance[1] + transmittance[2])
+            / <F as NumCast>::from(3.0).unwrap();
+
+        Rgba::new(color.color.red * transmittance[0] + scattered[0],
+                  color.color.green * transmittance[1] + scattered[1],
+                  color.color.blue * transmittance[2] + scattered[2],
+                  color.alpha + (<F as One>::one() - color.alpha) * (<F as One>::one() - mean_transmittance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use na::Point3;
+    use na::Vector3;
+    use na::ApproxEq;
+    use palette::Rgb;
+    use palette::Rgba;
+    use super::*;
+
+    #[test]
+    fn participating_medium_absorption() {
+        let medium: ParticipatingMedium<Point3<F>, Vector3<F>> = ParticipatingMedium::new(
+            Rgb::new(1.0, 0.0, 0.0),
+            Rgb::new(0.0, 0.0, 0.0),
+            density_uniform(1.0)
+        );
+        let color = medium.attenuate(&Duration::from_secs(0),
+                                     &Point3::new(0.0, 0.0, 0.0),
+                                     &Vector3::new(1.0, 0.0, 0.0),
+                                     Some((2.0 as F).ln()),
+                                     Rgba::new(1.0, 1.0, 1.0, 1.0));
+
+        assert!(color.color.red.approx_eq_eps(&0.5, &1e-5));
+        assert!(color.color.green.approx_eq_eps(&1.0, &1e-5));
+        assert!(color.color.blue.approx_eq_eps(&1.0, &1e-5));
+    }
+
+    #[test]
+    fn participating_medium_scattering() {
+        // Infinitely deep homogeneous fog converges to the color of the scattered light
+        let medium: ParticipatingMedium<Point3<F>, Vector3<F>> = ParticipatingMedium::new(
+            Rgb::new(0.0, 0.0, 0.0),
+            Rgb::new(1.0, 1.0, 1.0),
+            density_uniform(1.0)
+        ).with_light(Vector3::new(0.0, 0.0, -1.0), Rgb::new(0.5, 0.5, 0.5));
+        let color = medium.attenuate(&Duration::from_secs(0),
+                                     &Point3::new(0.0, 0.0, 0.0),
+                                     &Vector3::new(1.0, 0.0, 0.0),
+                                     None,
+                                     Rgba::new(0.0, 0.0, 0.0, 0.0));
+
+        assert!(color.color.red.approx_eq_eps(&0.5, &1e-5));
+        assert!(color.alpha.approx_eq_eps(&1.0, &1e-5));
+    }
+}
@@ -0,0 +1,199 @@
+use smallvec::SmallVec;
+use universe::entity::Traceable;
+use util::CustomPoint;
+use util::CustomVector;
+
+/// Returns `true`, if both references point to the same `Traceable`.
+pub fn same_traceable<P: CustomPoint<V>, V: CustomVector<P>>(a: &Traceable<P, V>,
+                                                              b: &Traceable<P, V>)
+                                                              -> bool {
+    a as *const _ as *const () == b as *const _ as *const ()
+}
+
+/// The media a ray is travelling through, in the order they were entered.
+/// The medium with the highest priority is the one the ray is traced in, which makes
+/// nested and overlapping volumes independent of the order the entities were declared in.
+#[derive(Clone)]
+pub struct MediumStack<'a, P: 'a + CustomPoint<V>, V: 'a + CustomVector<P>> {
+    media: SmallVec<[&'a Traceable<P, V>; 4]>,
+}
+
+impl<'a, P: CustomPoint<V>, V: CustomVector<P>> MediumStack<'a, P, V> {
+    pub fn new() -> Self {
+        MediumStack {
+            media: SmallVec::new(),
+        }
+    }
+
+    pub fn push(&mut self, medium: &'a Traceable<P, V>) {
+        self.media.push(medium);
+    }
+
+    /// Removes the most recently entered occurrence of the medium.
+    /// Returns `false`, if the medium was not present.
+    pub fn remove(&mut self, medium: &Traceable<P, V>) -> bool {
+        let position = self.media.iter()
+            .rposition(|other| same_traceable(*other, medium));
+
+        if let Some(position) = position {
+            self.media.remove(position);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn contains(&self, medium: &Traceable<P, V>) -> bool {
+        self.media.iter().any(|other| same_traceable(*other, medium))
+    }
+
+    pub fn len(&self) -> usize {
+        self.media.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.media.is_empty()
+    }
+
+    /// The medium the ray is currently travelling through -- the one with the highest priority.
+    /// Of the media with equal priorities, the most recently entered one is picked.
+    pub fn current(&self) -> Option<&'a Traceable<P, V>> {
+        let mut result: Option<&'a Traceable<P, V>> = None;
+
+        for medium in self.media.iter().rev() {
+            if result.map_or(true, |current| medium.priority() > current.priority()) {
+                result = Some(*medium);
+            }
+        }
+
+        result
+    }
+
+    /// Returns `true`, if crossing the surface of the given medium changes the medium
+    /// the ray is travelling through. Surfaces of media with lower priorities than the current
+    /// one are overshadowed and should be passed through.
+    pub fn is_boundary(&self, medium: &Traceable<P, V>) -> bool {
+        match self.current() {
+            Some(current) => same_traceable(current, medium)
+                             || medium.priority() >= current.priority(),
+            None => true,
+        }
+    }
+
+    /// Creates the stack of media on the other side of the surface of the given medium.
+    pub fn transition(&self, medium: &'a Traceable<P, V>, exiting: bool) -> Self {
+        let mut result = self.clone();
+
+        if exiting {
+            result.remove(medium);
+        } else {
+            result.push(medium);
+        }
+
+        result
+    }
+}
+
+impl<'a, P: CustomPoint<V>, V: CustomVector<P>> Default for MediumStack<'a, P, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use na::Point2;
+    use na::Vector2;
+    use universe::entity::Entity;
+    use universe::entity::Void;
+    use universe::entity::material::Material;
+    use universe::entity::material::Vacuum;
+    use universe::entity::shape::Shape;
+    use universe::entity::shape::VoidShape;
+    use universe::entity::surface::Surface;
+    use ::F;
+    use super::*;
+
+    struct Medium {
+        shape: Arc<VoidShape>,
+        material: Arc<Vacuum>,
+        priority: i32,
+    }
+
+    impl Medium {
+        fn new(priority: i32) -> Self {
+            Medium {
+                shape: Arc::new(VoidShape::new()),
+                material: Arc::new(Vacuum::new()),
+                priority: priority,
+            }
+        }
+    }
+
+    impl Entity<Point2<F>, Vector2<F>> for Medium {
+        fn as_traceable_mut(&mut self) -> Option<&mut Traceable<Point2<F>, Vector2<F>>> {
+            Some(self)
+        }
+
+        fn as_traceable(&self) -> Option<&Traceable<Point2<F>, Vector2<F>>> {
+            Some(self)
+        }
+    }
+
+    impl Traceable<Point2<F>, Vector2<F>> for Medium {
+        fn shape(&self) -> &Shape<Point2<F>, Vector2<F>> {
+            self.shape.as_ref()
+        }
+
+        fn material(&self) -> &Material<Point2<F>, Vector2<F>> {
+            self.material.as_ref()
+        }
+
+        fn surface(&self) -> Option<&Surface<Point2<F>, Vector2<F>>> {
+            None
+        }
+
+        fn priority(&self) -> i32 {
+            self.priority
+        }
+    }
+
+    #[test]
+    fn medium_stack_priority() {
+        let void = Void::<Point2<F>, Vector2<F>>::new_with_vacuum();
+        let water = Medium::new(1);
+        let glass = Medium::new(2);
+        let mut media = MediumStack::new();
+
+        media.push(&void);
+        assert!(same_traceable(media.current().unwrap(), &void));
+
+        media.push(&glass);
+        media.push(&water);
+        assert!(same_traceable(media.current().unwrap(), &glass));
+        assert!(media.is_boundary(&glass));
+        assert!(!media.is_boundary(&water));
+
+        let exited = media.transition(&glass, true);
+        assert!(same_traceable(exited.current().unwrap(), &water));
+        assert!(!exited.contains(&glass));
+        assert_eq!(media.len(), 3);
+    }
+
+    #[test]
+    fn medium_stack_equal_priorities() {
+        let first = Medium::new(0);
+        let second = Medium::new(0);
+        let mut media = MediumStack::new();
+
+        media.push(&first);
+        media.push(&second);
+        assert!(same_traceable(media.current().unwrap(), &second));
+        assert!(media.is_boundary(&first));
+
+        media.remove(&second);
+        assert!(same_traceable(media.current().unwrap(), &first));
+        assert!(!media.remove(&second));
+    }
+}
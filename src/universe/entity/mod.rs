@@ -2,9 +2,19 @@
 pub mod material;
 #[macro_use]
 pub mod shape;
+pub mod polytope;
+pub mod lens;
+pub mod stereo;
 pub mod surface;
+pub mod procedural;
+pub mod environment_map;
+pub mod texture;
+pub mod exr;
+pub mod uv;
+pub mod medium;
 
 use ::F;
+use std::i32;
 use std::time::Duration;
 use std::sync::Arc;
 use simulation::SimulationContext;
@@ -14,6 +24,9 @@ use universe::entity::shape::VoidShape;
 use universe::entity::material::Material;
 use universe::entity::material::Vacuum;
 use universe::entity::surface::Surface;
+use universe::entity::lens::LensSample;
+use universe::entity::stereo::Eye;
+use universe::entity::stereo::Stereo;
 use util::CustomFloat;
 use util::CustomPoint;
 use util::CustomVector;
@@ -40,6 +53,40 @@ pub trait Camera<P: CustomPoint<V>, V: CustomVector<P>, U: Universe<P=P, V=V>>
                       -> V;
     fn max_depth(&self) -> u32;
     fn update(&mut self, delta_time: &Duration, context: &SimulationContext, universe: &U);
+
+    /// Finds the screen coordinates the point is seen at, the inverse of `get_ray_vector`.
+    /// Returns `None` for points the camera cannot see.
+    #[allow(unused_variables)]
+    fn project_point(&self, point: &P, screen_width: i32, screen_height: i32) -> Option<(F, F)> {
+        None
+    }
+
+    /// The ray through the pixel passing through the point of the aperture picked by the sample
+    /// from the unit cube, see `lens::lens_sample`. Pinhole cameras ignore the sample.
+    /// Stereo cameras trace the requested eye, see `Stereo::viewport`.
+    #[allow(unused_variables)]
+    fn get_ray(&self,
+               screen_x: i32,
+               screen_y: i32,
+               screen_width: i32,
+               screen_height: i32,
+               lens_sample: LensSample,
+               eye: Option<Eye>)
+               -> (P, V) {
+        (self.get_ray_point(screen_x, screen_y, screen_width, screen_height),
+         self.get_ray_vector(screen_x, screen_y, screen_width, screen_height))
+    }
+
+    /// How the two eyes of the camera are rendered, `None` for a single eye
+    fn stereo(&self) -> Option<Stereo> {
+        None
+    }
+
+    /// The normal of the hyperplane containing the rays of the camera, which the universe is
+    /// sliced with. Returns `None` if the rays are not restricted to a hyperplane.
+    fn cross_section(&self) -> Option<V> {
+        None
+    }
 }
 
 pub trait Traceable<P: CustomPoint<V>, V: CustomVector<P>>
@@ -47,6 +94,17 @@ pub trait Traceable<P: CustomPoint<V>, V: CustomVector<P>>
     fn shape(&self) -> &Shape<P, V>;
     fn material(&self) -> &Material<P, V>;
     fn surface(&self) -> Option<&Surface<P, V>>;
+
+    /// Decides which medium a ray travels through, where volumes overlap.
+    /// The medium with the highest priority is used.
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// Whether the entity stops the cameras with collisions enabled, see `Collision`
+    fn is_solid(&self) -> bool {
+        false
+    }
 }
 
 pub trait Locatable<P: CustomPoint<V>, V: CustomVector<P>> {
@@ -104,4 +162,8 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> Traceable<P, V> for Void<P, V> {
     fn surface(&self) -> Option<&Surface<P, V>> {
         None
     }
+
+    fn priority(&self) -> i32 {
+        i32::MIN
+    }
 }
@@ -0,0 +1,794 @@
+use ::F;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use num::Zero;
+use num::One;
+use num::Float;
+use num::NumCast;
+use na;
+use na::Dimension;
+use universe::entity::shape::Shape;
+use universe::entity::shape::Hyperplane;
+use universe::entity::shape::HalfSpace;
+use universe::entity::shape::ConvexPolytope;
+use universe::entity::shape::ComposableShape;
+use universe::entity::shape::SetOperation;
+use universe::entity::shape::Cylinder;
+use universe::entity::shape::Sphere;
+use util::CustomPoint;
+use util::CustomVector;
+use util::GeneralRotation;
+
+/// The golden ratio, which appears in the coordinates of the polytopes with pentagonal faces
+pub fn golden_ratio() -> F {
+    (<F as One>::one() + <F as NumCast>::from(5.0).unwrap().sqrt())
+        / <F as NumCast>::from(2.0).unwrap()
+}
+
+/// The permutations of coordinates to include
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    Even,
+    Odd,
+    Any,
+}
+
+fn index_permutations(length: usize) -> Vec<Vec<usize>> {
+    if length == 0 {
+        return vec![Vec::new()];
+    }
+
+    index_permutations(length - 1)
+        .into_iter()
+        .flat_map(|permutation| {
+            (0..length).map(move |position| {
+                let mut permutation = permutation.clone();
+                permutation.insert(position, length - 1);
+                permutation
+            })
+        })
+        .collect()
+}
+
+fn is_odd(permutation: &[usize]) -> bool {
+    let mut inversions = 0;
+
+    for i in 0..permutation.len() {
+        for j in (i + 1)..permutation.len() {
+            if permutation[i] > permutation[j] {
+                inversions += 1;
+            }
+        }
+    }
+
+    inversions % 2 == 1
+}
+
+/// All of the distinct vectors made by permuting the coordinates with the given parity
+/// and changing the signs of the non-zero ones, the usual way to list the vertices
+/// of regular polytopes.
+pub fn signed_permutations<V>(coordinates: &[F], parity: Parity) -> Vec<V>
+    where V: FromIterator<F> + PartialEq {
+    let mut result: Vec<V> = Vec::new();
+
+    for permutation in index_permutations(coordinates.len()) {
+        let odd = is_odd(&permutation);
+
+        if (parity == Parity::Even && odd) || (parity == Parity::Odd && !odd) {
+            continue;
+        }
+
+        let permuted: Vec<F> = permutation.iter().map(|index| coordinates[*index]).collect();
+        let mut signed: Vec<Vec<F>> = vec![permuted.clone()];
+
+        for (index, coordinate) in permuted.iter().enumerate() {
+            if *coordinate != <F as Zero>::zero() {
+                let flipped: Vec<Vec<F>> = signed.iter()
+                    .map(|vector| {
+                        let mut vector = vector.clone();
+                        vector[index] = -vector[index];
+                        vector
+                    })
+                    .collect();
+
+                signed.extend(flipped);
+            }
+        }
+
+        for vector in signed {
+            let vector: V = vector.into_iter().collect();
+
+            if !result.contains(&vector) {
+                result.push(vector);
+            }
+        }
+    }
+
+    result
+}
+
+/// A rotation by the angle in radians in the plane of both vectors, from the first one
+/// towards the second one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaneRotation<V> {
+    pub from: V,
+    pub to: V,
+    pub angle: F,
+}
+
+impl<V> PlaneRotation<V> {
+    pub fn new(from: V, to: V, angle: F) -> Self {
+        PlaneRotation {
+            from: from,
+            to: to,
+            angle: angle,
+        }
+    }
+
+    pub fn rotate(&self, vectors: &mut [V])
+        where V: GeneralRotation<F> {
+        self.from.general_rotation(&self.to, self.angle, vectors);
+    }
+}
+
+/// A polytope centered at the origin with a circumradius of 1, from which shapes of any
+/// position and size are built
+#[derive(Clone, Debug)]
+pub struct RegularPolytope<P: CustomPoint<V>, V: CustomVector<P>> {
+    pub vertices: Vec<V>,
+    /// The outward normals of the facets
+    pub facet_normals: Vec<V>,
+    /// The distance of the facets from the center
+    pub inradius: F,
+    marker: PhantomData<P>,
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> RegularPolytope<P, V> {
+    /// The vertices are scaled to the circumradius of 1, the facets must all be at the same
+    /// distance from the center.
+    pub fn new(vertices: Vec<V>, facet_normals: Vec<V>) -> Self {
+        let vertices: Vec<V> = vertices.iter().map(|vertex| vertex.normalize()).collect();
+        let facet_normals: Vec<V> = facet_normals.iter()
+            .map(|normal| normal.normalize())
+            .collect();
+        let inradius = vertices.iter()
+            .map(|vertex| vertex.dot(&facet_normals[0]))
+            .fold(<F as Zero>::zero(), |result, distance| result.max(distance));
+
+        RegularPolytope {
+            vertices: vertices,
+            facet_normals: facet_normals,
+            inradius: inradius,
+            marker: PhantomData,
+        }
+    }
+
+    /// Applies the rotations in order
+    pub fn with_orientation(mut self, orientation: &[PlaneRotation<V>]) -> Self {
+        for rotation in orientation {
+            rotation.rotate(&mut self.vertices);
+            rotation.rotate(&mut self.facet_normals);
+        }
+
+        self
+    }
+
+    /// The pairs of indices of the vertices connected by an edge, the closest pairs of vertices
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        let mut pairs: Vec<(usize, usize, F)> = Vec::new();
+
+        for a in 0..self.vertices.len() {
+            for b in (a + 1)..self.vertices.len() {
+                pairs.push((a, b, (self.vertices[a] - self.vertices[b]).norm_squared()));
+            }
+        }
+
+        let shortest = pairs.iter()
+            .map(|&(_, _, distance)| distance)
+            .fold(<F as NumCast>::from(4.0).unwrap(), |result, distance| result.min(distance));
+        let tolerance = shortest * <F as NumCast>::from(1.0e-6).unwrap();
+
+        pairs.into_iter()
+            .filter(|&(_, _, distance)| distance <= shortest + tolerance)
+            .map(|(a, b, _)| (a, b))
+            .collect()
+    }
+
+    fn vertex_locations(&self, center: &P, circumradius: F) -> Vec<P> {
+        self.vertices.iter().map(|vertex| (*vertex * circumradius).translate(center)).collect()
+    }
+
+    /// The polytope as the intersection of the half-spaces of its facets
+    pub fn solid(&self, center: &P, circumradius: F) -> ConvexPolytope<P, V> {
+        let distance = self.inradius * circumradius;
+
+        ConvexPolytope::of(self.facet_normals.iter().map(|normal| {
+            HalfSpace::new_with_point(
+                Hyperplane::new_with_point(*normal, &(*normal * distance).translate(center)),
+                center
+            )
+        }))
+    }
+
+    /// The edges as cylinders joined by spheres at the vertices, see `wireframe`
+    pub fn wireframe(&self, center: &P, circumradius: F, edge_radius: F) -> ComposableShape<P, V> {
+        wireframe(&self.vertex_locations(center, circumradius), &self.edges(), edge_radius)
+    }
+}
+
+/// The number of edges and vertices of a wireframe grouped under a single bounding sphere
+const WIREFRAME_LEAF_SIZE: usize = 4;
+/// Enlarges the bounding spheres, so that they never touch the parts within them
+const BOUNDING_MARGIN: F = 1.001;
+/// The dot products of unit vectors considered zero
+const DIRECTION_EPSILON: F = 1.0e-9;
+
+/// A part of a wireframe with a sphere containing it
+struct BoundedPart<P: CustomPoint<V>, V: CustomVector<P>> {
+    center: P,
+    radius: F,
+    shape: Box<Shape<P, V>>,
+}
+
+/// The union of cylinders along the edges, joined by spheres at the vertices.
+/// The parts are grouped in a hierarchy of bounding spheres, see `bounding_hierarchy`.
+pub fn wireframe<P, V>(vertices: &[P],
+                       edges: &[(usize, usize)],
+                       edge_radius: F)
+                       -> ComposableShape<P, V>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let half = <F as One>::one() / (<F as One>::one() + <F as One>::one());
+    let mut parts: Vec<BoundedPart<P, V>> = Vec::new();
+
+    for &(a, b) in edges {
+        let edge = vertices[b] - vertices[a];
+        let center = (edge * half).translate(&vertices[a]);
+
+        parts.push(BoundedPart {
+            center: center,
+            radius: edge.norm() * half + edge_radius,
+            shape: Box::new(Cylinder::new_with_height(center, &edge, edge_radius, edge.norm())),
+        });
+    }
+
+    for vertex in vertices {
+        parts.push(BoundedPart {
+            center: *vertex,
+            radius: edge_radius,
+            shape: Box::new(Sphere::new(*vertex, edge_radius)),
+        });
+    }
+
+    bounding_hierarchy(parts)
+}
+
+/// Splits the parts in halves along the axis they are spread the most along, until only
+/// a few are left. Each union of the halves is intersected with a sphere containing it,
+/// so that the rays missing the sphere skip all of the parts within it.
+fn bounding_hierarchy<P, V>(mut parts: Vec<BoundedPart<P, V>>) -> ComposableShape<P, V>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let dimension = <V as Dimension>::dimension(None);
+    let count: F = <F as NumCast>::from(parts.len()).unwrap();
+    let center: V = parts.iter()
+        .fold(na::zero::<V>(), |sum, part| sum + *part.center.as_vector()) / count;
+    let center: P = center.to_point();
+    let radius = parts.iter()
+        .fold(<F as Zero>::zero(), |radius, part| {
+            radius.max(na::distance(&center, &part.center) + part.radius)
+        }) * BOUNDING_MARGIN;
+    let union = if parts.len() <= WIREFRAME_LEAF_SIZE {
+        ComposableShape::of(parts.into_iter().map(|part| part.shape), SetOperation::Union)
+    } else {
+        let extent = |axis: usize| {
+            let coordinates = parts.iter().map(|part| part.center[axis]);
+            let max = coordinates.clone().fold(<F as Float>::neg_infinity(), |a, b| a.max(b));
+            let min = coordinates.fold(<F as Float>::infinity(), |a, b| a.min(b));
+
+            max - min
+        };
+        let axis = (0..dimension)
+            .max_by(|a, b| extent(*a).partial_cmp(&extent(*b)).unwrap())
+            .unwrap();
+
+        parts.sort_by(|a, b| a.center[axis].partial_cmp(&b.center[axis]).unwrap());
+
+        let second = parts.split_off(parts.len() / 2);
+        let halves: Vec<Box<Shape<P, V>>> = vec![Box::new(bounding_hierarchy(parts)),
+                                                 Box::new(bounding_hierarchy(second))];
+
+        ComposableShape::of(halves, SetOperation::Union)
+    };
+
+    // The sphere comes first, so that the intersection skips the union when it is missed
+    ComposableShape::new(Sphere::new(center, radius), union, SetOperation::Intersection)
+}
+
+/// Collects the bounding planes of a shape made of half-spaces, returns `false` if the shape
+/// is not an intersection of half-spaces.
+fn collect_planes<P, V>(shape: &Shape<P, V>, planes: &mut Vec<Hyperplane<P, V>>) -> bool
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    if let Some(polytope) = shape.as_any().downcast_ref::<ConvexPolytope<P, V>>() {
+        planes.extend(polytope.planes.iter().map(|plane| {
+            Hyperplane::new(plane.normal, plane.constant)
+        }));
+        true
+    } else if let Some(half_space) = shape.as_any().downcast_ref::<HalfSpace<P, V>>() {
+        planes.push(half_space.boundary());
+        true
+    } else if let Some(composed) = shape.as_any().downcast_ref::<ComposableShape<P, V>>() {
+        match composed.operation {
+            SetOperation::Intersection =>
+                composed.shapes.iter().all(|shape| collect_planes(shape.as_ref(), planes)),
+            _ => false,
+        }
+    } else {
+        false
+    }
+}
+
+/// Converts a `ConvexPolytope`, a `HalfSpace` or a `ComposableShape` intersecting any of those
+/// to a single `ConvexPolytope`, `None` for any other shape.
+pub fn convex_polytope_of<P, V>(shape: &Shape<P, V>) -> Option<ConvexPolytope<P, V>>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let mut planes = Vec::new();
+
+    if collect_planes(shape, &mut planes) {
+        Some(ConvexPolytope::new(planes))
+    } else {
+        None
+    }
+}
+
+/// Moves to the next combination of increasing indices lower than `count`,
+/// returns `false` after the last one.
+fn next_combination(indices: &mut [usize], count: usize) -> bool {
+    let length = indices.len();
+
+    for position in (0..length).rev() {
+        if indices[position] < count - (length - position) {
+            indices[position] += 1;
+
+            for following in (position + 1)..length {
+                indices[following] = indices[following - 1] + 1;
+            }
+
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Gaussian elimination with partial pivoting, each row is the coefficients followed
+/// by the right-hand side. Returns the rank and reduces the rows in place.
+fn eliminate(rows: &mut [Vec<F>], columns: usize) -> usize {
+    let mut rank = 0;
+
+    for column in 0..columns {
+        if rank == rows.len() {
+            break;
+        }
+
+        let pivot = (rank..rows.len())
+            .max_by(|a, b| {
+                rows[*a][column].abs().partial_cmp(&rows[*b][column].abs()).unwrap()
+            })
+            .unwrap();
+
+        if rows[pivot][column].abs() <= <F as NumCast>::from(1.0e-9).unwrap() {
+            continue;
+        }
+
+        rows.swap(rank, pivot);
+
+        for row in 0..rows.len() {
+            if row != rank {
+                let factor = rows[row][column] / rows[rank][column];
+
+                for index in column..rows[row].len() {
+                    let value = rows[rank][index];
+                    rows[row][index] -= factor * value;
+                }
+            }
+        }
+
+        rank += 1;
+    }
+
+    rank
+}
+
+/// The vertices and edges of a convex polytope
+#[derive(Clone, Debug)]
+pub struct Skeleton<P> {
+    pub vertices: Vec<P>,
+    /// The pairs of indices of the vertices connected by an edge
+    pub edges: Vec<(usize, usize)>,
+}
+
+/// Whether the point is inside of all of the half-spaces bounded by the planes
+fn is_inside<P, V>(planes: &[Hyperplane<P, V>], point: &P, tolerance: F) -> bool
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    planes.iter().all(|plane| {
+        na::dot(&plane.normal, point.as_vector()) + plane.constant <= tolerance
+    })
+}
+
+/// Adds the part of the vector perpendicular to the orthonormal basis to it, if there is any
+fn extend_basis<P, V>(basis: &mut Vec<V>, vector: &V)
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let perpendicular = basis.iter()
+        .fold(*vector, |result, axis| result - *axis * na::dot(vector, axis));
+
+    if perpendicular.norm() > DIRECTION_EPSILON.sqrt() {
+        basis.push(perpendicular.normalize());
+    }
+}
+
+/// A unit vector perpendicular to the orthonormal basis, which must not span the whole space
+fn perpendicular_to<P, V>(basis: &[V]) -> V
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let dimension = <V as Dimension>::dimension(None);
+
+    (0..dimension)
+        .map(|axis| {
+            let unit: V = (0..dimension)
+                .map(|index| if index == axis { <F as One>::one() } else { <F as Zero>::zero() })
+                .collect();
+
+            basis.iter().fold(unit, |result, other| result - *other * na::dot(&unit, other))
+        })
+        .max_by(|a, b| a.norm_squared().partial_cmp(&b.norm_squared()).unwrap())
+        .unwrap()
+        .normalize()
+}
+
+/// How far the point moves along the direction before it leaves one of the half-spaces, other
+/// than the skipped ones, and which one it leaves. `None` if it never leaves any of them.
+fn exit_distance<P, V>(planes: &[Hyperplane<P, V>], point: &P, direction: &V, skipped: &[usize])
+                       -> Option<(usize, F)>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    planes.iter()
+        .enumerate()
+        .filter(|&(index, _)| !skipped.contains(&index))
+        .filter_map(|(index, plane)| {
+            let speed = na::dot(&plane.normal, direction);
+
+            if speed > DIRECTION_EPSILON {
+                let distance = -(na::dot(&plane.normal, point.as_vector()) + plane.constant) / speed;
+
+                Some((index, distance.max(<F as Zero>::zero())))
+            } else {
+                None
+            }
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+}
+
+/// The point where the planes at the indices meet, if they meet in a single point
+fn meeting_point<P, V>(planes: &[Hyperplane<P, V>], indices: &[usize]) -> Option<P>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let dimension = <V as Dimension>::dimension(None);
+    let mut rows: Vec<Vec<F>> = indices.iter()
+        .map(|index| {
+            let plane = &planes[*index];
+            let mut row: Vec<F> = (0..dimension).map(|axis| plane.normal[axis]).collect();
+
+            row.push(-plane.constant);
+            row
+        })
+        .collect();
+
+    if eliminate(&mut rows, dimension) == dimension {
+        let vertex: V = rows.iter()
+            .enumerate()
+            .map(|(axis, row)| row[dimension] / row[axis])
+            .collect();
+
+        Some(vertex.to_point())
+    } else {
+        None
+    }
+}
+
+/// Finds a vertex to start walking the edges from. From a point inside of the polytope,
+/// it moves along the planes it reaches until it is stopped in a corner. Only when neither
+/// the origin nor the mean of the points of the planes closest to it are inside, the planes
+/// are combined until they meet in a vertex, which takes a while with many planes.
+/// `None` if there are no vertices, e.g. when the polytope contains a whole line.
+fn first_vertex<P, V>(planes: &[Hyperplane<P, V>], tolerance: F) -> Option<P>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let dimension = <V as Dimension>::dimension(None);
+
+    if planes.len() < dimension {
+        return None;
+    }
+
+    let count: F = <F as NumCast>::from(planes.len()).unwrap();
+    let closest_mean: V = planes.iter()
+        .fold(na::zero::<V>(), |sum, plane| sum - plane.normal * plane.constant) / count;
+    let start = [na::zero::<V>().to_point(), closest_mean.to_point()].iter()
+        .cloned()
+        .find(|point| is_inside(planes, point, tolerance));
+
+    let mut point = match start {
+        Some(point) => point,
+        None => {
+            let mut indices: Vec<usize> = (0..dimension).collect();
+
+            loop {
+                if let Some(vertex) = meeting_point(planes, &indices) {
+                    if is_inside(planes, &vertex, tolerance) {
+                        return Some(vertex);
+                    }
+                }
+
+                if !next_combination(&mut indices, planes.len()) {
+                    return None;
+                }
+            }
+        }
+    };
+    let mut basis: Vec<V> = Vec::new();
+
+    while basis.len() < dimension {
+        let direction = perpendicular_to(&basis);
+        let (direction, (index, distance)) = match exit_distance(planes, &point, &direction, &[]) {
+            Some(exit) => (direction, exit),
+            None => match exit_distance(planes, &point, &-direction, &[]) {
+                Some(exit) => (-direction, exit),
+                None => return None,
+            },
+        };
+
+        point = (direction * distance).translate(&point);
+        extend_basis(&mut basis, &planes[index].normal);
+    }
+
+    Some(point)
+}
+
+/// The vertices connected to the vertex by an edge. Every `dimension - 1` of the planes
+/// touching the vertex meeting in a line give a direction, which is followed if it stays
+/// inside of all of the touching planes.
+fn neighbours<P, V>(planes: &[Hyperplane<P, V>], vertex: &P, tolerance: F) -> Vec<P>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let dimension = <V as Dimension>::dimension(None);
+    let touching: Vec<usize> = planes.iter()
+        .enumerate()
+        .filter(|&(_, plane)| {
+            (na::dot(&plane.normal, vertex.as_vector()) + plane.constant).abs() <= tolerance
+        })
+        .map(|(index, _)| index)
+        .collect();
+    let mut result = Vec::new();
+
+    if touching.len() + 1 < dimension {
+        return result;
+    }
+
+    let mut indices: Vec<usize> = (0..(dimension - 1)).collect();
+
+    loop {
+        let mut basis: Vec<V> = Vec::new();
+
+        for index in &indices {
+            extend_basis(&mut basis, &planes[touching[*index]].normal);
+        }
+
+        if basis.len() + 1 == dimension {
+            let direction = perpendicular_to(&basis);
+
+            for direction in &[direction, -direction] {
+                let feasible = touching.iter()
+                    .all(|index| na::dot(&planes[*index].normal, direction) <= DIRECTION_EPSILON);
+
+                // The unbounded edges are left out
+                if let Some((_, distance)) = exit_distance(planes, vertex, direction, &touching) {
+                    if feasible && distance > tolerance {
+                        result.push((*direction * distance).translate(vertex));
+                    }
+                }
+            }
+        }
+
+        if !next_combination(&mut indices, touching.len()) {
+            break;
+        }
+    }
+
+    result
+}
+
+/// Finds the vertices and edges by walking along the edges from the first vertex found,
+/// see `first_vertex`. The unbounded edges of unbounded polytopes are left out.
+pub fn skeleton<P, V>(polytope: &ConvexPolytope<P, V>) -> Skeleton<P>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let planes = &polytope.planes;
+    let scale = planes.iter()
+        .fold(<F as One>::one(), |result, plane| result.max(plane.constant.abs()));
+    let tolerance = scale * <F as NumCast>::from(1.0e-7).unwrap();
+    let mut vertices: Vec<P> = first_vertex(planes, tolerance).into_iter().collect();
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    let mut current = 0;
+
+    while current < vertices.len() {
+        for neighbour in neighbours(planes, &vertices[current], tolerance) {
+            let index = match vertices.iter().position(|other| {
+                na::distance_squared(other, &neighbour) <= tolerance * tolerance
+            }) {
+                Some(index) => index,
+                None => {
+                    vertices.push(neighbour);
+                    vertices.len() - 1
+                }
+            };
+            let edge = (current.min(index), current.max(index));
+
+            if edge.0 != edge.1 && !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+
+        current += 1;
+    }
+
+    Skeleton {
+        vertices: vertices,
+        edges: edges,
+    }
+}
+
+/// The edges of a bounded convex shape made of half-spaces as cylinders, see `wireframe`.
+/// `None` if the shape is not made of half-spaces or has no edges.
+pub fn edges_of<P, V>(shape: &Shape<P, V>, edge_radius: F) -> Option<ComposableShape<P, V>>
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    convex_polytope_of(shape)
+        .map(|polytope| skeleton(&polytope))
+        .and_then(|skeleton| {
+            if skeleton.edges.is_empty() {
+                None
+            } else {
+                Some(wireframe(&skeleton.vertices, &skeleton.edges, edge_radius))
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use na::Point3;
+    use na::Vector3;
+    use na::Point4;
+    use na::Vector4;
+    use na::BaseFloat;
+    use universe::entity::shape::Shape;
+    use super::*;
+
+    #[test]
+    fn permutations_with_signs() {
+        let all: Vec<Vector3<F>> = signed_permutations(&[1.0, 0.0, 0.0], Parity::Any);
+        let even: Vec<Vector3<F>> = signed_permutations(&[0.0, 1.0, 2.0], Parity::Even);
+
+        assert_eq!(all.len(), 6);
+        assert_eq!(even.len(), 12);
+        assert!(even.contains(&Vector3::new(2.0, 0.0, -1.0)));
+        assert!(!even.contains(&Vector3::new(1.0, 0.0, 2.0)));
+    }
+
+    #[test]
+    fn oriented_cube() {
+        let cube: RegularPolytope<Point3<F>, Vector3<F>> =
+            RegularPolytope::new(signed_permutations(&[1.0, 1.0, 1.0], Parity::Any),
+                                 signed_permutations(&[1.0, 0.0, 0.0], Parity::Any))
+                .with_orientation(&[PlaneRotation::new(Vector3::new(1.0, 0.0, 0.0),
+                                                       Vector3::new(0.0, 1.0, 0.0),
+                                                       <F as BaseFloat>::frac_pi_4())]);
+        let solid = cube.solid(&Point3::new(0.0, 0.0, 0.0), (3.0 as F).sqrt());
+
+        assert_eq!(cube.edges().len(), 12);
+        assert!(solid.is_point_inside(&Point3::new(1.4, 0.0, 0.0)));
+        assert!(!solid.is_point_inside(&Point3::new(1.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn skeleton_of_half_spaces() {
+        let center = Point3::new(1.0, 2.0, 3.0);
+        let box_polytope = ConvexPolytope::new_box(&center, &Vector3::new(1.0, 2.0, 3.0));
+        let box_skeleton = skeleton(&box_polytope);
+
+        assert_eq!(box_skeleton.vertices.len(), 8);
+        assert_eq!(box_skeleton.edges.len(), 12);
+
+        // Four faces meet at each vertex of an octahedron
+        let octahedron: RegularPolytope<Point3<F>, Vector3<F>> =
+            RegularPolytope::new(signed_permutations(&[1.0, 0.0, 0.0], Parity::Any),
+                                 signed_permutations(&[1.0, 1.0, 1.0], Parity::Any));
+        let octahedron_skeleton = skeleton(&octahedron.solid(&center, 1.0));
+
+        assert_eq!(octahedron_skeleton.vertices.len(), 6);
+        assert_eq!(octahedron_skeleton.edges.len(), 12);
+    }
+
+    #[test]
+    fn composable_half_spaces_are_convex() {
+        let shapes: Vec<Box<Shape<Point3<F>, Vector3<F>>>> = vec![
+            Box::new(ConvexPolytope::new_box(&Point3::new(0.0, 0.0, 0.0),
+                                             &Vector3::new(2.0, 2.0, 2.0))),
+            Box::new(HalfSpace::new_with_point(
+                Hyperplane::new_with_point(Vector3::new(1.0, 1.0, 1.0),
+                                           &Point3::new(0.5, 0.5, 0.5)),
+                &Point3::new(0.0, 0.0, 0.0)
+            )),
+        ];
+        let cut_cube = ComposableShape::of(shapes, SetOperation::Intersection);
+        let cut_polytope = convex_polytope_of::<Point3<F>, Vector3<F>>(&cut_cube).unwrap();
+        let cut_skeleton = skeleton(&cut_polytope);
+        let union: ComposableShape<Point3<F>, Vector3<F>> =
+            ComposableShape::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0),
+                                 Sphere::new(Point3::new(1.0, 0.0, 0.0), 1.0),
+                                 SetOperation::Union);
+
+        // The corner is cut off by a triangle
+        assert_eq!(cut_skeleton.vertices.len(), 10);
+        assert_eq!(cut_skeleton.edges.len(), 15);
+        assert!(convex_polytope_of::<Point3<F>, Vector3<F>>(&union).is_none());
+    }
+
+    #[test]
+    fn skeleton_of_24_cell() {
+        // Six octahedral cells meet at each vertex
+        let mut facet_normals: Vec<Vector4<F>> =
+            signed_permutations(&[1.0, 0.0, 0.0, 0.0], Parity::Any);
+
+        facet_normals.extend(signed_permutations::<Vector4<F>>(&[0.5, 0.5, 0.5, 0.5], Parity::Any));
+
+        let cell: RegularPolytope<Point4<F>, Vector4<F>> =
+            RegularPolytope::new(signed_permutations(&[1.0, 1.0, 0.0, 0.0], Parity::Any),
+                                 facet_normals);
+        let skeleton = skeleton(&cell.solid(&Point4::new(0.0, 0.0, 0.0, 0.0), 1.0));
+
+        assert_eq!(skeleton.vertices.len(), 24);
+        assert_eq!(skeleton.edges.len(), 96);
+    }
+
+    #[test]
+    fn wireframe_covers_edges() {
+        let cube = ConvexPolytope::new_box(&Point3::new(0.0, 0.0, 0.0), &Vector3::new(2.0, 2.0, 2.0));
+        let skeleton = skeleton(&cube);
+        let frame = wireframe(&skeleton.vertices, &skeleton.edges, 0.1);
+
+        for &(a, b) in &skeleton.edges {
+            let (a, b) = (skeleton.vertices[a], skeleton.vertices[b]);
+
+            assert!(frame.is_point_inside(&a));
+            assert!(frame.is_point_inside(&Point3::new((a.x + b.x) / 2.0,
+                                                       (a.y + b.y) / 2.0,
+                                                       (a.z + b.z) / 2.0)));
+        }
+
+        assert!(!frame.is_point_inside(&Point3::new(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn skeleton_of_hypercube() {
+        let polytope = ConvexPolytope::new_box(&Point4::new(0.0, 0.0, 0.0, 0.0),
+                                               &Vector4::new(1.0, 1.0, 1.0, 1.0));
+        let skeleton = skeleton(&polytope);
+
+        assert_eq!(skeleton.vertices.len(), 16);
+        assert_eq!(skeleton.edges.len(), 32);
+    }
+}
@@ -0,0 +1,303 @@
+use ::F;
+use std::time::Duration;
+use std::sync::Arc;
+use palette::Rgba;
+use noise::{Perlin, NoiseModule, Seedable};
+use na;
+use na::Dimension;
+use na::Dot;
+use num::NumCast;
+use num::Zero;
+use num::One;
+use universe::entity::material::NOISE_DIMENSION;
+use universe::entity::material::noise_coordinates;
+use universe::entity::shape::TracingContext;
+use universe::entity::surface::HeightProvider;
+use universe::entity::surface::SurfaceColorProvider;
+use util;
+use util::CustomPoint;
+use util::CustomVector;
+
+fn scale_coordinates(coordinates: &[F; NOISE_DIMENSION], factor: F) -> [F; NOISE_DIMENSION] {
+    let mut result = *coordinates;
+
+    for coordinate in &mut result {
+        *coordinate *= factor;
+    }
+
+    result
+}
+
+/// Sums octaves of noise with increasing frequencies and decreasing amplitudes,
+/// the result is divided by the sum of the amplitudes.
+fn fractal<T: Fn(&[F; NOISE_DIMENSION]) -> F>(coordinates: &[F; NOISE_DIMENSION],
+                                               octaves: u32,
+                                               lacunarity: F,
+                                               gain: F,
+                                               noise: T)
+                                               -> F {
+    let mut result = <F as Zero>::zero();
+    let mut amplitude = <F as One>::one();
+    let mut amplitude_sum = <F as Zero>::zero();
+    let mut frequency = <F as One>::one();
+
+    for _ in 0..octaves {
+        result += noise(&scale_coordinates(coordinates, frequency)) * amplitude;
+        amplitude_sum += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+
+    if amplitude_sum > <F as Zero>::zero() {
+        result / amplitude_sum
+    } else {
+        result
+    }
+}
+
+/// Hashes the cell coordinates to a pseudo-random number in the range [0; 1).
+fn hash_cell(cell: &[i64; NOISE_DIMENSION], seed: u64, salt: u64) -> F {
+    let mut hash: u64 = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ salt;
+
+    for coordinate in cell {
+        hash ^= (*coordinate as u64)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15)
+            .wrapping_add(hash << 6)
+            .wrapping_add(hash >> 2);
+    }
+
+    // The SplitMix64 finalizer
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    hash ^= hash >> 31;
+
+    <F as NumCast>::from((hash >> 11) as f64 / (1u64 << 53) as f64).unwrap()
+}
+
+/// Finds the closest feature point of cellular noise, each unit cell contains a single
+/// randomly placed feature point.
+/// Returns the distance to the closest feature point and a random value of its cell.
+fn worley(coordinates: &[F; NOISE_DIMENSION], seed: u64) -> (F, F) {
+    const NEIGHBOURS: usize = 81; // 3 ^ NOISE_DIMENSION
+    let mut base: [i64; NOISE_DIMENSION] = [0; NOISE_DIMENSION];
+
+    for (index, cell) in base.iter_mut().enumerate() {
+        *cell = <i64 as NumCast>::from(coordinates[index].floor()).unwrap();
+    }
+
+    let mut closest_distance_squared: Option<F> = None;
+    let mut closest_value = <F as Zero>::zero();
+
+    for neighbour in 0..NEIGHBOURS {
+        let mut cell = base;
+        let mut remaining = neighbour;
+
+        for coordinate in &mut cell {
+            *coordinate += (remaining % 3) as i64 - 1;
+            remaining /= 3;
+        }
+
+        let mut distance_squared = <F as Zero>::zero();
+
+        for (axis, coordinate) in cell.iter().enumerate() {
+            let feature = <F as NumCast>::from(*coordinate).unwrap()
+                + hash_cell(&cell, seed, axis as u64);
+            let delta = feature - coordinates[axis];
+            distance_squared += delta * delta;
+        }
+
+        if closest_distance_squared.map_or(true, |closest| distance_squared < closest) {
+            closest_distance_squared = Some(distance_squared);
+            closest_value = hash_cell(&cell, seed, NOISE_DIMENSION as u64);
+        }
+    }
+
+    (closest_distance_squared.unwrap().sqrt(), closest_value)
+}
+
+/// Fractal Brownian motion -- octaves of Perlin noise, in the range [-1; 1]
+pub fn scalar_fbm<P: CustomPoint<V>, V: CustomVector<P>>
+    (seed: usize, size: F, speed: F, octaves: u32, lacunarity: F, gain: F)
+     -> Box<HeightProvider<P>> {
+    let perlin = Perlin::new().set_seed(seed);
+
+    Box::new(move |location: &P, time: &Duration| {
+        let coordinates = noise_coordinates(location, time, size, speed);
+
+        fractal(&coordinates, octaves, lacunarity, gain, |coordinates| {
+            perlin.get(*coordinates)
+        })
+    })
+}
+
+/// Octaves of the absolute value of Perlin noise, in the range [0; 1]
+pub fn scalar_turbulence<P: CustomPoint<V>, V: CustomVector<P>>
+    (seed: usize, size: F, speed: F, octaves: u32, lacunarity: F, gain: F)
+     -> Box<HeightProvider<P>> {
+    let perlin = Perlin::new().set_seed(seed);
+
+    Box::new(move |location: &P, time: &Duration| {
+        let coordinates = noise_coordinates(location, time, size, speed);
+
+        fractal(&coordinates, octaves, lacunarity, gain, |coordinates| {
+            perlin.get(*coordinates).abs()
+        })
+    })
+}
+
+/// Worley noise -- the distance to the closest feature point in units of `size`
+pub fn scalar_worley<P: CustomPoint<V>, V: CustomVector<P>>
+    (seed: u64, size: F, speed: F)
+     -> Box<HeightProvider<P>> {
+    Box::new(move |location: &P, time: &Duration| {
+        worley(&noise_coordinates(location, time, size, speed), seed).0
+    })
+}
+
+/// Voronoi cells -- a random value in the range [0; 1) constant within each cell
+pub fn scalar_voronoi<P: CustomPoint<V>, V: CustomVector<P>>
+    (seed: u64, size: F, speed: F)
+     -> Box<HeightProvider<P>> {
+    Box::new(move |location: &P, time: &Duration| {
+        worley(&noise_coordinates(location, time, size, speed), seed).1
+    })
+}
+
+/// A solid checkerboard in all dimensions of the space, alternating between 0 and 1
+#[allow(unused_variables)]
+pub fn scalar_checkerboard<P: CustomPoint<V>, V: CustomVector<P>>
+    (size: F)
+     -> Box<HeightProvider<P>> {
+    Box::new(move |location: &P, time: &Duration| {
+        let dimension = <P as Dimension>::dimension(None);
+        let mut parity: i64 = 0;
+
+        for axis in 0..dimension {
+            parity += <i64 as NumCast>::from((location[axis] / size).floor()).unwrap();
+        }
+
+        if util::remainder(parity, 2) == 0 {
+            <F as Zero>::zero()
+        } else {
+            <F as One>::one()
+        }
+    })
+}
+
+/// A linear gradient, the value is the projection of the location onto the direction
+/// divided by the length of the direction
+#[allow(unused_variables)]
+pub fn scalar_gradient<P: CustomPoint<V>, V: CustomVector<P>>
+    (direction: V)
+     -> Box<HeightProvider<P>> {
+    let scaled_direction = direction / direction.dot(&direction);
+
+    Box::new(move |location: &P, time: &Duration| {
+        location.as_vector().dot(&scaled_direction)
+    })
+}
+
+/// A radial gradient, the value is the distance from the center
+#[allow(unused_variables)]
+pub fn scalar_radial<P: CustomPoint<V>, V: CustomVector<P>>
+    (center: P)
+     -> Box<HeightProvider<P>> {
+    Box::new(move |location: &P, time: &Duration| {
+        na::distance(location, &center)
+    })
+}
+
+/// Linearly interpolates the colors between the stops, clamping the values outside
+/// of the positions.
+fn ramp(positions: &[F], colors: &[Rgba<F>], value: F) -> Rgba<F> {
+    if value <= positions[0] {
+        return colors[0];
+    }
+
+    for index in 1..positions.len() {
+        if value < positions[index] {
+            let ratio = (value - positions[index - 1]) / (positions[index] - positions[index - 1]);
+
+            return util::combine_palette_color(colors[index], colors[index - 1], ratio);
+        }
+    }
+
+    colors[colors.len() - 1]
+}
+
+/// Maps the values of the scalar field to colors, the positions must be increasing.
+pub fn surface_color_ramp<P: CustomPoint<V>, V: CustomVector<P>>
+    (field: Box<HeightProvider<P>>, positions: Vec<F>, colors: Vec<Rgba<F>>)
+     -> Box<SurfaceColorProvider<P, V>> {
+    assert!(!positions.is_empty(), "A color ramp requires at least one stop.");
+    assert_eq!(positions.len(), colors.len(),
+               "The number of color ramp positions and colors must be equal.");
+    assert!(positions.windows(2).all(|pair| pair[0] <= pair[1]),
+            "The color ramp positions must be increasing.");
+
+    let field: Arc<HeightProvider<P>> = field.into();
+
+    Box::new(move |context: &TracingContext<P, V>| {
+        ramp(&positions, &colors, field(&context.intersection.location, &context.time))
+    })
+}
+
+pub fn surface_color_checkerboard<P: CustomPoint<V>, V: CustomVector<P>>
+    (size: F, color_even: Rgba<F>, color_odd: Rgba<F>)
+     -> Box<SurfaceColorProvider<P, V>> {
+    surface_color_ramp(scalar_checkerboard(size),
+                       vec![<F as Zero>::zero(), <F as One>::one()],
+                       vec![color_even, color_odd])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use na::ApproxEq;
+    use na::Point3;
+    use na::Point4;
+    use na::Vector3;
+    use na::Vector4;
+    use palette::Rgba;
+    use ::F;
+    use super::*;
+
+    #[test]
+    fn checkerboard_alternates() {
+        let field = scalar_checkerboard::<Point4<F>, Vector4<F>>(1.0);
+        let time = Duration::from_secs(0);
+
+        assert_eq!(field(&Point4::new(0.5, 0.5, 0.5, 0.5), &time), 0.0);
+        assert_eq!(field(&Point4::new(1.5, 0.5, 0.5, 0.5), &time), 1.0);
+        assert_eq!(field(&Point4::new(1.5, 0.5, 0.5, -0.5), &time), 0.0);
+    }
+
+    #[test]
+    fn worley_range() {
+        let worley = scalar_worley::<Point3<F>, Vector3<F>>(7, 1.0, 0.0);
+        let voronoi = scalar_voronoi::<Point3<F>, Vector3<F>>(7, 1.0, 0.0);
+        let time = Duration::from_secs(0);
+
+        for index in 0..64 {
+            let offset = index as F * 0.37;
+            let location = Point3::new(offset, -offset * 0.5, offset * 1.3);
+            let distance = worley(&location, &time);
+            let value = voronoi(&location, &time);
+
+            assert!(distance >= 0.0 && distance <= 2.0);
+            assert!(value >= 0.0 && value < 1.0);
+        }
+    }
+
+    #[test]
+    fn ramp_interpolation() {
+        let positions = [0.0, 1.0];
+        let colors = [Rgba::new(0.0, 0.0, 0.0, 1.0), Rgba::new(1.0, 0.5, 0.0, 1.0)];
+        let middle = ramp(&positions, &colors, 0.5);
+
+        assert!(middle.color.red.approx_eq(&0.5));
+        assert!(middle.color.green.approx_eq(&0.25));
+        assert!(ramp(&positions, &colors, -1.0).color.red.approx_eq(&0.0));
+        assert!(ramp(&positions, &colors, 2.0).color.red.approx_eq(&1.0));
+    }
+}
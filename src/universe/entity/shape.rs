@@ -8,16 +8,17 @@ use std::fmt::Display;
 use std::time::Duration;
 use std::any::TypeId;
 use std::any::Any;
-use std::sync::Arc;
 use std::iter;
+use std::iter::Peekable;
 use palette::Rgba;
 use universe::entity::Traceable;
 use universe::entity::material::Material;
+use universe::entity::medium::MediumStack;
+use universe::aov::AovRecorder;
 use util::CustomFloat;
 use util::CustomPoint;
 use util::CustomVector;
 use util::HasId;
-use util::Provider;
 use util::TypePairMap;
 use util::PossiblyImmediateIterator;
 use num::Zero;
@@ -26,6 +27,8 @@ use num::NumCast;
 use mopa;
 use na;
 use na::Cross;
+use na::Dimension;
+use na::Repeat;
 use smallvec::SmallVec;
 use smallvec::IntoIter;
 
@@ -57,17 +60,12 @@ pub type Intersector<'a, P, V> = &'a Fn(&Material<P, V>, &Shape<P, V>)
 
 /// Calls the `trace` method on the current Universe and returns the resulting color.
 // TODO: It feels wrong to have a type alias to a reference of another type
-pub type ColorTracer<'a, P, V> = &'a Fn(&Duration, &Traceable<P, V>, &P, &V) -> Rgba<F>;
+pub type ColorTracer<'a, P, V> = &'a Fn(&Duration, &MediumStack<'a, P, V>, &P, &V) -> Rgba<F>;
 
 /// Calls the `trace_path` method on the current Universe and returns the resulting location and
 /// vector.
 // TODO: It feels wrong to have a type alias to a reference of another type
-pub type PathTracer<'a, P, V> = &'a Fn(&Duration, &F, &Traceable<P, V>, &P, &V) -> (P, V);
-
-/// Calls the `trace_path` method on the current Universe and returns the resulting location and
-/// vector.
-// TODO: It feels wrong to have a type alias to a reference of another type
-pub type MaterialFinder<'a, P, V> = &'a Fn(&P) -> Option<&'a Traceable<P, V>>;
+pub type PathTracer<'a, P, V> = &'a Fn(&Duration, &F, &MediumStack<'a, P, V>, &P, &V) -> (P, V);
 
 pub trait Shape<P: CustomPoint<V>, V: CustomVector<P>>
     where Self: HasId + Debug + Display + mopa::Any + Send + Sync
@@ -108,6 +106,30 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> Intersection<P, V> {
     }
 }
 
+/// Approximates the cross-section of a ray, which widens linearly with the travelled distance.
+/// Used to estimate the area of a surface covered by a single pixel.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RayCone {
+    /// The width of the cross-section at the origin of the ray
+    pub width: F,
+    /// The angle by which the cross-section widens, in radians
+    pub spread: F,
+}
+
+impl RayCone {
+    pub fn new(width: F, spread: F) -> Self {
+        RayCone {
+            width: width,
+            spread: spread,
+        }
+    }
+
+    /// The cone at the given distance along the ray
+    pub fn propagate(&self, distance: F) -> Self {
+        RayCone::new(self.width + self.spread * distance, self.spread)
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct TracingContext<'a,
                           P: 'a + CustomPoint<V>,
@@ -115,6 +137,11 @@ pub struct TracingContext<'a,
 {
     pub debugging: bool,
     pub time: Duration,
+    /// The wavelength of the traced light in nanometres, `None` when tracing all of
+    /// the visible spectrum at once
+    pub wavelength: Option<F>,
+    /// The cross-section of the ray at the intersection
+    pub cone: RayCone,
     pub origin_traceable: &'a Traceable<P, V>,
     pub origin_location: P,
     pub origin_direction: V,
@@ -131,8 +158,10 @@ pub struct ColorTracingContext<'a,
 {
     pub general: TracingContext<'a, P, V>,
     pub depth_remaining: &'a u32,
+    pub media: &'a MediumStack<'a, P, V>,
     pub trace: ColorTracer<'a, P, V>,
-    pub material_at: MaterialFinder<'a, P, V>,
+    /// Collects the output variables, only present for the surface hit by the primary ray
+    pub aovs: Option<&'a AovRecorder>,
 }
 
 #[derive(Copy, Clone)]
@@ -142,8 +171,8 @@ pub struct PathTracingContext<'a,
 {
     pub general: TracingContext<'a, P, V>,
     pub distance: &'a F,
+    pub media: &'a MediumStack<'a, P, V>,
     pub trace: PathTracer<'a, P, V>,
-    pub material_at: MaterialFinder<'a, P, V>,
 }
 
 #[allow(dead_code)]
@@ -157,445 +186,185 @@ pub enum SetOperation {
 
 debug_as_display!(SetOperation);
 
-pub type IntersectionProvider<P, V> = Provider<Intersection<P, V>, ImmediateIntersections<P, V>>;
+/// The intersections of a ray with a shape ordered by distance. It is only used by the thread
+/// tracing the ray, so the intersections are computed without any locking.
+pub type IntersectionProvider<P, V> = Peekable<GeneralIntersectionMarcher<P, V>>;
 
-struct ComposableShapeIterator<P: CustomPoint<V>, V: CustomVector<P>> {
-    shape_a: Arc<Box<Shape<P, V>>>,
-    shape_b: Arc<Box<Shape<P, V>>>,
-    provider_a: IntersectionProvider<P, V>,
-    provider_b: IntersectionProvider<P, V>,
-    index_a: usize,
-    index_b: usize,
+#[derive(Debug)]
+pub struct ComposableShape<P: CustomPoint<V>, V: CustomVector<P>> {
+    /// The operands of the operation, the `Complement` subtracts all the others from the first one
+    pub shapes: Vec<Box<Shape<P, V>>>,
+    pub operation: SetOperation,
+    marker: PhantomData<Shape<P, V>>,
 }
 
-impl<P: CustomPoint<V>, V: CustomVector<P>> ComposableShapeIterator<P, V> {
-    fn new(shape_a: Arc<Box<Shape<P, V>>>,
-           shape_b: Arc<Box<Shape<P, V>>>,
-           provider_a: IntersectionProvider<P, V>,
-           provider_b: IntersectionProvider<P, V>)
-           -> ComposableShapeIterator<P, V> {
-        ComposableShapeIterator {
-            shape_a: shape_a,
-            shape_b: shape_b,
-            provider_a: provider_a,
-            provider_b: provider_b,
-            index_a: 0,
-            index_b: 0,
-        }
-    }
-}
+shape!(ComposableShape<P: 'static + CustomPoint<V>, V: 'static + CustomVector<P>>);
 
-struct UnionIterator<P: CustomPoint<V>, V: CustomVector<P>> {
-    data: ComposableShapeIterator<P, V>,
-}
+impl<P: CustomPoint<V>, V: CustomVector<P>> ComposableShape<P, V> {
+    pub fn new<A: Shape<P, V> + 'static, B: Shape<P, V> + 'static>
+        (a: A,
+         b: B,
+         operation: SetOperation)
+         -> ComposableShape<P, V> {
+        let shapes: Vec<Box<Shape<P, V>>> = vec![Box::new(a), Box::new(b)];
 
-impl<P: CustomPoint<V>, V: CustomVector<P>> UnionIterator<P, V> {
-    fn new(shape_a: Arc<Box<Shape<P, V>>>,
-           shape_b: Arc<Box<Shape<P, V>>>,
-           provider_a: IntersectionProvider<P, V>,
-           provider_b: IntersectionProvider<P, V>)
-           -> UnionIterator<P, V> {
-        UnionIterator {
-            data: ComposableShapeIterator::new(shape_a, shape_b, provider_a, provider_b),
-        }
+        Self::of(shapes, operation)
     }
-}
 
-impl<P: CustomPoint<V>, V: CustomVector<P>> Iterator for UnionIterator<P, V> {
-    type Item = Intersection<P, V>;
+    /// A single node applying the operation to all of the shapes at once, instead of a tree
+    /// of nodes applying it to pairs of shapes.
+    pub fn of<I: IntoIterator<Item = Box<Shape<P, V>>>>(shapes: I,
+                                                          operation: SetOperation)
+                                                          -> ComposableShape<P, V> {
+        let shapes: Vec<Box<Shape<P, V>>> = shapes.into_iter().collect();
 
+        assert!(shapes.len() >= 2,
+                "2 or more `Shape`s are needed to construct a `ComposableShape`.");
 
-    // Should return the following intersections:
-    // --> [a] [b] [a[a+b]b]
-    //     ^-^ ^-^ ^-------^
-    #[allow(useless_let_if_seq)]
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let intersection_a = self.data.provider_a.get(self.data.index_a);
-            let intersection_b = self.data.provider_b.get(self.data.index_b);
-
-            if intersection_a.is_some() {
-                let unwrapped_a = intersection_a.unwrap();
-
-                if intersection_b.is_some() {
-                    let unwrapped_b = intersection_b.unwrap();
-                    let closer: Intersection<P, V>;
-                    let closer_index: &mut usize;
-                    let further_shape: &Shape<P, V>;
-
-                    if unwrapped_a.distance < unwrapped_b.distance {
-                        closer = unwrapped_a;
-                        closer_index = &mut self.data.index_a;
-                        further_shape = self.data.shape_b.as_ref().as_ref();
-                    } else {
-                        closer = unwrapped_b;
-                        closer_index = &mut self.data.index_b;
-                        further_shape = self.data.shape_a.as_ref().as_ref();
-                    }
+        ComposableShape {
+            shapes: shapes,
+            operation: operation,
+            marker: PhantomData,
+        }
+    }
 
-                    if !further_shape.is_point_inside(&closer.location) {
-                        *closer_index += 1;
-                        return Some(closer);
-                    }
+    /// Whether an intersection with the shape at the index lies on the surface of the composed
+    /// shape, and if so, whether its normal has to be inverted. The intersection is inside
+    /// the shapes flagged in `inside`, `inside_count` of them.
+    ///
+    /// ```text
+    /// Union:               --> [a] [b] [a[a+b]b]
+    ///                          ^-^ ^-^ ^-------^
+    /// Intersection:        --> [a] [b] [a[a+b]b]
+    ///                                    ^---^
+    /// Complement:          --> [a] [b] [a[a+b]b] [b[a+b]a]
+    ///                          ^-^     ^-^             ^ ^
+    /// SymmetricDifference: --> [a] [b] [a[a+b]b]
+    ///                          ^-^ ^-^ ^-^   ^-^
+    /// ```
+    fn surface_intersection(&self, index: usize, inside: &[bool], inside_count: usize)
+                            -> Option<bool> {
+        // The number of the other shapes containing the intersection
+        let others = inside_count - inside[index] as usize;
 
-                    *closer_index += 1;
+        match self.operation {
+            SetOperation::Union => {
+                if others > 0 {
+                    None
                 } else {
-                    self.data.index_a += 1;
-
-                    if self.data.shape_b.is_point_inside(&unwrapped_a.location) {
-                        return None;
-                    }
-
-                    return intersection_a;
+                    Some(false)
                 }
-            } else {
-                if intersection_b.is_some() {
-                    let unwrapped_b = intersection_b.unwrap();
-                    self.data.index_b += 1;
-
-                    if self.data.shape_a.is_point_inside(&unwrapped_b.location) {
-                        return None;
-                    }
-                }
-
-                return intersection_b;
             }
-        }
-    }
-}
-
-struct IntersectionIterator<P: CustomPoint<V>, V: CustomVector<P>> {
-    data: ComposableShapeIterator<P, V>,
-}
-
-impl<P: CustomPoint<V>, V: CustomVector<P>> IntersectionIterator<P, V> {
-    fn new(shape_a: Arc<Box<Shape<P, V>>>,
-           shape_b: Arc<Box<Shape<P, V>>>,
-           provider_a: IntersectionProvider<P, V>,
-           provider_b: IntersectionProvider<P, V>)
-           -> IntersectionIterator<P, V> {
-        IntersectionIterator {
-            data: ComposableShapeIterator::new(shape_a, shape_b, provider_a, provider_b),
-        }
-    }
-}
-
-impl<P: CustomPoint<V>, V: CustomVector<P>>
-        Iterator for IntersectionIterator<P, V> {
-    type Item = Intersection<P, V>;
-
-// Should return the following intersections:
-// --> [a] [b] [a[a+b]b]
-//               ^---^
-    #[allow(useless_let_if_seq)]
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let intersection_a = self.data.provider_a.get(self.data.index_a);
-            let intersection_b = self.data.provider_b.get(self.data.index_b);
-
-            if intersection_a.is_some() {
-                if intersection_b.is_some() {
-                    let unwrapped_a = intersection_a.unwrap();
-                    let unwrapped_b = intersection_b.unwrap();
-                    let closer: Intersection<P, V>;
-                    let closer_index: &mut usize;
-                    let further_shape: &Shape<P, V>;
-
-                    if unwrapped_a.distance < unwrapped_b.distance {
-                        closer = unwrapped_a;
-                        closer_index = &mut self.data.index_a;
-                        further_shape = self.data.shape_b.as_ref().as_ref();
-                    } else {
-                        closer = unwrapped_b;
-                        closer_index = &mut self.data.index_b;
-                        further_shape = self.data.shape_a.as_ref().as_ref();
-                    }
-
-                    *closer_index += 1;
-
-                    if further_shape.is_point_inside(&closer.location) {
-                        return Some(closer);
-                    }
+            SetOperation::Intersection => {
+                if others == self.shapes.len() - 1 {
+                    Some(false)
                 } else {
-                    self.data.index_a += 1;
-
-                    if self.data.shape_b.is_point_inside(&intersection_a.unwrap().location) {
-                        return intersection_a;
-                    }
-
-                    return None;
+                    None
                 }
-            } else {
-                if intersection_b.is_some() {
-                    self.data.index_b += 1;
-
-                    if self.data.shape_a.is_point_inside(&intersection_b.unwrap().location) {
-                        return intersection_b;
-                    }
+            }
+            SetOperation::Complement => {
+                let subtracted = others - (index != 0 && inside[0]) as usize;
+
+                if subtracted > 0 {
+                    None
+                } else if index == 0 {
+                    Some(false)
+                } else if inside[0] {
+                    Some(true)
+                } else {
+                    None
                 }
-
-                return None;
             }
+            SetOperation::SymmetricDifference => Some(others % 2 == 1),
         }
     }
-}
 
-struct ComplementIterator<P: CustomPoint<V>, V: CustomVector<P>> {
-    data: ComposableShapeIterator<P, V>,
-}
+    #[allow(unused_variables)]
+    pub fn intersect_linear(location: &P,
+                               direction: &V,
+                               vacuum: &Material<P, V>,
+                               shape: &Shape<P, V>,
+                               intersect: Intersector<P, V>)
+                               -> GeneralIntersectionMarcher<P, V> {
+        let composed: &ComposableShape<P, V> =
+            shape.as_any().downcast_ref::<ComposableShape<P, V>>().unwrap();
+        let mut providers: SmallVec<[IntersectionProvider<P, V>; 8]> = SmallVec::new();
+        // Whether the ray is inside each of the shapes, toggled by every intersection with it,
+        // so that the intersections are classified without testing them against every shape
+        let mut inside: SmallVec<[bool; 8]> = SmallVec::new();
+
+        for (index, shape) in composed.shapes.iter().enumerate() {
+            let mut provider = intersect(vacuum, shape.as_ref());
+            let shape_inside = shape.is_point_inside(location);
+            // The result lies within these shapes, a ray missing one of them misses it as well
+            let bounding = match composed.operation {
+                SetOperation::Intersection => true,
+                SetOperation::Complement => index == 0,
+                SetOperation::Union | SetOperation::SymmetricDifference => false,
+            };
 
-impl<P: CustomPoint<V>, V: CustomVector<P>> ComplementIterator<P, V> {
-    fn new(shape_a: Arc<Box<Shape<P, V>>>,
-           shape_b: Arc<Box<Shape<P, V>>>,
-           provider_a: IntersectionProvider<P, V>,
-           provider_b: IntersectionProvider<P, V>)
-           -> ComplementIterator<P, V> {
-        ComplementIterator {
-            data: ComposableShapeIterator::new(shape_a, shape_b, provider_a, provider_b),
+            if bounding && !shape_inside && provider.peek().is_none() {
+                return PossiblyImmediateIterator::Immediate(SmallVec::new().into_iter());
+            }
+
+            providers.push(provider);
+            inside.push(shape_inside);
         }
-    }
-}
 
-impl<P: CustomPoint<V>, V: CustomVector<P>> Iterator for ComplementIterator<P, V> {
-    type Item = Intersection<P, V>;
+        let mut inside_count = inside.iter().filter(|inside| **inside).count();
+        // Merging the intersections right away avoids boxing a lazy iterator for each ray
+        let mut intersections: SmallVec<ImmediateIntersections<P, V>> = SmallVec::new();
 
-    // Should return the following intersections:
-    // --> [a] [b] [a[a+b]b] [b[a+b]a]
-    //     ^-^     ^-^             ^ ^
-    fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let intersection_a = self.data.provider_a.get(self.data.index_a);
-            let intersection_b = self.data.provider_b.get(self.data.index_b);
-
-            if intersection_a.is_some() {
-                if intersection_b.is_some() {
-                    let unwrapped_a = intersection_a.unwrap();
-                    let unwrapped_b = intersection_b.unwrap();
-
-                    if unwrapped_a.distance < unwrapped_b.distance {
-                        self.data.index_a += 1;
-
-                        if !self.data.shape_b.is_point_inside(&unwrapped_a.location) {
-                            return Some(unwrapped_a);
-                        }
-                    } else {
-                        self.data.index_b += 1;
-
-                        if self.data.shape_a.is_point_inside(&unwrapped_b.location) {
-                            let mut inverted_b = unwrapped_b;
-                            inverted_b.normal = -inverted_b.normal;
-                            return Some(inverted_b);
-                        }
-                    }
-                } else {
-                    return intersection_a;
-                }
-            } else {
-                if intersection_b.is_some() {
-                    let unwrapped_b = intersection_b.unwrap();
-
-                    self.data.index_b += 1;
+            let mut closest: Option<(usize, F)> = None;
 
-                    if self.data.shape_a.is_point_inside(&unwrapped_b.location) {
-                        let mut inverted_b = unwrapped_b;
-                        inverted_b.normal = -inverted_b.normal;
-                        return Some(inverted_b);
+            for (index, provider) in providers.iter_mut().enumerate() {
+                if let Some(intersection) = provider.peek() {
+                    if closest.map_or(true, |(_, distance)| intersection.distance < distance) {
+                        closest = Some((index, intersection.distance));
                     }
                 }
-
-                return None;
             }
-        }
-    }
-}
 
-struct SymmetricDifferenceIterator<P: CustomPoint<V>, V: CustomVector<P>> {
-    data: ComposableShapeIterator<P, V>,
-}
-
-impl<P: CustomPoint<V>, V: CustomVector<P>> SymmetricDifferenceIterator<P, V> {
-    fn new(shape_a: Arc<Box<Shape<P, V>>>,
-           shape_b: Arc<Box<Shape<P, V>>>,
-           provider_a: IntersectionProvider<P, V>,
-           provider_b: IntersectionProvider<P, V>)
-           -> SymmetricDifferenceIterator<P, V> {
-        SymmetricDifferenceIterator {
-            data: ComposableShapeIterator::new(shape_a, shape_b, provider_a, provider_b),
-        }
-    }
-}
-
-impl<P: CustomPoint<V>, V: CustomVector<P>>
-        Iterator for SymmetricDifferenceIterator<P, V> {
-    type Item = Intersection<P, V>;
-
-// Should return the following intersections:
-// --> [a] [b] [a[a+b]b]
-//     ^-^ ^-^ ^-^   ^-^
-    #[allow(useless_let_if_seq)]
-    fn next(&mut self) -> Option<Self::Item> {
-        let intersection_a = self.data.provider_a.get(self.data.index_a);
-        let intersection_b = self.data.provider_b.get(self.data.index_b);
-
-        if intersection_a.is_some() {
-            if intersection_b.is_some() {
-                let unwrapped_a = intersection_a.unwrap();
-                let unwrapped_b = intersection_b.unwrap();
-                let closer: Intersection<P, V>;
-                let closer_index: &mut usize;
-                let further_shape: &Shape<P, V>;
-
-                if unwrapped_a.distance < unwrapped_b.distance {
-                    closer = unwrapped_a;
-                    closer_index = &mut self.data.index_a;
-                    further_shape = self.data.shape_b.as_ref().as_ref();
-                } else {
-                    closer = unwrapped_b;
-                    closer_index = &mut self.data.index_b;
-                    further_shape = self.data.shape_a.as_ref().as_ref();
-                }
-
-                if further_shape.is_point_inside(&closer.location) {
-                    *closer_index += 1;
-                    let mut closer_inverted = closer;
-                    closer_inverted.normal = -closer_inverted.normal;
-                    return Some(closer_inverted);
-                }
-
-                *closer_index += 1;
-
-                Some(closer)
-            } else {
-                let unwrapped_a = intersection_a.unwrap();
-
-                self.data.index_a += 1;
+            let index = match closest {
+                Some((index, _)) => index,
+                None => break,
+            };
+            let mut intersection = providers[index].next().unwrap();
 
-                if self.data.shape_b.is_point_inside(&unwrapped_a.location) {
-                    let mut closer_inverted = unwrapped_a;
-                    closer_inverted.normal = -closer_inverted.normal;
-                    return Some(closer_inverted);
+            if let Some(inverted) = composed.surface_intersection(index, &inside, inside_count) {
+                if inverted {
+                    intersection.normal = -intersection.normal;
                 }
 
-                intersection_a
+                intersections.push(intersection);
             }
-        } else {
-            if intersection_b.is_some() {
-                let unwrapped_b = intersection_b.unwrap();
-
-                self.data.index_b += 1;
 
-                if self.data.shape_a.is_point_inside(&unwrapped_b.location) {
-                    let mut closer_inverted = unwrapped_b;
-                    closer_inverted.normal = -closer_inverted.normal;
-                    return Some(closer_inverted);
-                }
+            if inside[index] {
+                inside_count -= 1;
+            } else {
+                inside_count += 1;
             }
 
-            intersection_b
+            inside[index] = !inside[index];
         }
-    }
-}
-
-#[derive(Debug)]
-pub struct ComposableShape<P: CustomPoint<V>, V: CustomVector<P>> {
-    pub a: Arc<Box<Shape<P, V>>>,
-    pub b: Arc<Box<Shape<P, V>>>,
-    pub operation: SetOperation,
-    marker: PhantomData<Shape<P, V>>,
-}
-
-shape!(ComposableShape<P: 'static + CustomPoint<V>, V: 'static + CustomVector<P>>);
-
-impl<P: CustomPoint<V>, V: CustomVector<P>> ComposableShape<P, V> {
-    pub fn new<A: Shape<P, V> + 'static, B: Shape<P, V> + 'static>
-        (a: A,
-         b: B,
-         operation: SetOperation)
-         -> ComposableShape<P, V> {
-        ComposableShape {
-            a: Arc::new(Box::new(a)),
-            b: Arc::new(Box::new(b)),
-            operation: operation,
-            marker: PhantomData,
-        }
-    }
 
-    pub fn of<I: IntoIterator<Item = Box<Shape<P, V>>>>(shapes: I,
-                                                          operation: SetOperation)
-                                                          -> ComposableShape<P, V> {
-        const PANIC: &str = "2 or more `Shape`s are needed to construct a `ComposableShape`.";
-        let mut shapes = shapes.into_iter();
-        let mut result = ComposableShape {
-            a: Arc::new(shapes.next().expect(PANIC)),
-            b: Arc::new(shapes.next().expect(PANIC)),
-            operation: operation,
-            marker: PhantomData,
-        };
-
-        for shape in shapes {
-            result = ComposableShape {
-                a: Arc::new(Box::new(result)),
-                b: Arc::new(shape),
-                operation: operation,
-                marker: PhantomData,
-            };
-        }
-
-        result
-    }
-
-    #[allow(unused_variables)]
-    pub fn intersect_linear(location: &P,
-                               direction: &V,
-                               vacuum: &Material<P, V>,
-                               shape: &Shape<P, V>,
-                               intersect: Intersector<P, V>)
-                               -> GeneralIntersectionMarcher<P, V> {
-        let composed: &ComposableShape<P, V> =
-            shape.as_any().downcast_ref::<ComposableShape<P, V>>().unwrap();
-        let provider_a = intersect(vacuum, composed.a.as_ref().as_ref());
-        let provider_b = intersect(vacuum, composed.b.as_ref().as_ref());
-        PossiblyImmediateIterator::Dynamic(match composed.operation {
-            SetOperation::Union => {
-                Box::new(UnionIterator::new(Arc::clone(&composed.a),
-                                            Arc::clone(&composed.b),
-                                            provider_a,
-                                            provider_b))
-            }
-            SetOperation::Intersection => {
-                Box::new(IntersectionIterator::new(Arc::clone(&composed.a),
-                                                   Arc::clone(&composed.b),
-                                                   provider_a,
-                                                   provider_b))
-            }
-            SetOperation::Complement => {
-                Box::new(ComplementIterator::new(Arc::clone(&composed.a),
-                                                 Arc::clone(&composed.b),
-                                                 provider_a,
-                                                 provider_b))
-            }
-            SetOperation::SymmetricDifference => {
-                Box::new(SymmetricDifferenceIterator::new(Arc::clone(&composed.a),
-                                                          Arc::clone(&composed.b),
-                                                          provider_a,
-                                                          provider_b))
-            }
-        })
+        PossiblyImmediateIterator::Immediate(intersections.into_iter())
     }
 }
 
 impl<P: 'static + CustomPoint<V>, V: 'static + CustomVector<P>> Shape<P, V>
         for ComposableShape<P, V> {
     fn is_point_inside(&self, point: &P) -> bool {
+        let mut inside = self.shapes.iter().map(|shape| shape.is_point_inside(point));
+
         match self.operation {
-            SetOperation::Union =>
-                self.a.is_point_inside(point) || self.b.is_point_inside(point),
-            SetOperation::Intersection =>
-                self.a.is_point_inside(point) && self.b.is_point_inside(point),
+            SetOperation::Union => inside.any(|inside| inside),
+            SetOperation::Intersection => inside.all(|inside| inside),
             SetOperation::Complement =>
-                self.a.is_point_inside(point) && !self.b.is_point_inside(point),
+                inside.next().unwrap() && !inside.any(|inside| inside),
             SetOperation::SymmetricDifference =>
-                self.a.is_point_inside(point) ^ self.b.is_point_inside(point),
+                inside.filter(|inside| *inside).count() % 2 == 1,
         }
     }
 }
@@ -840,6 +609,11 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> HalfSpace<P, V> {
         Self::new(plane, identifier)
     }
 
+    /// The bounding plane with its normal pointing out of the half-space
+    pub fn boundary(&self) -> Hyperplane<P, V> {
+        Hyperplane::new(self.plane.normal * -self.signum, self.plane.constant * -self.signum)
+    }
+
     pub fn intersect_linear(location: &P,
                                direction: &V,
                                vacuum: &Material<P, V>,
@@ -880,6 +654,133 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> Shape<P, V> for HalfSpace<P, V> {
     }
 }
 
+/// The intersection of half-spaces, each bounded by a hyperplane with an outward unit normal.
+/// A point is inside when `dot(normal, point) + constant <= 0` for all of the planes.
+#[derive(Debug)]
+pub struct ConvexPolytope<P: CustomPoint<V>, V: CustomVector<P>> {
+    pub planes: Vec<Hyperplane<P, V>>,
+}
+
+shape!(ConvexPolytope<P: CustomPoint<V>, V: CustomVector<P>>);
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> ConvexPolytope<P, V> {
+    /// The normals of the planes point outwards, they are normalized.
+    pub fn new<I: IntoIterator<Item = Hyperplane<P, V>>>(planes: I) -> Self {
+        let planes = planes.into_iter()
+            .map(|plane| {
+                let length = plane.normal.norm();
+
+                Hyperplane::new(plane.normal / length, plane.constant / length)
+            })
+            .collect();
+
+        ConvexPolytope {
+            planes: planes,
+        }
+    }
+
+    pub fn of<I: IntoIterator<Item = HalfSpace<P, V>>>(half_spaces: I) -> Self {
+        Self::new(half_spaces.into_iter().map(|half_space| half_space.boundary()))
+    }
+
+    /// A polytope whose facets are all at the same distance from the center,
+    /// the facets are perpendicular to the normals.
+    pub fn new_with_facet_normals(center: &P, normals: &[V], inradius: F) -> Self {
+        Self::new(normals.iter().map(|normal| {
+            let normal = normal.normalize();
+
+            Hyperplane::new_with_point(normal, &(normal * inradius).translate(center))
+        }))
+    }
+
+    /// An axis-aligned box with the given lengths of its edges
+    pub fn new_box(center: &P, dimensions: &V) -> Self {
+        let dimension = <V as Dimension>::dimension(None);
+        let mut planes = Vec::with_capacity(2 * dimension);
+
+        for axis_index in 0..dimension {
+            let mut axis = V::repeat(<F as Zero>::zero());
+            axis[axis_index] = <F as One>::one();
+            let half_length = dimensions[axis_index] / (<F as One>::one() + <F as One>::one());
+
+            for normal in &[axis, -axis] {
+                planes.push(Hyperplane::new_with_point(*normal,
+                                                       &(*normal * half_length).translate(center)));
+            }
+        }
+
+        Self::new(planes)
+    }
+
+    /// The slab method, the ray enters the polytope after entering all of the half-spaces
+    /// and leaves it when leaving the first one.
+    #[allow(unused_variables)]
+    pub fn intersect_linear(location: &P,
+                               direction: &V,
+                               vacuum: &Material<P, V>,
+                               shape: &Shape<P, V>,
+                               intersect: Intersector<P, V>)
+                               -> GeneralIntersectionMarcher<P, V> {
+        let polytope: &ConvexPolytope<P, V> =
+            shape.as_any().downcast_ref::<ConvexPolytope<P, V>>().unwrap();
+        let mut enter: Option<(F, &V)> = None;
+        let mut exit: Option<(F, &V)> = None;
+
+        for plane in &polytope.planes {
+            // Positive inside of the half-space
+            let depth = -(na::dot(&plane.normal, location.as_vector()) + plane.constant);
+            let speed = na::dot(&plane.normal, direction);
+
+            if speed == <F as Zero>::zero() {
+                if depth < <F as Zero>::zero() {
+                    // Parallel to the plane and outside of it
+                    return PossiblyImmediateIterator::Immediate(SmallVec::new().into_iter());
+                }
+
+                continue;
+            }
+
+            let t = depth / speed;
+
+            if speed < <F as Zero>::zero() {
+                if enter.map_or(true, |(t_enter, _)| t > t_enter) {
+                    enter = Some((t, &plane.normal));
+                }
+            } else if exit.map_or(true, |(t_exit, _)| t < t_exit) {
+                exit = Some((t, &plane.normal));
+            }
+        }
+
+        let mut intersections = SmallVec::with_capacity(2);
+
+        if let (Some((t_enter, _)), Some((t_exit, _))) = (enter, exit) {
+            if t_enter > t_exit {
+                return PossiblyImmediateIterator::Immediate(intersections.into_iter());
+            }
+        }
+
+        for &(t, normal) in enter.iter().chain(exit.iter()) {
+            // Don't trace in the opposite direction
+            if t >= <F as Zero>::zero() {
+                intersections.push(Intersection::new((*direction * t).translate(location),
+                                                     *direction,
+                                                     *normal,
+                                                     t));
+            }
+        }
+
+        PossiblyImmediateIterator::Immediate(intersections.into_iter())
+    }
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> Shape<P, V> for ConvexPolytope<P, V> {
+    fn is_point_inside(&self, point: &P) -> bool {
+        self.planes.iter().all(|plane| {
+            na::dot(&plane.normal, point.as_vector()) + plane.constant <= <F as Zero>::zero()
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Cylinder<P: CustomPoint<V>, V: CustomVector<P>> {
     pub center: P,  // Must be normalized; TODO: update after upgrading nalgebra
@@ -1072,6 +973,36 @@ mod tests {
         assert!(marcher.next().is_none());
     }
 
+    #[test]
+    fn intersect_composable_linear() {
+        let location = Point2::new(0.0, 0.0);
+        let direction = Vector2::new(1.0, 0.0);
+        let lens = ComposableShape::new(Sphere::new(Point2::new(2.0, 0.0), 1.0),
+                                        Sphere::new(Point2::new(3.0, 0.0), 1.0),
+                                        SetOperation::Intersection);
+        let intersect_sphere = |material: &Material<Point2<F>, Vector2<F>>,
+                                shape: &Shape<Point2<F>, Vector2<F>>| {
+            Sphere::intersect_linear(&location, &direction, material, shape,
+                                     &|_, _| { unimplemented!() }).peekable()
+        };
+        let mut marcher = ComposableShape::intersect_linear(
+            &location,
+            &direction,
+            &Vacuum::new(),
+            &lens,
+            &intersect_sphere
+        );
+
+        let first = marcher.next().unwrap();
+        let second = marcher.next().unwrap();
+
+        assert!(first.distance.approx_eq_ulps(&2.0, 2));
+        assert_eq!(first.normal, Vector2::new(-1.0, 0.0));
+        assert!(second.distance.approx_eq_ulps(&3.0, 2));
+        assert_eq!(second.normal, Vector2::new(1.0, 0.0));
+        assert!(marcher.next().is_none());
+    }
+
     #[test]
     fn intersect_plane_linear() {
         let mut marcher = Hyperplane::intersect_linear(
@@ -1146,4 +1077,118 @@ mod tests {
         assert!(second.distance.approx_eq_ulps(&3.0, 2));
         assert!(marcher.next().is_none());
     }
+
+    #[test]
+    fn intersect_composable_n_ary_linear() {
+        let location = Point2::new(0.0, 0.0);
+        let direction = Vector2::new(1.0, 0.0);
+        let shapes: Vec<Box<Shape<Point2<F>, Vector2<F>>>> = vec![
+            Box::new(Sphere::new(Point2::new(4.0, 0.0), 3.5)),
+            Box::new(Sphere::new(Point2::new(2.0, 0.0), 1.0)),
+            Box::new(Sphere::new(Point2::new(6.0, 0.0), 1.0)),
+        ];
+        let shape = ComposableShape::of(shapes, SetOperation::Complement);
+        let intersect_sphere = |material: &Material<Point2<F>, Vector2<F>>,
+                                shape: &Shape<Point2<F>, Vector2<F>>| {
+            Sphere::intersect_linear(&location, &direction, material, shape,
+                                     &|_, _| { unimplemented!() }).peekable()
+        };
+        let intersections: Vec<Intersection<Point2<F>, Vector2<F>>> =
+            ComposableShape::intersect_linear(&location, &direction, &Vacuum::new(), &shape,
+                                              &intersect_sphere).collect();
+        let distances = [0.5, 1.0, 3.0, 5.0, 7.0, 7.5];
+
+        assert_eq!(intersections.len(), distances.len());
+
+        for (intersection, distance) in intersections.iter().zip(distances.iter()) {
+            assert!(intersection.distance.approx_eq(distance));
+        }
+
+        // The normals of the subtracted shapes point inside of them
+        assert_eq!(intersections[1].normal, Vector2::new(1.0, 0.0));
+        assert_eq!(intersections[2].normal, Vector2::new(-1.0, 0.0));
+        assert!(shape.is_point_inside(&Point2::new(4.0, 0.0)));
+        assert!(!shape.is_point_inside(&Point2::new(2.0, 0.0)));
+    }
+
+    #[test]
+    fn intersect_complement_tail_linear() {
+        // The subtracted shape runs out of intersections before the first shape does
+        let location = Point2::new(0.0, 0.0);
+        let direction = Vector2::new(1.0, 0.0);
+        let shape = ComposableShape::new(Sphere::new(Point2::new(5.0, 0.0), 3.0),
+                                         Sphere::new(Point2::new(2.0, 0.0), 1.5),
+                                         SetOperation::Complement);
+        let intersect_sphere = |material: &Material<Point2<F>, Vector2<F>>,
+                                shape: &Shape<Point2<F>, Vector2<F>>| {
+            Sphere::intersect_linear(&location, &direction, material, shape,
+                                     &|_, _| { unimplemented!() }).peekable()
+        };
+        let intersections: Vec<Intersection<Point2<F>, Vector2<F>>> =
+            ComposableShape::intersect_linear(&location, &direction, &Vacuum::new(), &shape,
+                                              &intersect_sphere).take(4).collect();
+
+        assert_eq!(intersections.len(), 2);
+        assert!(intersections[0].distance.approx_eq(&3.5));
+        assert_eq!(intersections[0].normal, Vector2::new(-1.0, 0.0));
+        assert!(intersections[1].distance.approx_eq(&8.0));
+        assert_eq!(intersections[1].normal, Vector2::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn intersect_composable_misses_bounding_shape() {
+        let location = Point2::new(0.0, 0.0);
+        let direction = Vector2::new(1.0, 0.0);
+        let intersect_sphere = |material: &Material<Point2<F>, Vector2<F>>,
+                                shape: &Shape<Point2<F>, Vector2<F>>| {
+            Sphere::intersect_linear(&location, &direction, material, shape,
+                                     &|_, _| { unimplemented!() }).peekable()
+        };
+
+        for operation in &[SetOperation::Intersection, SetOperation::Complement] {
+            let shape = ComposableShape::new(Sphere::new(Point2::new(4.0, 5.0), 1.0),
+                                             Sphere::new(Point2::new(4.0, 0.0), 1.0),
+                                             *operation);
+            let mut marcher = ComposableShape::intersect_linear(&location, &direction,
+                                                                &Vacuum::new(), &shape,
+                                                                &intersect_sphere);
+
+            assert!(marcher.next().is_none());
+        }
+    }
+
+    #[test]
+    fn intersect_polytope_linear() {
+        let polytope = ConvexPolytope::new_box(&Point2::new(3.0, 0.0), &Vector2::new(2.0, 4.0));
+        let mut marcher = ConvexPolytope::intersect_linear(
+            &Point2::new(0.0, 1.0),
+            &Vector2::new(1.0, 0.0),
+            &Vacuum::new(),
+            &polytope,
+            &|_, _| { unimplemented!() }
+        );
+
+        let first = marcher.next().unwrap();
+        let second = marcher.next().unwrap();
+
+        assert_eq!(first.location, Point2::new(2.0, 1.0));
+        assert_eq!(first.normal, Vector2::new(-1.0, 0.0));
+        assert!(first.distance.approx_eq_ulps(&2.0, 2));
+        assert_eq!(second.location, Point2::new(4.0, 1.0));
+        assert_eq!(second.normal, Vector2::new(1.0, 0.0));
+        assert!(second.distance.approx_eq_ulps(&4.0, 2));
+        assert!(marcher.next().is_none());
+        assert!(polytope.is_point_inside(&Point2::new(3.5, -1.5)));
+        assert!(!polytope.is_point_inside(&Point2::new(3.5, 2.5)));
+
+        let mut missing = ConvexPolytope::intersect_linear(
+            &Point2::new(0.0, 3.0),
+            &Vector2::new(1.0, 0.0),
+            &Vacuum::new(),
+            &polytope,
+            &|_, _| { unimplemented!() }
+        );
+
+        assert!(missing.next().is_none());
+    }
 }
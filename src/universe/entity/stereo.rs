@@ -0,0 +1,137 @@
+use ::F;
+use na;
+use na::Dot;
+use palette::Rgb;
+use std::ops::Mul;
+use std::ops::Sub;
+
+/// One of the two eyes of a stereo camera
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// How the images of both eyes are arranged in the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// The left eye on the left half of the screen, the right eye on the right half
+    SideBySide,
+    /// The left eye on the top half of the screen, the right eye on the bottom half
+    TopBottom,
+    /// Both eyes over the whole screen, the left eye in the red channel and the right eye
+    /// in the green and blue channels, for red/cyan glasses
+    Anaglyph,
+}
+
+/// Renders the scene from two parallel eyes offset along the left direction of the camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    /// The distance between the eyes
+    pub eye_separation: F,
+}
+
+impl Stereo {
+    pub fn new(layout: StereoLayout, eye_separation: F) -> Self {
+        Stereo {
+            layout: layout,
+            eye_separation: eye_separation,
+        }
+    }
+
+    /// The offset of the eye along the left direction of the camera
+    pub fn eye_offset(&self, eye: Eye) -> F {
+        match eye {
+            Eye::Left => self.eye_separation / 2.0,
+            Eye::Right => -self.eye_separation / 2.0,
+        }
+    }
+
+    /// Finds the eye the pixel is seen by and the position of the pixel within the half of
+    /// the screen of that eye. The second half gets the extra pixel of odd dimensions.
+    /// The anaglyph covers the whole screen with the requested eye, `None` if no eye was
+    /// requested, e.g. for the debug render passes.
+    pub fn viewport(&self,
+                    eye: Option<Eye>,
+                    screen_x: i32,
+                    screen_y: i32,
+                    screen_width: i32,
+                    screen_height: i32)
+                    -> (Option<Eye>, i32, i32, i32, i32) {
+        match self.layout {
+            StereoLayout::SideBySide => {
+                let half_width = (screen_width / 2).max(1);
+
+                if screen_x < half_width {
+                    (Some(Eye::Left), screen_x, screen_y, half_width, screen_height)
+                } else {
+                    (Some(Eye::Right), screen_x - half_width, screen_y,
+                     screen_width - half_width, screen_height)
+                }
+            }
+            StereoLayout::TopBottom => {
+                let half_height = (screen_height / 2).max(1);
+
+                // The rows start at the bottom of the screen
+                if screen_y < half_height {
+                    (Some(Eye::Right), screen_x, screen_y, screen_width, half_height)
+                } else {
+                    (Some(Eye::Left), screen_x, screen_y - half_height,
+                     screen_width, screen_height - half_height)
+                }
+            }
+            StereoLayout::Anaglyph => (eye, screen_x, screen_y, screen_width, screen_height),
+        }
+    }
+}
+
+/// The direction of the eye offset of the ray in an omni-directional stereo panorama, the
+/// left direction turned with the longitude of the ray in the plane of the forward and left
+/// directions. It shrinks towards the poles, where both eyes meet, instead of flipping.
+pub fn omnidirectional_left<V>(direction: &V, forward: &V, left: &V) -> V
+    where V: Copy + Dot<F> + Mul<F, Output=V> + Sub<V, Output=V>
+{
+    *left * na::dot(direction, forward) - *forward * na::dot(direction, left)
+}
+
+/// Combines the colors seen by both eyes into a red/cyan anaglyph
+pub fn anaglyph(left: Rgb<F>, right: Rgb<F>) -> Rgb<F> {
+    Rgb::new(left.red, right.green, right.blue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_by_side_viewports() {
+        let stereo = Stereo::new(StereoLayout::SideBySide, 0.1);
+
+        assert_eq!(stereo.viewport(None, 10, 5, 100, 50), (Some(Eye::Left), 10, 5, 50, 50));
+        assert_eq!(stereo.viewport(None, 60, 5, 100, 50), (Some(Eye::Right), 10, 5, 50, 50));
+        // The last column of an odd width stays within the viewport of the right eye
+        assert_eq!(stereo.viewport(None, 100, 5, 101, 50), (Some(Eye::Right), 50, 5, 51, 50));
+    }
+
+    #[test]
+    fn omnidirectional_offsets_follow_longitude() {
+        use universe::d3::Vector3;
+
+        let (forward, left) = (Vector3::x(), Vector3::y());
+
+        assert_eq!(omnidirectional_left(&forward, &forward, &left), left);
+        assert_eq!(omnidirectional_left(&left, &forward, &left), -forward);
+        assert_eq!(omnidirectional_left(&-forward, &forward, &left), -left);
+        assert_eq!(omnidirectional_left(&Vector3::z(), &forward, &left), Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn top_bottom_viewports() {
+        let stereo = Stereo::new(StereoLayout::TopBottom, 0.1);
+
+        assert_eq!(stereo.viewport(None, 10, 40, 100, 50), (Some(Eye::Left), 10, 15, 100, 25));
+        assert_eq!(stereo.viewport(None, 10, 5, 100, 50), (Some(Eye::Right), 10, 5, 100, 25));
+        assert_eq!(stereo.viewport(None, 10, 50, 100, 51), (Some(Eye::Left), 10, 25, 100, 26));
+    }
+}
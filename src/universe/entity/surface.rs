@@ -1,18 +1,32 @@
 use ::F;
 use std;
 use std::sync::Arc;
+use std::time::Duration;
 use palette::ComponentWise;
 use palette::blend::PreAlpha;
 use num::traits::NumCast;
 use std::marker::PhantomData;
-use palette;
 use palette::Rgba;
 use palette::Blend;
 use image::DynamicImage;
 use image::GenericImage;
+use image::ImageResult;
+use image::hdr::HDRDecoder;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use universe::entity::exr;
+use noise::{Perlin, NoiseModule, Seedable};
 use universe::entity::shape::TracingContext;
 use universe::entity::shape::ColorTracingContext;
 use universe::entity::shape::PathTracingContext;
+use universe::entity::material::noise_coordinates;
+use universe::entity::medium::same_traceable;
+use universe::spectrum;
+use universe::inspection;
+use universe::inspection::RayKind;
+use universe::aov;
+use universe::spectrum::RefractiveIndexProvider;
 use util;
 use util::CustomFloat;
 use util::CustomPoint;
@@ -20,6 +34,10 @@ use util::CustomVector;
 use num::Zero;
 use num::One;
 use na::Cast;
+use na::Dimension;
+use na::Dot;
+use na::Norm;
+use na::Repeat;
 use na::Point2;
 use na::ApproxEq;
 use palette::Rgb;
@@ -29,6 +47,11 @@ pub type ReflectionRatioProvider<P, V> = (Fn(&TracingContext<P, V>) -> F) + Send
 pub type ReflectionDirectionProvider<P, V> = (Fn(&TracingContext<P, V>) -> V) + Send + Sync;
 pub type ThresholdDirectionProvider<P, V> = (Fn(&TracingContext<P, V>) -> V) + Send + Sync;
 pub type SurfaceColorProvider<P, V> = (Fn(&TracingContext<P, V>) -> Rgba<F>) + Send + Sync;
+/// Computes the normal used for shading, facing the same side as `intersection.normal`
+pub type NormalProvider<P, V> = (Fn(&TracingContext<P, V>) -> V) + Send + Sync;
+/// Computes the height of a bump map at the given location, also used as a general scalar field
+/// by the procedural textures
+pub type HeightProvider<P> = (Fn(&P, &Duration) -> F) + Send + Sync;
 pub type ThresholdPathModifier<P, V> = (Fn(&PathTracingContext<P, V>, &mut P, &mut V)) + Send + Sync;
 
 pub trait Surface<P: CustomPoint<V>, V: CustomVector<P>>: Send + Sync {
@@ -41,6 +64,7 @@ pub struct ComposableSurface<P: CustomPoint<V>, V: CustomVector<P>> {
     pub reflection_direction: Arc<ReflectionDirectionProvider<P, V>>,
     pub threshold_direction: Arc<ThresholdDirectionProvider<P, V>>,
     pub surface_color: Arc<SurfaceColorProvider<P, V>>,
+    pub normal: Arc<NormalProvider<P, V>>,
 }
 
 impl<P: CustomPoint<V>, V: CustomVector<P>> ComposableSurface<P, V> {
@@ -59,8 +83,26 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> ComposableSurface<P, V> {
         surface_color(context)
     }
 
+    /// Creates a context with the intersection normals replaced by the perturbed normal.
+    /// The geometric normals are still used to offset the origins of the traced rays.
+    fn get_shading_context<'a>(&self, context: &TracingContext<'a, P, V>) -> TracingContext<'a, P, V> {
+        let normal = (self.normal)(context);
+        let mut result = *context;
+
+        result.intersection_normal_closer =
+            if context.intersection_normal_closer.dot(&context.intersection.normal) >= <F as Zero>::zero() {
+                normal
+            } else {
+                -normal
+            };
+        result.intersection.normal = normal;
+
+        result
+    }
+
     fn get_intersection_color(&self,
                               reflection_ratio: F,
+                              shading: &TracingContext<P, V>,
                               context: &ColorTracingContext<P, V>)
                               -> Option<Rgba<F>> {
         if reflection_ratio >= <F as One>::one() {
@@ -68,63 +110,57 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> ComposableSurface<P, V> {
         }
 
         Some({
-            let surface_color = self.get_surface_color(&context.general);
-            let surface_color_data: [u8; 4] = surface_color.to_pixel();
-            let surface_color_alpha = surface_color_data[3];
+            let surface_color = self.get_surface_color(shading);
 
-            if surface_color_alpha == std::u8::MAX {
+            if surface_color.alpha >= <F as One>::one() {
                 surface_color
             } else {
                 let trace = context.trace;
 
-                let mut transitioned_direction = (self.threshold_direction)(&context.general);
+                let mut transitioned_direction = (self.threshold_direction)(shading);
                 // Offset the new origin, so it doesn't hit the same shape over and over
                 // The question is -- is there a better way? I think not.
                 let new_origin = context.general.intersection.location +
                                  -context.general.intersection_normal_closer * <F as ApproxEq<F>>::approx_epsilon(None) * Cast::from(128.0);
 
                 // Apply the material transition
-                let destination_traceable = if context.general.exiting {
-                    if let Some(result) = (context.material_at)(&new_origin) {
-                        result
-                    } else {
-                        return None;
-                    }
+                let destination_media = context.media.transition(context.general.intersection_traceable,
+                                                                 context.general.exiting);
+                let destination_traceable = if let Some(result) = destination_media.current() {
+                    result
                 } else {
-                    context.general.intersection_traceable
+                    return None;
                 };
 
-                context.general.origin_traceable.material().exit(&new_origin, &mut transitioned_direction);
-                destination_traceable.material().enter(&new_origin, &mut transitioned_direction);
+                if !same_traceable(context.general.origin_traceable, destination_traceable) {
+                    context.general.origin_traceable.material().exit(&new_origin, &mut transitioned_direction);
+                    destination_traceable.material().enter(&new_origin, &mut transitioned_direction);
+                }
+
+                if context.general.debugging {
+                    inspection::set_next_kind(RayKind::Transmission);
+                }
 
                 let transition_color = trace(&context.general.time,
-                                             destination_traceable,
+                                             &destination_media,
                                              &new_origin,
                                              &transitioned_direction);
-                let surface_palette: Rgba<F> = palette::Rgba::new_u8(surface_color_data[0],
-                                                                     surface_color_data[1],
-                                                                     surface_color_data[2],
-                                                                     surface_color_data[3]);
-                let transition_color: [u8; 4] = transition_color.to_pixel();
-                let transition_palette = palette::Rgba::new_u8(transition_color[0],
-                                                               transition_color[1],
-                                                               transition_color[2],
-                                                               transition_color[3]);
-
-                surface_palette.over(transition_palette)
+
+                surface_color.over(transition_color)
             }
         })
     }
 
     fn get_reflection_color(&self,
                             reflection_ratio: F,
+                            shading: &TracingContext<P, V>,
                             context: &ColorTracingContext<P, V>)
                             -> Option<Rgba<F>> {
         if reflection_ratio <= <F as Zero>::zero() {
             return None;
         }
 
-        let reflection_direction = self.get_reflection_direction(&context.general);
+        let reflection_direction = self.get_reflection_direction(shading);
         let trace = context.trace;
         // Offset the new origin, so it doesn't hit the same shape over and over
         // The question is -- is there a better way? I think not.
@@ -132,8 +168,12 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> ComposableSurface<P, V> {
                          (context.general.intersection_normal_closer
                             * <F as ApproxEq<F>>::approx_epsilon(None) * Cast::from(128.0));
 
+        if context.general.debugging {
+            inspection::set_next_kind(RayKind::Reflection);
+        }
+
         Some(trace(&context.general.time,
-                   context.general.origin_traceable,
+                   context.media,
                    &new_origin,
                    &reflection_direction))
     }
@@ -142,13 +182,33 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> ComposableSurface<P, V> {
 impl<P: CustomPoint<V>, V: CustomVector<P>> Surface<P, V>
         for ComposableSurface<P, V> {
     fn get_color(&self, context: ColorTracingContext<P, V>) -> Rgba<F> {
-        let reflection_ratio = self.get_reflection_ratio(&context.general)
+        let shading = self.get_shading_context(&context.general);
+        let reflection_ratio = self.get_reflection_ratio(&shading)
             .min(<F as One>::one())
             .max(<F as Zero>::zero());
+
+        if context.general.debugging {
+            inspection::record_reflection_ratio(reflection_ratio);
+        }
+
         let intersection_color: Option<Rgba<F>> =
-            self.get_intersection_color(reflection_ratio, &context);
+            self.get_intersection_color(reflection_ratio, &shading, &context);
         let reflection_color: Option<Rgba<F>> =
-            self.get_reflection_color(reflection_ratio, &context);
+            self.get_reflection_color(reflection_ratio, &shading, &context);
+
+        if let Some(aovs) = context.aovs {
+            let weighted = |color: &Option<Rgba<F>>, ratio: F| {
+                color.map_or(Rgb::new(<F as Zero>::zero(), <F as Zero>::zero(), <F as Zero>::zero()),
+                             |color| Rgb::new(color.color.red * ratio,
+                                              color.color.green * ratio,
+                                              color.color.blue * ratio))
+            };
+
+            aovs.record_surface(self.get_surface_color(&shading).color,
+                                aov::normal_coordinates(&shading.intersection.normal));
+            aovs.record_contributions(weighted(&intersection_color, <F as One>::one() - reflection_ratio),
+                                      weighted(&reflection_color, reflection_ratio));
+        }
 
         if intersection_color.is_none() {
             return reflection_color.expect("No intersection color calculated; the reflection color should exist.");
@@ -174,23 +234,23 @@ impl<P: CustomPoint<V>, V: CustomVector<P>> Surface<P, V>
                 -context.general.intersection_normal_closer * <F as ApproxEq<F>>::approx_epsilon(None) * Cast::from(128.0);
 
             // Apply the material transition
-            let destination_traceable = if context.general.exiting {
-                if let Some(result) = (context.material_at)(&new_origin) {
-                    result
-                } else {
-                    return None;
-                }
+            let destination_media = context.media.transition(context.general.intersection_traceable,
+                                                             context.general.exiting);
+            let destination_traceable = if let Some(result) = destination_media.current() {
+                result
             } else {
-                context.general.intersection_traceable
+                return None;
             };
             let mut transitioned_direction = context.general.intersection.direction;
 
-            context.general.origin_traceable.material().exit(&new_origin, &mut transitioned_direction);
-            destination_traceable.material().enter(&new_origin, &mut transitioned_direction);
+            if !same_traceable(context.general.origin_traceable, destination_traceable) {
+                context.general.origin_traceable.material().exit(&new_origin, &mut transitioned_direction);
+                destination_traceable.material().enter(&new_origin, &mut transitioned_direction);
+            }
 
             Some(trace(&context.general.time,
                        &new_distance,
-                       destination_traceable,
+                       &destination_media,
                        &new_origin,
                        &transitioned_direction))
         }
@@ -210,11 +270,26 @@ pub fn reflection_ratio_uniform<P: CustomPoint<V>, V: CustomVector<P>>
     })
 }
 
-#[allow(unused_variables)]
+/// The wavelength to evaluate refractive indices at
+fn context_wavelength<P: CustomPoint<V>, V: CustomVector<P>>(context: &TracingContext<P, V>) -> F {
+    context.wavelength.unwrap_or(<F as NumCast>::from(spectrum::WAVELENGTH_REFERENCE).unwrap())
+}
+
 pub fn reflection_ratio_fresnel<P: CustomPoint<V>, V: CustomVector<P>>
     (refractive_index_inside: F, refractive_index_outside: F)
      -> Box<ReflectionRatioProvider<P, V>> {
+    reflection_ratio_fresnel_dispersive(spectrum::refractive_index_constant(refractive_index_inside),
+                                        spectrum::refractive_index_constant(refractive_index_outside))
+}
+
+pub fn reflection_ratio_fresnel_dispersive<P: CustomPoint<V>, V: CustomVector<P>>
+    (refractive_index_inside: Box<RefractiveIndexProvider>,
+     refractive_index_outside: Box<RefractiveIndexProvider>)
+     -> Box<ReflectionRatioProvider<P, V>> {
     Box::new(move |context: &TracingContext<P, V>| {
+        let wavelength = context_wavelength(context);
+        let refractive_index_inside = refractive_index_inside(wavelength);
+        let refractive_index_outside = refractive_index_outside(wavelength);
         let normal = -context.intersection_normal_closer;
         let from_theta = context.intersection.direction.angle_between(&normal);
         let (from_index, to_index) = if context.exiting {
@@ -268,8 +343,18 @@ pub fn threshold_direction_identity<P: CustomPoint<V>, V: CustomVector<P>>
 pub fn threshold_direction_snell<P: CustomPoint<V>, V: CustomVector<P>>
     (refractive_index: F)
     -> Box<ThresholdDirectionProvider<P, V>>
+{
+    threshold_direction_snell_dispersive(spectrum::refractive_index_constant(refractive_index))
+}
+
+/// Refracts the ray according to the refractive index at the traced wavelength,
+/// which separates white light into its spectrum, when rendering spectrally.
+pub fn threshold_direction_snell_dispersive<P: CustomPoint<V>, V: CustomVector<P>>
+    (refractive_index: Box<RefractiveIndexProvider>)
+    -> Box<ThresholdDirectionProvider<P, V>>
 {
     Box::new(move |context: &TracingContext<P, V>| {
+        let refractive_index = refractive_index(context_wavelength(context));
         let normal = -context.intersection_normal_closer;
         let from_theta = context.intersection.direction.angle_between(&normal);
         let refractive_index_modifier = if context.exiting {
@@ -287,6 +372,121 @@ pub fn threshold_direction_snell<P: CustomPoint<V>, V: CustomVector<P>>
     })
 }
 
+pub fn normal_geometric<P: CustomPoint<V>, V: CustomVector<P>>
+    ()
+    -> Box<NormalProvider<P, V>>
+{
+    Box::new(move |context: &TracingContext<P, V>| {
+        context.intersection.normal
+    })
+}
+
+/// The distance used to approximate derivatives with central differences
+const DIFFERENCE_EPSILON: f64 = 1.0e-4;
+
+/// Approximates the gradient of the given function at the given location.
+pub fn gradient<P: CustomPoint<V>, V: CustomVector<P>, T: Fn(&P) -> F>(function: T, location: &P) -> V {
+    let epsilon = <F as NumCast>::from(DIFFERENCE_EPSILON).unwrap();
+    let dimension = <P as Dimension>::dimension(None);
+    let mut result = <V as Repeat<F>>::repeat(<F as Zero>::zero());
+
+    for axis in 0..dimension {
+        let mut forward = *location;
+        let mut backward = *location;
+        forward[axis] += epsilon;
+        backward[axis] -= epsilon;
+        result[axis] = (function(&forward) - function(&backward))
+            / (epsilon * <F as NumCast>::from(2.0).unwrap());
+    }
+
+    result
+}
+
+/// Projects the vector onto the tangent space of the given unit normal.
+pub fn project_tangent<P: CustomPoint<V>, V: CustomVector<P>>(vector: V, normal: &V) -> V {
+    vector - *normal * vector.dot(normal)
+}
+
+/// Replaces the perturbed normal with the geometric one, if it faces the opposite side.
+fn facing_normal<P: CustomPoint<V>, V: CustomVector<P>>(perturbed: V, geometric: V) -> V {
+    if perturbed.dot(&geometric) > <F as Zero>::zero() {
+        perturbed.normalize()
+    } else {
+        geometric
+    }
+}
+
+/// Perturbs the normal by the slope of a height map, the `strength` scales the heights.
+/// Works in any number of dimensions.
+pub fn normal_bump<P: CustomPoint<V>, V: CustomVector<P>>
+    (height: Box<HeightProvider<P>>, strength: F)
+    -> Box<NormalProvider<P, V>>
+{
+    Box::new(move |context: &TracingContext<P, V>| {
+        let normal = context.intersection.normal;
+        let slope: V = gradient(|location| height(location, &context.time),
+                                &context.intersection.location);
+
+        facing_normal(normal - project_tangent(slope, &normal) * strength, normal)
+    })
+}
+
+/// Uses a tangent-space normal map, the red and green channels point towards the increasing
+/// `u` and `v` coordinates and the blue channel along the geometric normal.
+/// The tangents are derived from the UV mapping, so that any `UVFn` may be used.
+/// The `strength` scales the tangential components of the mapped normals.
+pub fn normal_map<P: CustomPoint<V>, V: CustomVector<P>>
+    (uvfn: Box<UVFn<P>>, texture: Box<Texture>, strength: F)
+    -> Box<NormalProvider<P, V>>
+{
+    Box::new(move |context: &TracingContext<P, V>| {
+        let normal = context.intersection.normal;
+        let location = &context.intersection.location;
+        let tangent: V = project_tangent(gradient(|location| uvfn(location).x, location), &normal);
+        let bitangent: V = project_tangent(gradient(|location| uvfn(location).y, location), &normal);
+
+        if tangent.norm() <= <F as Zero>::zero() || bitangent.norm() <= <F as Zero>::zero() {
+            return normal;
+        }
+
+        let color = texture(&uvfn(location));
+        let one = <F as One>::one();
+        let two = <F as NumCast>::from(2.0).unwrap();
+        let perturbed = tangent.normalize() * ((color.color.red * two - one) * strength)
+            + bitangent.normalize() * ((color.color.green * two - one) * strength)
+            + normal * (color.color.blue * two - one);
+
+        facing_normal(perturbed, normal)
+    })
+}
+
+/// Uses the luminance of a texture as the height.
+#[allow(unused_variables)]
+pub fn height_texture<P: CustomPoint<V>, V: CustomVector<P>>
+    (uvfn: Box<UVFn<P>>, texture: Box<Texture>)
+    -> Box<HeightProvider<P>>
+{
+    Box::new(move |location: &P, time: &Duration| {
+        let color = texture(&uvfn(location));
+
+        color.color.red * <F as NumCast>::from(0.2126).unwrap()
+            + color.color.green * <F as NumCast>::from(0.7152).unwrap()
+            + color.color.blue * <F as NumCast>::from(0.0722).unwrap()
+    })
+}
+
+/// Procedural bumps given by Perlin noise, the heights range from -1 to 1.
+pub fn height_perlin<P: CustomPoint<V>, V: CustomVector<P>>
+    (seed: usize, size: F, speed: F)
+    -> Box<HeightProvider<P>>
+{
+    let perlin = Perlin::new().set_seed(seed);
+
+    Box::new(move |location: &P, time: &Duration| {
+        perlin.get(noise_coordinates(location, time, size, speed))
+    })
+}
+
 pub type BlendFunction = (Fn(Rgba<F>, Rgba<F>) -> Rgba<F>) + Send + Sync;
 pub type PaletteBlendFunction<C: Blend<Color=C> + ComponentWise> =
     (Fn(PreAlpha<C, <C as ComponentWise>::Scalar>, PreAlpha<C, <C as ComponentWise>::Scalar>)
@@ -431,7 +631,7 @@ pub fn surface_color_uniform<P: CustomPoint<V>, V: CustomVector<P>>
 pub type UVFn<P> = (Fn(&P) -> Point2<F>) + Send + Sync;
 pub type Texture = (Fn(&Point2<F>) -> Rgba<F>) + Send + Sync;
 
-pub fn texture_image_nearest_neighbor(dynamic_image: DynamicImage) -> Box<Texture> {
+pub fn texture_image_nearest_neighbor(dynamic_image: Arc<DynamicImage>) -> Box<Texture> {
     Box::new(move |point: &Point2<F>| {
         let (width, height) = dynamic_image.dimensions();
         let (x, y) = (point.x * <F as NumCast>::from(width).unwrap(),
@@ -450,7 +650,7 @@ pub fn texture_image_nearest_neighbor(dynamic_image: DynamicImage) -> Box<Textur
     })
 }
 
-pub fn texture_image_linear(dynamic_image: DynamicImage) -> Box<Texture> {
+pub fn texture_image_linear(dynamic_image: Arc<DynamicImage>) -> Box<Texture> {
     Box::new(move |point: &Point2<F>| {
         let (width, height) = dynamic_image.dimensions();
         let (x, y): (F, F) = (point.x * <F as NumCast>::from(width).unwrap() - 0.5,
@@ -488,9 +688,121 @@ pub fn texture_image_linear(dynamic_image: DynamicImage) -> Box<Texture> {
     })
 }
 
+/// An image with floating-point colors, which are not limited to the range [0; 1]
+pub struct FloatImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<Rgba<F>>,
+}
+
+impl FloatImage {
+    /// Loads a Radiance `.hdr` image, or an OpenEXR image if the path ends with `.exr`.
+    /// OpenEXR images compressed with anything but ZIP or ZIPS are rejected with an error.
+    pub fn open_hdr<T: AsRef<Path>>(path: T) -> ImageResult<Self> {
+        let path = path.as_ref();
+
+        if exr::is_exr(path) {
+            let image = try!(exr::read_exr(&mut try!(File::open(path))));
+
+            return Ok(FloatImage {
+                width: image.width,
+                height: image.height,
+                data: image.pixels.iter()
+                    .map(|pixel| Rgba::new(<F as NumCast>::from(pixel[0]).unwrap(),
+                                           <F as NumCast>::from(pixel[1]).unwrap(),
+                                           <F as NumCast>::from(pixel[2]).unwrap(),
+                                           <F as NumCast>::from(pixel[3]).unwrap()))
+                    .collect(),
+            });
+        }
+
+        let file = try!(File::open(path));
+        let decoder = try!(HDRDecoder::new(BufReader::new(file)));
+        let metadata = decoder.metadata();
+        let pixels = try!(decoder.read_image_hdr());
+
+        Ok(FloatImage {
+            width: metadata.width,
+            height: metadata.height,
+            data: pixels.iter()
+                .map(|pixel| Rgba::new(<F as NumCast>::from(pixel[0]).unwrap(),
+                                       <F as NumCast>::from(pixel[1]).unwrap(),
+                                       <F as NumCast>::from(pixel[2]).unwrap(),
+                                       <F as One>::one()))
+                .collect(),
+        })
+    }
+
+    /// Converts the 8-bit colors of the image to the range [0; 1].
+    pub fn from_dynamic_image(dynamic_image: &DynamicImage) -> Self {
+        let (width, height) = dynamic_image.dimensions();
+        let max = <F as NumCast>::from(std::u8::MAX).unwrap();
+
+        FloatImage {
+            width: width,
+            height: height,
+            data: dynamic_image.to_rgba()
+                .pixels()
+                .map(|pixel| Rgba::new(<F as NumCast>::from(pixel[0]).unwrap() / max,
+                                       <F as NumCast>::from(pixel[1]).unwrap() / max,
+                                       <F as NumCast>::from(pixel[2]).unwrap() / max,
+                                       <F as NumCast>::from(pixel[3]).unwrap() / max))
+                .collect(),
+        }
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> Rgba<F> {
+        self.data[(y * self.width + x) as usize]
+    }
+}
+
+pub fn texture_float_image_nearest_neighbor(image: FloatImage) -> Box<Texture> {
+    Box::new(move |point: &Point2<F>| {
+        let (x, y) = (point.x * <F as NumCast>::from(image.width).unwrap(),
+                      point.y * <F as NumCast>::from(image.height).unwrap());
+        let (x, y) = (util::remainder(x.floor(), <F as NumCast>::from(image.width).unwrap()),
+                      util::remainder(y.floor(), <F as NumCast>::from(image.height).unwrap()));
+
+        image.get_pixel(<u32 as NumCast>::from(x).unwrap(), <u32 as NumCast>::from(y).unwrap())
+    })
+}
+
+pub fn texture_float_image_linear(image: FloatImage) -> Box<Texture> {
+    Box::new(move |point: &Point2<F>| {
+        let (width, height) = (<F as NumCast>::from(image.width).unwrap(),
+                               <F as NumCast>::from(image.height).unwrap());
+        let (x, y): (F, F) = (point.x * width - 0.5, point.y * height - 0.5);
+        let (offset_x, offset_y) = (x - x.floor(), y - y.floor());
+        let pixel = |offset_x: F, offset_y: F| {
+            let pixel = image.get_pixel(
+                <u32 as NumCast>::from(util::remainder((x + offset_x).floor(), width)).unwrap(),
+                <u32 as NumCast>::from(util::remainder((y + offset_y).floor(), height)).unwrap());
+
+            [pixel.color.red, pixel.color.green, pixel.color.blue, pixel.alpha]
+        };
+        let pixels = [pixel(0.0, 0.0), pixel(1.0, 0.0), pixel(0.0, 1.0), pixel(1.0, 1.0)];
+        let mut data: [F; 4] = [Cast::from(0.0); 4];
+
+        for (index, color) in data.iter_mut().enumerate() {
+            *color = (pixels[0][index] * (<F as One>::one() - offset_x) + pixels[1][index] * offset_x)
+                     * (<F as One>::one() - offset_y)
+                     + (pixels[2][index] * (<F as One>::one() - offset_x) + pixels[3][index] * offset_x)
+                     * offset_y;
+        }
+
+        Rgba::new(data[0], data[1], data[2], data[3])
+    })
+}
+
 pub trait MappedTexture<P: CustomPoint<V>, V: CustomVector<P>>
     : Send + Sync {
     fn get_color(&self, point: &P) -> Rgba<F>;
+
+    /// Samples the texture at the intersection. Filtered textures use the cross-section
+    /// of the ray to avoid aliasing.
+    fn get_color_filtered(&self, context: &TracingContext<P, V>) -> Rgba<F> {
+        self.get_color(&context.intersection.location)
+    }
 }
 
 #[derive(Default)]
@@ -537,6 +849,101 @@ pub fn surface_color_texture<P: CustomPoint<V>, V: CustomVector<P>>
     (mapped_texture: Box<MappedTexture<P, V>>)
      -> Box<SurfaceColorProvider<P, V>> {
     Box::new(move |context: &TracingContext<P, V>| {
-        mapped_texture.get_color(&context.intersection.location)
+        mapped_texture.get_color_filtered(context)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::time::Duration;
+    use palette::Rgb;
+    use na::Norm;
+    use output;
+    use universe::aov::AovImage;
+    use universe::aov::AovLayer;
+    use universe::aov::AovSample;
+    use universe::d3::Point3;
+    use universe::d3::Vector3;
+    use universe::entity::Void;
+    use universe::entity::shape::Intersection;
+    use universe::entity::shape::RayCone;
+    use super::*;
+
+    #[test]
+    fn bumps_tilt_normals_against_the_slope() {
+        let void = Void::<Point3, Vector3>::new_with_vacuum();
+        let normal = Vector3::new(0.0, 0.0, 1.0);
+        let context = TracingContext {
+            debugging: false,
+            time: Duration::new(0, 0),
+            wavelength: None,
+            cone: RayCone::new(0.0, 0.0),
+            origin_traceable: &void,
+            origin_location: Point3::new(0.0, 0.0, 1.0),
+            origin_direction: -normal,
+            intersection_traceable: &void,
+            intersection: Intersection::new(Point3::new(0.0, 0.0, 0.0), -normal, normal, 1.0),
+            intersection_normal_closer: normal,
+            exiting: false,
+        };
+        let slope = normal_bump::<Point3, Vector3>(
+            Box::new(|location: &Point3, _: &Duration| location.x * 0.5), 2.0);
+        let flat = normal_bump::<Point3, Vector3>(
+            Box::new(|_: &Point3, _: &Duration| 1.0), 2.0);
+        let perlin = normal_bump::<Point3, Vector3>(height_perlin(0, 1.0, 0.0), 1.0);
+
+        assert!((slope(&context) - Vector3::new(-1.0, 0.0, 1.0).normalize()).norm() < 1.0e-3);
+        assert!((flat(&context) - normal).norm() < 1.0e-3);
+        assert!((perlin(&context).norm() - 1.0).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn open_radiance_image() {
+        let path = env::temp_dir().join("euclider_open_radiance_image.hdr");
+
+        {
+            let mut file = File::create(&path).unwrap();
+
+            file.write_all(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n").unwrap();
+            // Flat RGBE pixels, too narrow to be run-length encoded
+            file.write_all(&[128, 64, 0, 130, 128, 128, 128, 129]).unwrap();
+        }
+
+        let image = FloatImage::open_hdr(&path).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        assert!((image.get_pixel(0, 0).color.red - 2.0).abs() < 0.05);
+        assert!((image.get_pixel(0, 0).color.green - 1.0).abs() < 0.05);
+        assert!(image.get_pixel(0, 0).color.blue.abs() < 0.05);
+        assert!((image.get_pixel(1, 0).color.green - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn open_exr_image() {
+        let path = env::temp_dir().join("euclider_open_exr_image.exr");
+        let mut samples = vec![AovSample::default(); 4];
+
+        samples[2].beauty = Rgb::new(4.0, 0.5, 0.0);
+
+        output::write_exr(&mut File::create(&path).unwrap(), &AovImage {
+            width: 2,
+            height: 2,
+            layers: vec![AovLayer::Beauty],
+            samples: samples,
+        }).unwrap();
+
+        let image = FloatImage::open_hdr(&path).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        // The rows of the rendered image start at the bottom, the ones of textures at the top
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.get_pixel(0, 0), Rgba::new(4.0, 0.5, 0.0, 1.0));
+        assert_eq!(image.get_pixel(0, 1), Rgba::new(0.0, 0.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,462 @@
+use ::F;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::thread::LocalKey;
+use image;
+use image::DynamicImage;
+use image::ImageResult;
+use universe::entity::exr;
+use palette::Rgba;
+use na::Dimension;
+use na::Dot;
+use na::Norm;
+use na::Point2;
+use na::Vector2;
+use num::NumCast;
+use num::Zero;
+use num::One;
+use universe::entity::shape::TracingContext;
+use universe::entity::surface::FloatImage;
+use universe::entity::surface::MappedTexture;
+use universe::entity::surface::Texture;
+use universe::entity::surface::UVFn;
+use universe::entity::surface::project_tangent;
+use util;
+use util::CustomPoint;
+use util::CustomVector;
+
+/// The area of a texture covered by a single pixel, approximated by an ellipse with the given
+/// axes in texture coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureFootprint {
+    pub major: Vector2<F>,
+    pub minor: Vector2<F>,
+}
+
+impl Default for TextureFootprint {
+    fn default() -> Self {
+        TextureFootprint {
+            major: Vector2::new(<F as Zero>::zero(), <F as Zero>::zero()),
+            minor: Vector2::new(<F as Zero>::zero(), <F as Zero>::zero()),
+        }
+    }
+}
+
+pub type TextureFiltered = (Fn(&Point2<F>, &TextureFootprint) -> Rgba<F>) + Send + Sync;
+
+/// The image with its downscaled versions, each level is half the size of the previous one.
+pub struct MipMap {
+    pub levels: Vec<FloatImage>,
+}
+
+impl MipMap {
+    pub fn new(image: FloatImage) -> Self {
+        let mut levels = vec![image];
+
+        loop {
+            let next = {
+                let previous = &levels[levels.len() - 1];
+
+                if previous.width <= 1 && previous.height <= 1 {
+                    break;
+                }
+
+                MipMap::downscale(previous)
+            };
+
+            levels.push(next);
+        }
+
+        MipMap { levels: levels }
+    }
+
+    /// Averages blocks of 2x2 pixels, the last row or column of odd sizes is repeated.
+    fn downscale(image: &FloatImage) -> FloatImage {
+        let width = (image.width / 2).max(1);
+        let height = (image.height / 2).max(1);
+        let mut data = Vec::with_capacity((width * height) as usize);
+        let quarter = <F as NumCast>::from(0.25).unwrap();
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum: [F; 4] = [<F as Zero>::zero(); 4];
+
+                for &(offset_x, offset_y) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let pixel = image.get_pixel((x * 2 + offset_x).min(image.width - 1),
+                                                (y * 2 + offset_y).min(image.height - 1));
+
+                    sum[0] += pixel.color.red;
+                    sum[1] += pixel.color.green;
+                    sum[2] += pixel.color.blue;
+                    sum[3] += pixel.alpha;
+                }
+
+                data.push(Rgba::new(sum[0] * quarter, sum[1] * quarter,
+                                    sum[2] * quarter, sum[3] * quarter));
+            }
+        }
+
+        FloatImage {
+            width: width,
+            height: height,
+            data: data,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.levels[0].height
+    }
+
+    /// Converts a vector in texture coordinates to the length in pixels of the full-size image.
+    pub fn texel_length(&self, vector: &Vector2<F>) -> F {
+        Vector2::new(vector.x * <F as NumCast>::from(self.width()).unwrap(),
+                     vector.y * <F as NumCast>::from(self.height()).unwrap()).norm()
+    }
+
+    /// The level of detail at which a pixel of the image is as wide as the given width in pixels
+    /// of the full-size image.
+    pub fn level_of_detail(&self, width: F) -> F {
+        let max_level = <F as NumCast>::from(self.levels.len() - 1).unwrap();
+
+        width.max(<F as One>::one()).log2().min(max_level)
+    }
+
+    fn sample_bilinear(&self, level: usize, point: &Point2<F>) -> [F; 4] {
+        let image = &self.levels[level];
+        let (width, height) = (<F as NumCast>::from(image.width).unwrap(),
+                               <F as NumCast>::from(image.height).unwrap());
+        let half = <F as NumCast>::from(0.5).unwrap();
+        let (x, y) = (point.x * width - half, point.y * height - half);
+        let (offset_x, offset_y) = (x - x.floor(), y - y.floor());
+        let pixel = |offset_x: F, offset_y: F| {
+            let pixel = image.get_pixel(
+                <u32 as NumCast>::from(util::remainder((x + offset_x).floor(), width)).unwrap(),
+                <u32 as NumCast>::from(util::remainder((y + offset_y).floor(), height)).unwrap());
+
+            [pixel.color.red, pixel.color.green, pixel.color.blue, pixel.alpha]
+        };
+        let one = <F as One>::one();
+        let zero = <F as Zero>::zero();
+        let pixels = [pixel(zero, zero), pixel(one, zero), pixel(zero, one), pixel(one, one)];
+        let mut result: [F; 4] = [zero; 4];
+
+        for (index, component) in result.iter_mut().enumerate() {
+            *component = (pixels[0][index] * (one - offset_x) + pixels[1][index] * offset_x)
+                         * (one - offset_y)
+                         + (pixels[2][index] * (one - offset_x) + pixels[3][index] * offset_x)
+                         * offset_y;
+        }
+
+        result
+    }
+
+    /// Interpolates between the bilinearly filtered neighbouring levels.
+    fn sample_level(&self, point: &Point2<F>, level_of_detail: F) -> [F; 4] {
+        let lower = <usize as NumCast>::from(level_of_detail.floor()).unwrap();
+        let upper = (lower + 1).min(self.levels.len() - 1);
+        let ratio = level_of_detail - level_of_detail.floor();
+        let lower_color = self.sample_bilinear(lower, point);
+
+        if upper == lower || ratio <= <F as Zero>::zero() {
+            return lower_color;
+        }
+
+        let upper_color = self.sample_bilinear(upper, point);
+        let mut result = lower_color;
+
+        for (index, component) in result.iter_mut().enumerate() {
+            *component = *component * (<F as One>::one() - ratio) + upper_color[index] * ratio;
+        }
+
+        result
+    }
+
+    /// Samples the level of detail matching the larger axis of the footprint.
+    pub fn sample_trilinear(&self, point: &Point2<F>, footprint: &TextureFootprint) -> Rgba<F> {
+        let width = self.texel_length(&footprint.major).max(self.texel_length(&footprint.minor));
+        let color = self.sample_level(point, self.level_of_detail(width));
+
+        Rgba::new(color[0], color[1], color[2], color[3])
+    }
+
+    /// Samples the level of detail matching the smaller axis of the footprint multiple times
+    /// along the larger axis. The ratio of the axes is limited by `max_anisotropy`.
+    pub fn sample_anisotropic(&self,
+                              point: &Point2<F>,
+                              footprint: &TextureFootprint,
+                              max_anisotropy: u32)
+                              -> Rgba<F> {
+        let (mut major, mut minor) = (footprint.major, footprint.minor);
+
+        if self.texel_length(&major) < self.texel_length(&minor) {
+            ::std::mem::swap(&mut major, &mut minor);
+        }
+
+        let max_anisotropy = max_anisotropy.max(1);
+        let major_length = self.texel_length(&major);
+        let minor_length = self.texel_length(&minor)
+            .max(major_length / <F as NumCast>::from(max_anisotropy).unwrap());
+        let samples = if minor_length > <F as Zero>::zero() {
+            <u32 as NumCast>::from((major_length / minor_length).ceil()).unwrap()
+                .max(1)
+                .min(max_anisotropy)
+        } else {
+            1
+        };
+        let level_of_detail = self.level_of_detail(minor_length);
+        let samples_float = <F as NumCast>::from(samples).unwrap();
+        let half = <F as NumCast>::from(0.5).unwrap();
+        let mut sum: [F; 4] = [<F as Zero>::zero(); 4];
+
+        for index in 0..samples {
+            let offset = (<F as NumCast>::from(index).unwrap() + half) / samples_float - half;
+            let color = self.sample_level(&(*point + major * offset), level_of_detail);
+
+            for (component, value) in sum.iter_mut().zip(color.iter()) {
+                *component += *value / samples_float;
+            }
+        }
+
+        Rgba::new(sum[0], sum[1], sum[2], sum[3])
+    }
+}
+
+pub fn texture_mipmap_trilinear(mipmap: Arc<MipMap>) -> Box<TextureFiltered> {
+    Box::new(move |point: &Point2<F>, footprint: &TextureFootprint| {
+        mipmap.sample_trilinear(point, footprint)
+    })
+}
+
+pub fn texture_mipmap_anisotropic(mipmap: Arc<MipMap>, max_anisotropy: u32) -> Box<TextureFiltered> {
+    Box::new(move |point: &Point2<F>, footprint: &TextureFootprint| {
+        mipmap.sample_anisotropic(point, footprint, max_anisotropy)
+    })
+}
+
+/// Ignores the footprint, so that any texture may be used where a filtered one is required.
+#[allow(unused_variables)]
+pub fn texture_unfiltered(texture: Box<Texture>) -> Box<TextureFiltered> {
+    Box::new(move |point: &Point2<F>, footprint: &TextureFootprint| {
+        texture(point)
+    })
+}
+
+/// The gradients of the texture coordinates, projected onto the tangent space.
+/// Differences across the seams of the texture are wrapped around.
+fn uv_gradients<P: CustomPoint<V>, V: CustomVector<P>>(uvfn: &UVFn<P>, location: &P, normal: &V)
+        -> (V, V) {
+    const EPSILON: f64 = 1.0e-4;
+    let epsilon = <F as NumCast>::from(EPSILON).unwrap();
+    let dimension = <P as Dimension>::dimension(None);
+    let mut gradient_u = *normal * <F as Zero>::zero();
+    let mut gradient_v = gradient_u;
+    let wrap = |difference: F| difference - difference.round();
+
+    for axis in 0..dimension {
+        let mut forward = *location;
+        let mut backward = *location;
+        forward[axis] += epsilon;
+        backward[axis] -= epsilon;
+        let (uv_forward, uv_backward) = (uvfn(&forward), uvfn(&backward));
+        let denominator = epsilon * <F as NumCast>::from(2.0).unwrap();
+
+        gradient_u[axis] = wrap(uv_forward.x - uv_backward.x) / denominator;
+        gradient_v[axis] = wrap(uv_forward.y - uv_backward.y) / denominator;
+    }
+
+    (project_tangent::<P, V>(gradient_u, normal), project_tangent::<P, V>(gradient_v, normal))
+}
+
+/// Estimates the footprint of the ray on the texture. The cross-section of the ray is stretched
+/// along the direction of the ray projected onto the surface, as the ray gets more grazing.
+pub fn texture_footprint<P: CustomPoint<V>, V: CustomVector<P>>(uvfn: &UVFn<P>,
+                                                                context: &TracingContext<P, V>)
+                                                                -> TextureFootprint {
+    const MIN_COSINE: f64 = 0.01;
+    let width = context.cone.width;
+
+    if width <= <F as Zero>::zero() {
+        return TextureFootprint::default();
+    }
+
+    let zero = <F as Zero>::zero();
+    let normal = context.intersection.normal.normalize();
+    let direction = context.intersection.direction.normalize();
+    let (gradient_u, gradient_v) = uv_gradients(uvfn, &context.intersection.location, &normal);
+    let cosine = direction.dot(&normal).abs().max(<F as NumCast>::from(MIN_COSINE).unwrap());
+    let tangent_direction = project_tangent::<P, V>(direction, &normal);
+    let normalize_or_zero = |vector: V| {
+        if vector.norm() > zero {
+            vector.normalize()
+        } else {
+            vector
+        }
+    };
+    let (major, minor) = if tangent_direction.norm() > zero {
+        let major = tangent_direction.normalize();
+        let remainder_u = gradient_u - major * gradient_u.dot(&major);
+        let remainder_v = gradient_v - major * gradient_v.dot(&major);
+        let minor = if remainder_u.norm() >= remainder_v.norm() {
+            normalize_or_zero(remainder_u)
+        } else {
+            normalize_or_zero(remainder_v)
+        };
+
+        (major * (width / cosine), minor * width)
+    } else {
+        let major = normalize_or_zero(gradient_u);
+        let minor = normalize_or_zero(gradient_v - major * gradient_v.dot(&major));
+
+        (major * width, minor * width)
+    };
+
+    TextureFootprint {
+        major: Vector2::new(gradient_u.dot(&major), gradient_v.dot(&major)),
+        minor: Vector2::new(gradient_u.dot(&minor), gradient_v.dot(&minor)),
+    }
+}
+
+/// A texture mapped onto surfaces, filtered according to the footprint of the ray.
+pub struct MappedTextureFiltered<P: CustomPoint<V>, V: CustomVector<P>> {
+    pub uvfn: Box<UVFn<P>>,
+    pub texture: Box<TextureFiltered>,
+    marker_vector: PhantomData<V>,
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> MappedTextureFiltered<P, V> {
+    pub fn new(uvfn: Box<UVFn<P>>, texture: Box<TextureFiltered>) -> Self {
+        MappedTextureFiltered {
+            uvfn: uvfn,
+            texture: texture,
+            marker_vector: PhantomData,
+        }
+    }
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> MappedTexture<P, V> for MappedTextureFiltered<P, V> {
+    fn get_color(&self, point: &P) -> Rgba<F> {
+        (self.texture)(&(self.uvfn)(point), &TextureFootprint::default())
+    }
+
+    fn get_color_filtered(&self, context: &TracingContext<P, V>) -> Rgba<F> {
+        let footprint = texture_footprint(self.uvfn.as_ref(), context);
+
+        (self.texture)(&(self.uvfn)(&context.intersection.location), &footprint)
+    }
+}
+
+type TextureCache<T> = RefCell<HashMap<PathBuf, Weak<T>>>;
+
+thread_local! {
+    static IMAGE_CACHE: TextureCache<DynamicImage> = RefCell::new(HashMap::new());
+    static MIPMAP_CACHE: TextureCache<MipMap> = RefCell::new(HashMap::new());
+}
+
+/// Returns the loaded resource, if it is still in use, otherwise loads it again.
+fn load_cached<T, L>(cache: &'static LocalKey<TextureCache<T>>, path: &Path, load: L)
+        -> ImageResult<Arc<T>>
+    where T: 'static,
+          L: FnOnce(&Path) -> ImageResult<T> {
+    let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let cached = cache.with(|cache| cache.borrow().get(&key).and_then(|weak| weak.upgrade()));
+
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    let loaded = Arc::new(try!(load(path)));
+
+    cache.with(|cache| cache.borrow_mut().insert(key, Arc::downgrade(&loaded)));
+
+    Ok(loaded)
+}
+
+/// Loads an image, sharing it with the other textures using the same file.
+pub fn load_image<T: AsRef<Path>>(path: T) -> ImageResult<Arc<DynamicImage>> {
+    load_cached(&IMAGE_CACHE, path.as_ref(), |path| image::open(path))
+}
+
+/// Loads an image and builds its mip chain, sharing it with the other textures using
+/// the same file. Radiance `.hdr` and OpenEXR images keep their high dynamic range.
+pub fn load_mipmap<T: AsRef<Path>>(path: T) -> ImageResult<Arc<MipMap>> {
+    load_cached(&MIPMAP_CACHE, path.as_ref(), |path| {
+        let is_hdr = path.extension()
+            .map_or(false, |extension| extension.to_string_lossy().to_lowercase() == "hdr");
+        let image = if is_hdr || exr::is_exr(path) {
+            try!(FloatImage::open_hdr(path))
+        } else {
+            FloatImage::from_dynamic_image(&*try!(load_image(path)))
+        };
+
+        Ok(MipMap::new(image))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use na::ApproxEq;
+    use na::Point2;
+    use na::Vector2;
+    use palette::Rgba;
+    use universe::entity::surface::FloatImage;
+    use super::*;
+
+    fn checkerboard(size: u32) -> FloatImage {
+        FloatImage {
+            width: size,
+            height: size,
+            data: (0..size * size)
+                .map(|index| {
+                    let value = ((index % size + index / size) % 2) as F;
+
+                    Rgba::new(value, value, value, 1.0)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn mipmap_levels() {
+        let mipmap = MipMap::new(checkerboard(8));
+        let sizes: Vec<u32> = mipmap.levels.iter().map(|level| level.width).collect();
+
+        assert_eq!(sizes, vec![8, 4, 2, 1]);
+        assert!(mipmap.levels[1].get_pixel(0, 0).color.red.approx_eq(&0.5));
+    }
+
+    #[test]
+    fn trilinear_averages_large_footprints() {
+        let mipmap = MipMap::new(checkerboard(8));
+        let point = Point2::new(0.3, 0.6);
+        let sharp = mipmap.sample_trilinear(&point, &TextureFootprint::default());
+        let blurred = mipmap.sample_trilinear(&point, &TextureFootprint {
+            major: Vector2::new(1.0, 0.0),
+            minor: Vector2::new(0.0, 1.0),
+        });
+
+        assert!(sharp.color.red >= 0.0 && sharp.color.red <= 1.0);
+        assert!(blurred.color.red.approx_eq(&0.5));
+    }
+
+    #[test]
+    fn anisotropic_limits_samples() {
+        let mipmap = MipMap::new(checkerboard(8));
+        let color = mipmap.sample_anisotropic(&Point2::new(0.5, 0.5), &TextureFootprint {
+            major: Vector2::new(1.0, 0.0),
+            minor: Vector2::new(0.0, 0.0),
+        }, 4);
+
+        // Four samples across a single row average out the checkerboard
+        assert!(color.color.red.approx_eq(&0.5));
+    }
+}
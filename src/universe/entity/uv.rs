@@ -0,0 +1,226 @@
+use ::F;
+use std::marker::PhantomData;
+use palette::Rgba;
+use na::BaseFloat;
+use na::Dimension;
+use na::Dot;
+use na::Norm;
+use na::Point2;
+use num::Zero;
+use num::One;
+use universe::entity::shape::TracingContext;
+use universe::entity::surface::MappedTexture;
+use universe::entity::surface::Texture;
+use universe::entity::surface::UVFn;
+use util::CustomPoint;
+use util::CustomVector;
+use util::cast;
+
+/// The coordinate of the vector along the axis, in multiples of the length of the axis
+fn project_onto<P: CustomPoint<V>, V: CustomVector<P>>(vector: &V, axis: &V) -> F {
+    vector.dot(axis) / axis.dot(axis)
+}
+
+/// The two axes following the given one, used to map the faces perpendicular to it
+fn face_axes(axis: usize, dimension: usize) -> (usize, usize) {
+    ((axis + 1) % dimension, (axis + 2) % dimension)
+}
+
+/// Projects the point onto a plane. The texture is repeated along the given axes,
+/// the length of each axis is the size of a single repetition.
+pub fn uv_planar<P: CustomPoint<V>, V: CustomVector<P>>(origin: P, axis_u: V, axis_v: V)
+        -> Box<UVFn<P>> {
+    Box::new(move |point: &P| {
+        let relative = *point - origin;
+
+        Point2::new(project_onto::<P, V>(&relative, &axis_u),
+                    project_onto::<P, V>(&relative, &axis_v))
+    })
+}
+
+/// Uses two coordinates of the point directly, divided by the size of a single repetition
+/// of the texture. Allows textures to be mapped in any plane of a 4D scene.
+pub fn uv_axes<P: CustomPoint<V>, V: CustomVector<P>>(axis_u: usize, axis_v: usize, size: F)
+        -> Box<UVFn<P>> {
+    let dimension = <P as Dimension>::dimension(None);
+
+    assert!(axis_u < dimension && axis_v < dimension,
+            "The axes must be lower than the dimension {}.", dimension);
+
+    Box::new(move |point: &P| {
+        Point2::new(point[axis_u] / size, point[axis_v] / size)
+    })
+}
+
+/// Wraps the texture around the axis of a cylinder. The `u` coordinate is the angle from
+/// `reference` towards `side`, the `v` coordinate is the distance along `axis`, in multiples
+/// of its length.
+pub fn uv_cylindrical<P: CustomPoint<V>, V: CustomVector<P>>(origin: P,
+                                                             axis: V,
+                                                             reference: V,
+                                                             side: V)
+                                                             -> Box<UVFn<P>> {
+    let reference = reference.normalize();
+    let side = side.normalize();
+
+    Box::new(move |point: &P| {
+        let relative = *point - origin;
+        let angle = relative.dot(&side).atan2(relative.dot(&reference));
+
+        Point2::new(cast(0.5) + angle / <F as BaseFloat>::two_pi(),
+                    project_onto::<P, V>(&relative, &axis))
+    })
+}
+
+/// Projects the point onto the face of an axis-aligned box it is closest to, each face
+/// shows the whole texture. The faces perpendicular to an axis use the two axes following it.
+pub fn uv_box<P: CustomPoint<V>, V: CustomVector<P>>(center: P, half_extents: V)
+        -> Box<UVFn<P>> {
+    let dimension = <P as Dimension>::dimension(None);
+
+    Box::new(move |point: &P| {
+        let relative = *point - center;
+        let mut dominant = 0;
+        let mut dominant_value = <F as Zero>::zero();
+
+        for axis in 0..dimension {
+            let value = (relative[axis] / half_extents[axis]).abs();
+
+            if value > dominant_value {
+                dominant = axis;
+                dominant_value = value;
+            }
+        }
+
+        let (axis_u, axis_v) = face_axes(dominant, dimension);
+        let half = cast(0.5);
+        let mut u = relative[axis_u] / half_extents[axis_u];
+
+        // Keep the texture from being mirrored on the opposite face
+        if relative[dominant] < <F as Zero>::zero() {
+            u = -u;
+        }
+
+        Point2::new((u + <F as One>::one()) * half,
+                    (relative[axis_v] / half_extents[axis_v] + <F as One>::one()) * half)
+    })
+}
+
+/// Blends planar projections of the texture along each axis, weighted by the alignment of
+/// the normal with the axis. Textures surfaces of any shape without a parametrization,
+/// with a higher `sharpness` making the transitions between the projections narrower.
+pub struct MappedTextureTriplanar<P: CustomPoint<V>, V: CustomVector<P>> {
+    pub texture: Box<Texture>,
+    pub origin: P,
+    pub size: F,
+    pub sharpness: F,
+    marker_vector: PhantomData<V>,
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> MappedTextureTriplanar<P, V> {
+    pub fn new(texture: Box<Texture>, origin: P, size: F, sharpness: F) -> Self {
+        MappedTextureTriplanar {
+            texture: texture,
+            origin: origin,
+            size: size,
+            sharpness: sharpness,
+            marker_vector: PhantomData,
+        }
+    }
+
+    fn blend(&self, point: &P, normal: &V) -> Rgba<F> {
+        let dimension = <P as Dimension>::dimension(None);
+        let relative = *point - self.origin;
+        let mut sum: [F; 4] = [<F as Zero>::zero(); 4];
+        let mut weight_sum = <F as Zero>::zero();
+
+        for axis in 0..dimension {
+            let weight = normal[axis].abs().powf(self.sharpness);
+
+            if weight <= <F as Zero>::zero() {
+                continue;
+            }
+
+            let (axis_u, axis_v) = face_axes(axis, dimension);
+            let color = (self.texture)(&Point2::new(relative[axis_u] / self.size,
+                                                    relative[axis_v] / self.size));
+
+            sum[0] += color.color.red * weight;
+            sum[1] += color.color.green * weight;
+            sum[2] += color.color.blue * weight;
+            sum[3] += color.alpha * weight;
+            weight_sum += weight;
+        }
+
+        if weight_sum <= <F as Zero>::zero() {
+            return Rgba::new(<F as Zero>::zero(), <F as Zero>::zero(),
+                             <F as Zero>::zero(), <F as Zero>::zero());
+        }
+
+        Rgba::new(sum[0] / weight_sum, sum[1] / weight_sum,
+                  sum[2] / weight_sum, sum[3] / weight_sum)
+    }
+}
+
+impl<P: CustomPoint<V>, V: CustomVector<P>> MappedTexture<P, V> for MappedTextureTriplanar<P, V> {
+    /// Without a normal, the direction from the origin is used instead.
+    fn get_color(&self, point: &P) -> Rgba<F> {
+        self.blend(point, &(*point - self.origin))
+    }
+
+    fn get_color_filtered(&self, context: &TracingContext<P, V>) -> Rgba<F> {
+        self.blend(&context.intersection.location, &context.intersection.normal.normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use na::ApproxEq;
+    use universe::d3::Point3;
+    use universe::d3::Vector3;
+    use universe::d4::Point4;
+    use universe::d4::Vector4;
+    use super::*;
+
+    #[test]
+    fn planar_repeats_along_axes() {
+        let uvfn = uv_planar(Point3::new(1.0, 0.0, 0.0),
+                             Vector3::new(2.0, 0.0, 0.0),
+                             Vector3::new(0.0, 0.5, 0.0));
+        let uv = uvfn(&Point3::new(2.0, 0.25, 7.0));
+
+        assert!(uv.x.approx_eq(&0.5));
+        assert!(uv.y.approx_eq(&0.5));
+    }
+
+    #[test]
+    fn cylindrical_angle_and_height() {
+        let uvfn = uv_cylindrical(Point3::new(0.0, 0.0, 0.0),
+                                  Vector3::new(0.0, 0.0, 4.0),
+                                  Vector3::new(1.0, 0.0, 0.0),
+                                  Vector3::new(0.0, 1.0, 0.0));
+        let uv = uvfn(&Point3::new(0.0, 3.0, 2.0));
+
+        assert!(uv.x.approx_eq(&0.75));
+        assert!(uv.y.approx_eq(&0.5));
+    }
+
+    #[test]
+    fn box_selects_dominant_face() {
+        let uvfn = uv_box(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 2.0, 1.0));
+        // Closest to the `+z` face, mapped by the `x` and `y` axes
+        let uv = uvfn(&Point3::new(0.5, 1.0, 1.0));
+
+        assert!(uv.x.approx_eq(&0.75));
+        assert!(uv.y.approx_eq(&0.75));
+    }
+
+    #[test]
+    fn axes_pick_coordinates() {
+        let uvfn = uv_axes::<Point4, Vector4>(1, 3, 2.0);
+        let uv = uvfn(&Point4::new(5.0, 1.0, 5.0, 3.0));
+
+        assert!(uv.x.approx_eq(&0.5));
+        assert!(uv.y.approx_eq(&1.5));
+    }
+}
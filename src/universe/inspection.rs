@@ -0,0 +1,290 @@
+use ::F;
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::Index;
+use json::JsonValue;
+use na::Dimension;
+use palette::Rgba;
+
+/// The reason a ray was traced
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayKind {
+    /// Traced from the camera
+    Primary,
+    /// Reflected off a surface
+    Reflection,
+    /// Transmitted through a surface
+    Transmission,
+    /// Continued through a surface overshadowed by a medium with a higher priority
+    PassThrough,
+    /// Traced by a surface without specifying the reason
+    Secondary,
+}
+
+impl fmt::Display for RayKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            RayKind::Primary => "primary",
+            RayKind::Reflection => "reflection",
+            RayKind::Transmission => "transmission",
+            RayKind::PassThrough => "pass_through",
+            RayKind::Secondary => "secondary",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The closest surface hit by a ray
+#[derive(Clone, Debug, PartialEq)]
+pub struct RayHit {
+    pub distance: F,
+    pub location: Vec<F>,
+    /// The geometric normal of the shape
+    pub normal: Vec<F>,
+    pub exiting: bool,
+    pub shape: String,
+    /// The material the ray travelled through before the surface
+    pub exited_material: Option<String>,
+    /// The material the ray travels through after passing the surface
+    pub entered_material: Option<String>,
+}
+
+/// A traced ray segment with all the rays it caused
+#[derive(Clone, Debug, PartialEq)]
+pub struct RayNode {
+    pub kind: RayKind,
+    pub depth_remaining: u32,
+    pub origin: Vec<F>,
+    pub direction: Vec<F>,
+    pub wavelength: Option<F>,
+    pub hit: Option<RayHit>,
+    pub reflection_ratio: Option<F>,
+    /// The color the ray contributed, before it is mixed with the other rays
+    pub color: Option<Rgba<F>>,
+    pub children: Vec<RayNode>,
+}
+
+/// The ray trees traced for a single pixel, one per traced wavelength
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RayInspection {
+    pub screen_x: i32,
+    pub screen_y: i32,
+    pub roots: Vec<RayNode>,
+}
+
+fn coordinates_to_json(coordinates: &[F]) -> JsonValue {
+    JsonValue::Array(coordinates.iter().map(|coordinate| (*coordinate).into()).collect())
+}
+
+fn option_to_json<T, C: Fn(&T) -> JsonValue>(value: &Option<T>, convert: C) -> JsonValue {
+    value.as_ref().map_or(JsonValue::Null, convert)
+}
+
+impl RayHit {
+    pub fn to_json(&self) -> JsonValue {
+        let mut result = JsonValue::new_object();
+
+        result["distance"] = self.distance.into();
+        result["location"] = coordinates_to_json(&self.location);
+        result["normal"] = coordinates_to_json(&self.normal);
+        result["exiting"] = self.exiting.into();
+        result["shape"] = self.shape.clone().into();
+        result["exited_material"] = option_to_json(&self.exited_material, |name| name.clone().into());
+        result["entered_material"] = option_to_json(&self.entered_material, |name| name.clone().into());
+
+        result
+    }
+}
+
+impl RayNode {
+    pub fn to_json(&self) -> JsonValue {
+        let mut result = JsonValue::new_object();
+
+        result["kind"] = self.kind.to_string().into();
+        result["depth_remaining"] = self.depth_remaining.into();
+        result["origin"] = coordinates_to_json(&self.origin);
+        result["direction"] = coordinates_to_json(&self.direction);
+        result["wavelength"] = option_to_json(&self.wavelength, |wavelength| (*wavelength).into());
+        result["hit"] = option_to_json(&self.hit, RayHit::to_json);
+        result["reflection_ratio"] = option_to_json(&self.reflection_ratio, |ratio| (*ratio).into());
+        result["color"] = option_to_json(&self.color, |color| {
+            coordinates_to_json(&[color.color.red, color.color.green, color.color.blue, color.alpha])
+        });
+        result["children"] = JsonValue::Array(self.children.iter().map(RayNode::to_json).collect());
+
+        result
+    }
+
+    /// Collects the segments of this ray and all of its children that hit a surface
+    fn collect_segments<'a>(&'a self, segments: &mut Vec<(RayKind, &'a [F], &'a [F])>) {
+        if let Some(ref hit) = self.hit {
+            segments.push((self.kind, &self.origin[..], &hit.location[..]));
+        }
+
+        for child in &self.children {
+            child.collect_segments(segments);
+        }
+    }
+}
+
+impl RayInspection {
+    pub fn to_json(&self) -> JsonValue {
+        let mut result = JsonValue::new_object();
+
+        result["screen_x"] = self.screen_x.into();
+        result["screen_y"] = self.screen_y.into();
+        result["rays"] = JsonValue::Array(self.roots.iter().map(RayNode::to_json).collect());
+
+        result
+    }
+
+    /// The start and end points of the traced segments that hit a surface
+    pub fn segments(&self) -> Vec<(RayKind, &[F], &[F])> {
+        let mut result = Vec::new();
+
+        for root in &self.roots {
+            root.collect_segments(&mut result);
+        }
+
+        result
+    }
+}
+
+struct Recording {
+    /// The rays being traced, the last one is the innermost
+    stack: Vec<RayNode>,
+    roots: Vec<RayNode>,
+    next_kind: Option<RayKind>,
+}
+
+thread_local! {
+    static RECORDING: RefCell<Option<Recording>> = RefCell::new(None);
+}
+
+/// Records the ray trees traced by the current thread while executing the action.
+/// The rays are only recorded when traced with the `debug` flag set.
+pub fn inspect<A: FnOnce()>(screen_x: i32, screen_y: i32, action: A) -> RayInspection {
+    RECORDING.with(|recording| {
+        *recording.borrow_mut() = Some(Recording {
+            stack: Vec::new(),
+            roots: Vec::new(),
+            next_kind: None,
+        });
+    });
+
+    action();
+
+    let recording = RECORDING.with(|recording| recording.borrow_mut().take())
+        .expect("The ray inspection recording was removed while tracing.");
+
+    RayInspection {
+        screen_x: screen_x,
+        screen_y: screen_y,
+        roots: recording.roots,
+    }
+}
+
+fn with_recording<T: FnOnce(&mut Recording)>(action: T) {
+    RECORDING.with(|recording| {
+        if let Some(ref mut recording) = *recording.borrow_mut() {
+            action(recording);
+        }
+    });
+}
+
+pub fn coordinates<T: Index<usize, Output = F> + Dimension>(value: &T) -> Vec<F> {
+    (0..<T as Dimension>::dimension(None)).map(|index| value[index]).collect()
+}
+
+/// Marks the reason the next ray is traced for.
+pub fn set_next_kind(kind: RayKind) {
+    with_recording(|recording| recording.next_kind = Some(kind));
+}
+
+pub fn begin_ray(origin: Vec<F>, direction: Vec<F>, wavelength: Option<F>, depth_remaining: u32) {
+    with_recording(|recording| {
+        let kind = recording.next_kind.take().unwrap_or_else(|| {
+            if recording.stack.is_empty() {
+                RayKind::Primary
+            } else {
+                RayKind::Secondary
+            }
+        });
+
+        recording.stack.push(RayNode {
+            kind: kind,
+            depth_remaining: depth_remaining,
+            origin: origin,
+            direction: direction,
+            wavelength: wavelength,
+            hit: None,
+            reflection_ratio: None,
+            color: None,
+            children: Vec::new(),
+        });
+    });
+}
+
+pub fn record_hit(hit: RayHit) {
+    with_recording(|recording| {
+        if let Some(node) = recording.stack.last_mut() {
+            node.hit = Some(hit);
+        }
+    });
+}
+
+pub fn record_reflection_ratio(reflection_ratio: F) {
+    with_recording(|recording| {
+        if let Some(node) = recording.stack.last_mut() {
+            node.reflection_ratio = Some(reflection_ratio);
+        }
+    });
+}
+
+pub fn end_ray(color: &Rgba<F>) {
+    with_recording(|recording| {
+        if let Some(mut node) = recording.stack.pop() {
+            node.color = Some(*color);
+
+            match recording.stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => recording.roots.push(node),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use palette::Rgba;
+    use super::*;
+
+    #[test]
+    fn builds_ray_tree() {
+        let color = Rgba::new(0.5, 0.5, 0.5, 1.0);
+
+        // Not recorded outside of an inspection
+        begin_ray(vec![0.0], vec![1.0], None, 1);
+        end_ray(&color);
+
+        let inspection = inspect(1, 2, || {
+            begin_ray(vec![0.0, 0.0, 0.0], vec![1.0, 0.0, 0.0], None, 2);
+            record_reflection_ratio(0.25);
+            set_next_kind(RayKind::Reflection);
+            begin_ray(vec![1.0, 0.0, 0.0], vec![-1.0, 0.0, 0.0], None, 1);
+            end_ray(&color);
+            set_next_kind(RayKind::Transmission);
+            begin_ray(vec![1.0, 0.0, 0.0], vec![1.0, 0.0, 0.0], None, 1);
+            end_ray(&color);
+            end_ray(&color);
+        });
+
+        assert_eq!(inspection.roots.len(), 1);
+        assert_eq!(inspection.roots[0].kind, RayKind::Primary);
+        assert_eq!(inspection.roots[0].reflection_ratio, Some(0.25));
+        assert_eq!(inspection.roots[0].children.len(), 2);
+        assert_eq!(inspection.roots[0].children[1].kind, RayKind::Transmission);
+        assert_eq!(inspection.to_json()["rays"][0]["children"][0]["kind"], "reflection");
+    }
+}
@@ -2,14 +2,33 @@
 pub mod entity;
 pub mod d3;
 pub mod d4;
+pub mod spectrum;
+pub mod tone_mapping;
+pub mod statistics;
+pub mod inspection;
+pub mod render_pass;
+pub mod aov;
+pub mod denoise;
 
 use std::time::Duration;
+use std::time::Instant;
 use std::borrow::Cow;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::RwLock;
+use std::ops::Mul;
+use std::ops::Sub;
+use na;
 use na::Cast;
+use na::Norm;
+use na::Dot;
 use na::BaseFloat;
+use na::ApproxEq;
+use na::Dimension;
+use num::Zero;
 use glium::texture::ClientFormat;
-use image;
 use palette::Blend;
 use palette::Rgb;
 use palette::Rgba;
@@ -20,21 +39,38 @@ use universe::entity::Entity;
 use universe::entity::Camera;
 use universe::entity::Traceable;
 use universe::entity::material::Material;
+use universe::entity::medium::MediumStack;
 use universe::entity::shape::Shape;
 use universe::entity::shape::GeneralIntersectors;
 use universe::entity::shape::Intersection;
 use universe::entity::shape::Intersector;
 use universe::entity::shape::TracingContext;
+use universe::entity::shape::RayCone;
 use universe::entity::shape::ColorTracingContext;
 use universe::entity::shape::PathTracingContext;
 use universe::entity::surface::MappedTexture;
+use universe::tone_mapping::ToneMapper;
+use universe::statistics::FrameStatistics;
+use universe::inspection::RayHit;
+use universe::inspection::RayInspection;
+use universe::inspection::RayKind;
+use universe::render_pass::RenderPass;
+use universe::aov::AovImage;
+use universe::aov::AovLayer;
+use universe::aov::AovRecorder;
+use universe::aov::AovSample;
+use universe::entity::lens;
+use universe::entity::lens::LensSample;
+use universe::entity::stereo;
+use universe::entity::stereo::Eye;
+use universe::entity::stereo::Stereo;
+use universe::entity::stereo::StereoLayout;
 use universe::entity::shape::IntersectionProvider;
 use util::CustomPoint;
 use util::CustomVector;
 use util::CustomFloat;
 use util::VectorAsPoint;
 use util::AngleBetween;
-use util::Provider;
 use ::F;
 
 pub type TraceResult<'a, P, V> = (&'a Traceable<P, V>,
@@ -57,6 +93,17 @@ pub trait Universe
     fn background_mut(&mut self) -> &mut MappedTexture<Self::P, Self::V>;
     fn background(&self) -> &MappedTexture<Self::P, Self::V>;
     fn set_background(&mut self, background: Box<MappedTexture<Self::P, Self::V>>);
+    fn tone_mapper(&self) -> &ToneMapper;
+    fn set_tone_mapper(&mut self, tone_mapper: ToneMapper);
+    /// The normal of the hyperplane the universe is sliced with, see `Camera::cross_section`.
+    /// It is read by every traced ray, so it is stored in the universe instead of locking
+    /// the camera.
+    fn cross_section(&self) -> Option<Self::V> {
+        None
+    }
+
+    /// Stores the cross section of the camera after it is updated
+    fn set_cross_section(&mut self, _cross_section: Option<Self::V>) {}
 
     fn intersect(&self,
                  location: &Self::P,
@@ -68,18 +115,15 @@ pub trait Universe
         let shape_id = shape.id();
         let intersector = self.intersectors().get(&(material_id, shape_id));
 
-        let intersector = intersector.expect(&format!("Couldn't find an intersector for material {} and shape {}.",
-                                             material, shape));
-        // if intersector.is_none() {
-        //     continue;
-        // }
-
-        // let intersector = intersector.unwrap();
+        statistics::record_intersector_call(material_id, shape_id, material, shape);
 
+        let intersector = intersector.unwrap_or_else(|| {
+            panic!("Couldn't find an intersector for material {} and shape {}.", material, shape)
+        });
         let intersect: Intersector<Self::P, Self::V> =
             &move |material, shape| self.intersect(location, direction, material, shape);
 
-        Provider::new(intersector(location, direction, material, shape, intersect))
+        intersector(location, direction, material, shape, intersect).peekable()
     }
 
     fn trace_closest<'a>(&'a self,
@@ -87,12 +131,15 @@ pub trait Universe
                          belongs_to: &'a Traceable<Self::P, Self::V>,
                          location: &Self::P,
                          direction: &Self::V,
+                         wavelength: Option<F>,
+                         cone: RayCone,
                          debug: bool,
                          filter: &Fn(&Traceable<Self::P, Self::V>) -> bool)
                          -> Option<TraceResult<'a, Self::P, Self::V>> {
         let material = belongs_to.material();
         let mut closest: Option<TraceResult<'a, Self::P, Self::V>> = None;
         let mut closest_distance: Option<F> = None;
+        let cross_section = self.cross_section();
 
         for other in self.entities() {
             let other_traceable = other.as_traceable();
@@ -108,10 +155,14 @@ pub trait Universe
             }
 
             let shape = other_traceable.shape();
-            let provider = self.intersect(location, direction, material, shape);
-            let mut provider_iter = provider.iter();
+            let mut provider = self.intersect(location, direction, material, shape);
+
+            if let Some(mut intersection) = provider.next() {
+                if let Some(ref section_normal) = cross_section {
+                    intersection.normal = project_onto_cross_section(&intersection.normal,
+                                                                     section_normal);
+                }
 
-            if let Some(intersection) = provider_iter.next() {
                 let exiting: bool;
                 let closer_normal: Self::V;
 
@@ -129,6 +180,8 @@ pub trait Universe
                     let context = TracingContext {
                         debugging: debug,
                         time: *time,
+                        wavelength: wavelength,
+                        cone: cone.propagate(intersection.distance),
                         origin_traceable: belongs_to,
                         origin_location: *location,
                         origin_direction: *direction,
@@ -146,75 +199,193 @@ pub trait Universe
         closest
     }
 
-    fn trace(&self,
-             time: &Duration,
-             max_depth: &u32,
-             belongs_to: &Traceable<Self::P, Self::V>,
-             location: &Self::P,
-             direction: &Self::V,
-             debug: bool)
-             -> Rgba<F> {
-        if *max_depth > 0 {
-            let result = self.trace_closest(time, belongs_to, location, direction, debug, &|other| {
-                other.surface().is_some()
-            });
+    fn trace<'a>(&'a self,
+                 time: &Duration,
+                 max_depth: &u32,
+                 media: &MediumStack<'a, Self::P, Self::V>,
+                 location: &Self::P,
+                 direction: &Self::V,
+                 wavelength: Option<F>,
+                 cone: RayCone,
+                 debug: bool,
+                 aovs: Option<&AovRecorder>)
+                 -> Rgba<F> {
+        let belongs_to = media.current();
+
+        if debug {
+            inspection::begin_ray(inspection::coordinates(location),
+                                  inspection::coordinates(direction),
+                                  wavelength,
+                                  *max_depth);
+        }
 
-            if result.is_some() {
-                let (closest, general_context) = result.unwrap();
+        let result = if *max_depth > 0 {
+            statistics::record_ray(*max_depth);
+            belongs_to.and_then(|belongs_to| {
+                self.trace_closest(time, belongs_to, location, direction, wavelength, cone, debug,
+                                   &|other| other.surface().is_some())
+            })
+        } else {
+            None
+        };
+
+        let (color, distance) = if let Some((closest, general_context)) = result {
+            let distance = general_context.intersection.distance;
+            let cone_at_intersection = general_context.cone;
+
+            if debug {
+                let entered = media.transition(closest, general_context.exiting).current();
+
+                inspection::record_hit(RayHit {
+                    distance: distance,
+                    location: inspection::coordinates(&general_context.intersection.location),
+                    normal: inspection::coordinates(&general_context.intersection.normal),
+                    exiting: general_context.exiting,
+                    shape: closest.shape().to_string(),
+                    exited_material: belongs_to.map(|medium| medium.material().to_string()),
+                    entered_material: entered.map(|medium| medium.material().to_string()),
+                });
+            }
+
+            let color = if !media.is_boundary(closest) {
+                // The surface is overshadowed by a medium with a higher priority,
+                // pass through it without changing the medium.
+                let new_origin = general_context.intersection.location +
+                    -general_context.intersection_normal_closer
+                    * <F as ApproxEq<F>>::approx_epsilon(None) * Cast::from(128.0);
+                let new_media = media.transition(closest, general_context.exiting);
+
+                if debug {
+                    inspection::set_next_kind(RayKind::PassThrough);
+                }
+
+                self.trace(time, &(*max_depth - 1), &new_media, &new_origin,
+                           &general_context.intersection.direction, wavelength,
+                           cone_at_intersection, debug, aovs)
+            } else {
+                if let Some(aovs) = aovs {
+                    aovs.record_hit(distance,
+                                    aov::normal_coordinates(&general_context.intersection.normal),
+                                    render_pass::entity_id(closest));
+                }
 
                 let context = ColorTracingContext {
                     general: general_context,
                     depth_remaining: max_depth,
-                    trace: &|time, traceable, location, direction| {
-                        self.trace(time, &(*max_depth - 1), traceable, location, direction, debug)
-                    },
-                    material_at: &|point| {
-                        self.material_at(point)
+                    media: media,
+                    trace: &|time, media, location, direction| {
+                        self.trace(time, &(*max_depth - 1), media, location, direction,
+                                   wavelength, cone_at_intersection, debug, None)
                     },
+                    aovs: aovs,
                 };
 
                 // We can safely unwrap here, because we filtered out all the entities without a surface.
                 let surface = closest.surface().unwrap();
 
-                return surface.get_color(context);
-            }
+                surface.get_color(context)
+            };
+
+            (color, Some(distance))
+        } else {
+            (self.background().get_color(&direction.to_point()), None)
+        };
+
+        // Apply the effects of the medium along the traced segment
+        let color = match belongs_to {
+            Some(belongs_to) => belongs_to.material()
+                .attenuate(time, location, direction, distance, color),
+            None => color,
+        };
+
+        if debug {
+            inspection::end_ray(&color);
         }
 
-        self.background().get_color(&direction.to_point())
+        color
     }
 
-    fn trace_path(&self,
-             time: &Duration,
-             distance: &F,
-             belongs_to: &Traceable<Self::P, Self::V>,
-             location: &Self::P,
-             direction: &Self::V,
-             debug: bool)
-             -> (Self::P, Self::V) {
-        let result = self.trace_closest(time, belongs_to, location, direction, debug, &|other| {
-            other.surface().is_some()
-        });
+    fn trace_path<'a>(&'a self,
+                      time: &Duration,
+                      distance: &F,
+                      media: &MediumStack<'a, Self::P, Self::V>,
+                      location: &Self::P,
+                      direction: &Self::V,
+                      collision: Option<Collision>,
+                      debug: bool)
+                      -> (Self::P, Self::V) {
+        let belongs_to = media.current()
+            .expect("Could not trace the path, the ray is not travelling through any medium.");
+        let result = self.trace_closest(time, belongs_to, location, direction, None,
+                                        RayCone::default(), debug,
+                                        &|other| other.surface().is_some());
 
         if result.is_some() {
             let (closest, general_context) = result.unwrap();
 
-            let context = PathTracingContext {
-                general: general_context,
-                distance: distance,
-                trace: &|time, distance, traceable, location, direction| {
-                    self.trace_path(time, distance, traceable, location, direction, debug)
-                },
-                material_at: &|point| {
-                    self.material_at(point)
-                },
-            };
+            if let Some(collision) = collision {
+                let normal = general_context.intersection_normal_closer;
+                let approach = -na::dot(direction, &normal);
+
+                if closest.is_solid() && !general_context.exiting
+                   && approach > <F as ApproxEq<F>>::approx_epsilon(None)
+                   && general_context.intersection.distance < *distance + collision.radius / approach {
+                    // Stop in front of the surface and slide along it with the rest of the distance
+                    let travel = (general_context.intersection.distance - collision.radius / approach)
+                        .max(<F as Zero>::zero())
+                        .min(*distance);
+                    let material = belongs_to.material();
+                    let (stop_location, stop_direction) =
+                        material.trace_path(location, direction, &travel);
+                    let mut exited_direction = stop_direction;
+                    let slide = stop_direction - normal * na::dot(&stop_direction, &normal);
+                    let slide_distance = (*distance - travel) * slide.norm();
+
+                    material.exit(&stop_location, &mut exited_direction);
+
+                    if collision.slides == 0
+                       || slide_distance <= <F as ApproxEq<F>>::approx_epsilon(None) {
+                        return (stop_location, exited_direction);
+                    }
+
+                    let (slide_location, _) =
+                        self.trace_path(time, &slide_distance, media, &stop_location,
+                                        &slide.normalize(), Some(collision.slide()), debug);
+
+                    return (slide_location, exited_direction);
+                }
+            }
+
+            if media.is_boundary(closest) {
+                let context = PathTracingContext {
+                    general: general_context,
+                    distance: distance,
+                    media: media,
+                    trace: &|time, distance, media, location, direction| {
+                        self.trace_path(time, distance, media, location, direction, collision,
+                                        debug)
+                    },
+                };
 
-            // We can safely unwrap here, because we filtered out all the entities without a surface.
-            let surface = closest.surface().unwrap();
-            let path = surface.get_path(context);
+                // We can safely unwrap here, because we filtered out all the entities without a surface.
+                let surface = closest.surface().unwrap();
+                let path = surface.get_path(context);
 
-            if path.is_some() {
-                return path.unwrap();
+                if path.is_some() {
+                    return path.unwrap();
+                }
+            } else if *distance > general_context.intersection.distance {
+                // The surface is overshadowed by a medium with a higher priority,
+                // pass through it without changing the medium.
+                let new_distance = *distance - general_context.intersection.distance;
+                let new_origin = general_context.intersection.location +
+                    -general_context.intersection_normal_closer
+                    * <F as ApproxEq<F>>::approx_epsilon(None) * Cast::from(128.0);
+                let new_media = media.transition(closest, general_context.exiting);
+
+                return self.trace_path(time, &new_distance, &new_media, &new_origin,
+                                       &general_context.intersection.direction, collision,
+                                       debug);
             }
         }
 
@@ -226,10 +397,13 @@ pub trait Universe
         (new_location, new_direction)
     }
 
-    fn material_at(&self, location: &Self::P) -> Option<&Traceable<Self::P, Self::V>> {
-        let mut belongs_to: Option<&Traceable<Self::P, Self::V>> = None;
+    /// Collects all the media containing the given location.
+    fn media_at(&self, location: &Self::P) -> MediumStack<Self::P, Self::V> {
+        let mut media = MediumStack::new();
 
-        for entity in self.entities() {
+        // Iterate in reverse, so that the entity declared first is picked
+        // among entities with equal priorities.
+        for entity in self.entities().iter().rev() {
             let traceable = entity.as_traceable();
 
             if traceable.is_none() {
@@ -243,11 +417,14 @@ pub trait Universe
                 continue;
             }
 
-            belongs_to = Some(&*traceable);
-            break;
+            media.push(traceable);
         }
 
-        belongs_to
+        media
+    }
+
+    fn material_at(&self, location: &Self::P) -> Option<&Traceable<Self::P, Self::V>> {
+        self.media_at(location).current()
     }
 
     fn trace_unknown(&self,
@@ -255,39 +432,81 @@ pub trait Universe
                      max_depth: &u32,
                      location: &Self::P,
                      direction: &Self::V,
-                     debug: bool)
+                     wavelength: Option<F>,
+                     cone: RayCone,
+                     debug: bool,
+                     aovs: Option<&AovRecorder>)
                      -> Option<Rgb<F>> {
-        self.material_at(location).map(|belongs_to| {
+        let media = self.media_at(location);
+
+        media.current().map(|belongs_to| {
             let mut transitioned_direction = *direction;
             belongs_to.material().enter(location, &mut transitioned_direction);
             let background =
                 Rgba::<F>::from(Rgb::<F>::new(Cast::from(1.0), Cast::from(1.0), Cast::from(1.0)))
                     .into_premultiplied();
-            let foreground = self.trace(time, max_depth, belongs_to, location,
-                                        &transitioned_direction, debug)
+            let foreground = self.trace(time, max_depth, &media, location,
+                                        &transitioned_direction, wavelength, cone, debug, aovs)
                 .into_premultiplied();
             Rgb::<F>::from_premultiplied(foreground.over(background))
         })
     }
 
+    /// The distance to the closest entity accepted by the filter in the direction,
+    /// `None` if nothing is hit
+    fn trace_distance_unknown(&self,
+                              time: &Duration,
+                              location: &Self::P,
+                              direction: &Self::V,
+                              filter: &Fn(&Traceable<Self::P, Self::V>) -> bool)
+                              -> Option<F> {
+        let media = self.media_at(location);
+
+        media.current().and_then(|belongs_to| {
+            self.trace_closest(time, belongs_to, location, direction, None,
+                               RayCone::new(<F as Zero>::zero(), <F as Zero>::zero()),
+                               false, filter)
+                .map(|(_, context)| context.intersection.distance)
+        })
+    }
+
     fn trace_path_unknown(&self,
                           time: &Duration,
                           distance: &F,
                           location: &Self::P,
                           direction: &Self::V,
+                          collision: Option<Collision>,
                           debug: bool)
                           -> Option<(Self::P, Self::V)> {
-        self.material_at(location).map(|belongs_to| {
+        let media = self.media_at(location);
+
+        media.current().map(|belongs_to| {
             let mut transitioned_direction = *direction;
 
             belongs_to.material().enter(location, &mut transitioned_direction);
-            self.trace_path(time, distance, belongs_to, location, &transitioned_direction, debug)
+            self.trace_path(time, distance, &media, location, &transitioned_direction, collision,
+                            debug)
         })
     }
 }
 
+/// The inputs of a single sample of a pixel traced by `Environment::trace_screen_point`
+#[derive(Clone, Copy)]
+pub struct ScreenSample<'a> {
+    /// The point on the lens the ray passes through
+    pub lens_sample: LensSample,
+    /// The eye of a stereo camera the ray is cast from, `None` for the whole screen
+    pub eye: Option<Eye>,
+    /// The traced wavelength in nanometres, `None` when tracing all of the visible spectrum
+    pub wavelength: Option<F>,
+    pub debug: bool,
+    /// Collects the output variables of the surface hit by the ray
+    pub aovs: Option<&'a AovRecorder>,
+}
+
 pub trait Environment: Sync {
     fn max_depth(&self) -> u32;
+    fn tone_mapper(&self) -> ToneMapper;
     fn trace_screen_point(&self,
                           time: &Duration,
                           max_depth: &u32,
@@ -295,68 +514,207 @@ pub trait Environment: Sync {
                           screen_y: i32,
                           screen_width: i32,
                           screen_height: i32,
-                          debug: bool)
+                          sample: &ScreenSample)
                           -> Rgb<F>;
+    /// Computes the color of the debug render pass for the pixel
+    fn trace_screen_point_pass(&self,
+                               time: &Duration,
+                               max_depth: &u32,
+                               screen_x: i32,
+                               screen_y: i32,
+                               screen_width: i32,
+                               screen_height: i32,
+                               pass: RenderPass)
+                               -> Rgb<F>;
+    /// Renders the frame, `preview` is repeatedly given the partially rendered frame
+    /// while the frame takes longer than `PREVIEW_INTERVAL_MILLIS`.
     fn render(&self,
               dimensions: (u32, u32),
               time: &Duration,
-              threads: u32,
-              context: &SimulationContext)
+              pool: &mut Pool,
+              context: &SimulationContext,
+              statistics: Option<&Mutex<FrameStatistics>>,
+              preview: Option<&mut FnMut(RawImage2d<u8>)>)
               -> RawImage2d<u8> {
         let (width, height) = dimensions;
-        const COLOR_DIM: usize = 3;
         let buffer_width = width / context.resolution;
         let buffer_height = height / context.resolution;
         let max_depth = self.max_depth();
-        let mut data: Vec<u8> = vec!(0; (buffer_width * buffer_height) as usize * COLOR_DIM);
-        let mut pool = Pool::new(threads);
+        let tone_mapper = if context.render_pass == RenderPass::Shaded {
+            context.tone_mapper(self.tone_mapper())
+        } else {
+            // The debug passes are displayed as computed
+            ToneMapper::default()
+        };
         let buffer_width_half = buffer_width / 2;
         let buffer_height_half = buffer_height / 2;
+        let anaglyph = self.stereo()
+            .map_or(false, |stereo| stereo.layout == StereoLayout::Anaglyph);
+        let mut preview_pixels = preview.map(|preview| {
+            move |pixels: &[[u8; 3]]| preview(raw_image(pixels, buffer_width, buffer_height))
+        });
+        let preview_pixels = preview_pixels.as_mut()
+            .map(|preview| preview as &mut FnMut(&[[u8; 3]]));
+        let pixels = render_buffer(buffer_width, buffer_height, pool, statistics, preview_pixels, |x, y| {
+            let debug_pixel = context.debugging
+                            && x == buffer_width_half
+                            && y == buffer_height_half;
+            let debug_pixel_surrounding = context.debugging
+                            && (x == buffer_width_half
+                                && (y == buffer_height_half - 1
+                                    || y == buffer_height_half + 1)
+                                || y == buffer_height_half
+                                && (x == buffer_width_half - 1
+                                    || x == buffer_width_half + 1));
 
-        pool.scoped(|scope| {
-            for (index, chunk) in &mut data.chunks_mut(COLOR_DIM).enumerate() {
-                scope.execute(move || {
-                    let x = index as u32 % buffer_width;
-                    let y = index as u32 / buffer_width;
-                    let debug_pixel = context.debugging
-                                    && x == buffer_width_half
-                                    && y == buffer_height_half;
-                    let debug_pixel_surrounding = context.debugging
-                                    && (x == buffer_width_half
-                                        && (y == buffer_height_half - 1
-                                            || y == buffer_height_half + 1)
-                                        || y == buffer_height_half
-                                        && (x == buffer_width_half - 1
-                                            || x == buffer_width_half + 1));
-                    let color = if debug_pixel_surrounding {
-                        Rgb::new_u8(255, 0, 0)
-                    } else {
+            if debug_pixel_surrounding {
+                return [255, 0, 0];
+            }
+
+            let color = if context.render_pass != RenderPass::Shaded {
+                self.trace_screen_point_pass(time,
+                                             &max_depth,
+                                             x as i32,
+                                             y as i32,
+                                             buffer_width as i32,
+                                             buffer_height as i32,
+                                             context.render_pass)
+            } else {
+                let trace_eye = |eye: Option<Eye>| lens::integrate(context.samples, (x as i32, y as i32), |lens_sample| {
+                    let trace_wavelength = |wavelength: Option<F>| {
                         self.trace_screen_point(time,
                                                 &max_depth,
                                                 x as i32,
                                                 y as i32,
                                                 buffer_width as i32,
                                                 buffer_height as i32,
-                                                debug_pixel)
+                                                &ScreenSample {
+                                                    lens_sample: lens_sample,
+                                                    eye: eye,
+                                                    wavelength: wavelength,
+                                                    debug: debug_pixel,
+                                                    aovs: None,
+                                                })
                     };
-                    let color = image::Rgb { data: color.to_pixel() };
 
-                    for (i, result) in chunk.iter_mut().enumerate() {
-                        *result = color.data[i];
+                    if context.spectral_samples > 0 {
+                        spectrum::integrate(context.spectral_samples,
+                                            |wavelength| trace_wavelength(Some(wavelength)))
+                    } else {
+                        trace_wavelength(None)
                     }
                 });
+
+                if anaglyph {
+                    stereo::anaglyph(trace_eye(Some(Eye::Left)), trace_eye(Some(Eye::Right)))
+                } else {
+                    trace_eye(None)
+                }
+            };
+
+            tone_mapper.to_pixel(color)
+        });
+
+        raw_image(&pixels, buffer_width, buffer_height)
+    }
+
+    /// Renders the beauty image along with the other output variables of each pixel.
+    fn render_aovs(&self,
+                   dimensions: (u32, u32),
+                   time: &Duration,
+                   pool: &mut Pool,
+                   context: &SimulationContext,
+                   layers: &[AovLayer],
+                   statistics: Option<&Mutex<FrameStatistics>>)
+                   -> AovImage {
+        let (width, height) = dimensions;
+        let buffer_width = width / context.resolution;
+        let buffer_height = height / context.resolution;
+        let max_depth = self.max_depth();
+        let anaglyph = self.stereo()
+            .map_or(false, |stereo| stereo.layout == StereoLayout::Anaglyph);
+        let samples = render_buffer(buffer_width, buffer_height, pool, statistics, None, |x, y| {
+            let pixel_samples = context.samples.max(1);
+            let trace_eye = |eye: Option<Eye>| {
+                let lens_samples: Vec<AovSample> = (0..pixel_samples).map(|index| {
+                    let lens_sample = lens::lens_sample(index, (x as i32, y as i32));
+
+                    aov::trace_aovs(context.spectral_samples, |wavelength, recorder| {
+                        self.trace_screen_point(time,
+                                                &max_depth,
+                                                x as i32,
+                                                y as i32,
+                                                buffer_width as i32,
+                                                buffer_height as i32,
+                                                &ScreenSample {
+                                                    lens_sample: lens_sample,
+                                                    eye: eye,
+                                                    wavelength: wavelength,
+                                                    debug: false,
+                                                    aovs: Some(recorder),
+                                                })
+                    })
+                }).collect();
+
+                aov::average(&lens_samples)
+            };
+
+            if anaglyph {
+                aov::anaglyph(&trace_eye(Some(Eye::Left)), &trace_eye(Some(Eye::Right)))
+            } else {
+                trace_eye(None)
             }
         });
 
-        RawImage2d {
-            data: Cow::Owned(data),
+        AovImage {
             width: buffer_width,
             height: buffer_height,
-            format: ClientFormat::U8U8U8,
+            layers: layers.to_vec(),
+            samples: samples,
         }
     }
 
-    fn update(&self, delta_time: &Duration, context: &SimulationContext);
+    /// Traces the pixel in the center of the screen and records every ray it causes.
+    /// It is the only pixel that can be picked, as the cursor is held in the center of
+    /// the window to rotate the camera.
+    fn inspect(&self,
+               dimensions: (u32, u32),
+               time: &Duration,
+               context: &SimulationContext)
+               -> RayInspection {
+        let (width, height) = dimensions;
+        let buffer_width = width / context.resolution;
+        let buffer_height = height / context.resolution;
+        let (x, y) = (buffer_width as i32 / 2, buffer_height as i32 / 2);
+        let max_depth = self.max_depth();
+
+        inspection::inspect(x, y, || {
+            let trace_wavelength = |wavelength: Option<F>| {
+                self.trace_screen_point(time, &max_depth, x, y,
+                                        buffer_width as i32, buffer_height as i32,
+                                        &ScreenSample {
+                                            lens_sample: lens::LENS_CENTER,
+                                            eye: None,
+                                            wavelength: wavelength,
+                                            debug: true,
+                                            aovs: None,
+                                        })
+            };
+
+            if context.spectral_samples > 0 {
+                spectrum::integrate(context.spectral_samples,
+                                    |wavelength| trace_wavelength(Some(wavelength)));
+            } else {
+                trace_wavelength(None);
+            }
+        })
+    }
+
+    /// Finds the position of the point on the screen, `None` if the camera cannot project it.
+    fn project_point(&self, point: &[F], screen_width: i32, screen_height: i32) -> Option<(F, F)>;
+    /// How the two eyes of the camera are rendered, `None` for a single eye
+    fn stereo(&self) -> Option<Stereo>;
+    fn update(&mut self, delta_time: &Duration, context: &SimulationContext);
 }
 
 impl<P: CustomPoint<V>, V: CustomVector<P>, U: Universe<P=P, V=V>>
@@ -368,6 +726,10 @@ impl<P: CustomPoint<V>, V: CustomVector<P>, U: Universe<P=P, V=V>>
             .max_depth()
     }
 
+    fn tone_mapper(&self) -> ToneMapper {
+        *Universe::tone_mapper(self)
+    }
+
     fn trace_screen_point(&self,
                           time: &Duration,
                           max_depth: &u32,
@@ -375,32 +737,425 @@ impl<P: CustomPoint<V>, V: CustomVector<P>, U: Universe<P=P, V=V>>
                           screen_y: i32,
                           screen_width: i32,
                           screen_height: i32,
-                          debug: bool)
+                          sample: &ScreenSample)
                           -> Rgb<F> {
+        let (point, vector, cone) = camera_ray(self, screen_x, screen_y, screen_width, screen_height,
+                                               sample.lens_sample, sample.eye);
+
+        statistics::record_path(*max_depth);
+
+        self.trace_unknown(time, max_depth, &point, &vector, sample.wavelength, cone, sample.debug,
+                           sample.aovs)
+            .unwrap_or_else(|| missing_medium_color(screen_x, screen_y))
+    }
+
+    fn trace_screen_point_pass(&self,
+                               time: &Duration,
+                               max_depth: &u32,
+                               screen_x: i32,
+                               screen_y: i32,
+                               screen_width: i32,
+                               screen_height: i32,
+                               pass: RenderPass)
+                               -> Rgb<F> {
+        let (point, vector, cone) = camera_ray(self, screen_x, screen_y, screen_width, screen_height,
+                                               lens::LENS_CENTER, None);
+
+        render_pass::trace_render_pass(self, pass, time, max_depth, &point, &vector, cone)
+            .unwrap_or_else(|| missing_medium_color(screen_x, screen_y))
+    }
+
+    fn project_point(&self, point: &[F], screen_width: i32, screen_height: i32) -> Option<(F, F)> {
+        if point.len() != <P as Dimension>::dimension(None) {
+            return None;
+        }
+
+        let point: P = point.iter().cloned().collect();
         let camera = self.camera().try_read()
-            .expect("Could not get the origin location and direction, the camera is mutably borrowed.");
-        let point = camera.get_ray_point(screen_x, screen_y, screen_width, screen_height);
-        let vector = camera.get_ray_vector(screen_x, screen_y, screen_width, screen_height);
+            .expect("Could not project the point, the camera is mutably borrowed.");
 
-        match self.trace_unknown(time, max_depth, &point, &vector, debug) {
-            Some(color) => color,
-            None => {
-                let checkerboard_size = 8;
+        camera.project_point(&point, screen_width, screen_height)
+    }
 
-                if (screen_x / checkerboard_size + screen_y / checkerboard_size) % 2 == 0 {
-                    Rgb::<F>::new(Cast::from(0.0), Cast::from(0.0), Cast::from(0.0))
-                } else {
-                    Rgb::<F>::new(Cast::from(1.0), Cast::from(0.0), Cast::from(1.0))
+    fn stereo(&self) -> Option<Stereo> {
+        self.camera().try_read()
+            .expect("Could not get the stereo mode, the camera is mutably borrowed.")
+            .stereo()
+    }
+
+    fn update(&mut self, delta_time: &Duration, context: &SimulationContext) {
+        let cross_section = {
+            let mut camera = self.camera()
+                .try_write()
+                .expect("Could not update the camera. It is already borrowed.");
+
+            camera.update(delta_time, context, self);
+            camera.cross_section()
+        };
+
+        self.set_cross_section(cross_section);
+    }
+}
+
+/// Stops the paths traced by `Universe::trace_path` in front of solid entities,
+/// see `Traceable::is_solid`, and slides them along the surfaces with the remaining distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collision {
+    /// The distance kept from the surfaces
+    pub radius: F,
+    /// How many more times the path may slide along a surface, stops it in corners
+    pub slides: u32,
+}
+
+impl Collision {
+    pub fn new(radius: F) -> Self {
+        Collision {
+            radius: radius,
+            slides: 3,
+        }
+    }
+
+    fn slide(&self) -> Self {
+        Collision {
+            radius: self.radius,
+            slides: self.slides - 1,
+        }
+    }
+}
+
+/// The maximum width and height of the tiles the image is split into
+pub const TILE_SIZE: u32 = 16;
+/// The shortest time between two previews of a frame that is still being rendered
+pub const PREVIEW_INTERVAL_MILLIS: u64 = 100;
+
+/// A rectangle of pixels rendered by a single worker
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub left: u32,
+    pub bottom: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Splits the image into tiles ordered by their distance from the center of the image,
+/// so that the middle of the image is shown first by the previews of slow frames.
+pub fn tiles_from_center(width: u32, height: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+
+    for bottom in (0..height).step_by(TILE_SIZE as usize) {
+        for left in (0..width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                left: left,
+                bottom: bottom,
+                width: TILE_SIZE.min(width - left),
+                height: TILE_SIZE.min(height - bottom),
+            });
+        }
+    }
+
+    // Compare doubled coordinates to stay in integers
+    let distance_squared = |tile: &Tile| {
+        let x = i64::from(tile.left * 2 + tile.width) - i64::from(width);
+        let y = i64::from(tile.bottom * 2 + tile.height) - i64::from(height);
+
+        x * x + y * y
+    };
+
+    tiles.sort_by_key(distance_squared);
+    tiles
+}
+
+/// Computes the value of each pixel on the thread pool, the rows start at the bottom.
+/// Each thread of the pool repeatedly takes the next tile until all of them are rendered,
+/// and writes every finished tile to the buffer right away. Meanwhile, the calling thread
+/// passes the partially rendered buffer to `preview`, at most once per
+/// `PREVIEW_INTERVAL_MILLIS`.
+fn render_buffer<T, S>(width: u32,
+                       height: u32,
+                       pool: &mut Pool,
+                       statistics: Option<&Mutex<FrameStatistics>>,
+                       preview: Option<&mut FnMut(&[T])>,
+                       shade: S)
+                       -> Vec<T>
+    where T: Clone + Default + Send,
+          S: Fn(u32, u32) -> T + Sync
+{
+    let tiles = tiles_from_center(width, height);
+    let next_tile = AtomicUsize::new(0);
+    let data: Mutex<Vec<T>> = Mutex::new((0..width * height).map(|_| T::default()).collect());
+    let workers = pool.thread_count();
+    let (finished_sender, finished_receiver) = mpsc::channel();
+
+    {
+        let (tiles, next_tile, data, shade) = (&tiles, &next_tile, &data, &shade);
+
+        pool.scoped(move |scope| {
+            for _ in 0..workers {
+                let finished_sender = finished_sender.clone();
+
+                scope.execute(move || {
+                    let _recording = statistics.map(statistics::record);
+
+                    while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                        let pixels: Vec<T> = (0..tile.width * tile.height)
+                            .map(|index| shade(tile.left + index % tile.width,
+                                               tile.bottom + index / tile.width))
+                            .collect();
+
+                        {
+                            let mut data = data.lock()
+                                .expect("Could not write the rendered tile, the lock is poisoned.");
+
+                            for (index, pixel) in pixels.into_iter().enumerate() {
+                                let x = tile.left + index as u32 % tile.width;
+                                let y = tile.bottom + index as u32 / tile.width;
+
+                                data[(y * width + x) as usize] = pixel;
+                            }
+                        }
+
+                        // Only fails if the calling thread stopped waiting for the tiles
+                        let _ = finished_sender.send(());
+                    }
+                });
+            }
+
+            // The receiver stops waiting once all the workers are done, even if one panics
+            drop(finished_sender);
+
+            if let Some(preview) = preview {
+                let interval = Duration::from_millis(PREVIEW_INTERVAL_MILLIS);
+                let mut last_preview = Instant::now();
+
+                while finished_receiver.recv().is_ok() {
+                    if last_preview.elapsed() >= interval {
+                        // Copied, so that the workers do not wait for the preview to be shown
+                        let snapshot = data.lock()
+                            .expect("Could not preview the rendered tiles, the lock is poisoned.")
+                            .clone();
+
+                        preview(&snapshot);
+                        last_preview = Instant::now();
+                    }
+                }
+            }
+        });
+    }
+
+    data.into_inner()
+        .expect("Could not write the rendered tile, the lock is poisoned.")
+}
+
+/// Converts the pixels to an image uploadable to a texture
+fn raw_image<'a>(pixels: &[[u8; 3]], width: u32, height: u32) -> RawImage2d<'a, u8> {
+    let data: Vec<u8> = pixels.iter()
+        .flat_map(|pixel| pixel.iter().cloned())
+        .collect();
+
+    RawImage2d {
+        data: Cow::Owned(data),
+        width: width,
+        height: height,
+        format: ClientFormat::U8U8U8,
+    }
+}
+
+/// The location, direction and footprint of the ray traced from the camera through the pixel
+fn camera_ray<U: Universe>(universe: &U,
+                           screen_x: i32,
+                           screen_y: i32,
+                           screen_width: i32,
+                           screen_height: i32,
+                           lens_sample: LensSample,
+                           eye: Option<Eye>)
+                           -> (U::P, U::V, RayCone) {
+    let camera = universe.camera().try_read()
+        .expect("Could not get the origin location and direction, the camera is mutably borrowed.");
+    let (point, vector) = camera.get_ray(screen_x, screen_y, screen_width, screen_height,
+                                         lens_sample, eye);
+    // The angle between the rays of neighbouring pixels through the center of the lens
+    let center = lens::LENS_CENTER;
+    let (_, center_vector) = camera.get_ray(screen_x, screen_y, screen_width, screen_height,
+                                            center, eye);
+    // The neighbouring pixel seen by the same eye, side by side eyes end in the middle
+    let viewport_eye = |x| camera.stereo()
+        .and_then(|stereo| stereo.viewport(eye, x, screen_y, screen_width, screen_height).0);
+    let next_x = if viewport_eye(screen_x + 1) == viewport_eye(screen_x) {
+        screen_x + 1
+    } else {
+        screen_x - 1
+    };
+    let (_, next_vector) = camera.get_ray(next_x, screen_y, screen_width, screen_height,
+                                          center, eye);
+    let spread = center_vector.angle_between(&next_vector);
+
+    (point, vector, RayCone::new(<F as Zero>::zero(), spread))
+}
+
+/// Removes the component of the normal along the normal of the cross section, so that
+/// the reflected and refracted rays stay within the slice and it is shaded as a lower
+/// dimensional scene. Normals perpendicular to the cross section are left as they are.
+fn project_onto_cross_section<V>(normal: &V, section_normal: &V) -> V
+    where V: Copy + Dot<F> + Norm<F> + Mul<F, Output=V> + Sub<V, Output=V>
+{
+    let projected = *normal - *section_normal * na::dot(normal, section_normal);
+
+    if projected.norm_squared() == <F as Zero>::zero() {
+        *normal
+    } else {
+        projected.normalize()
+    }
+}
+
+/// A checkerboard shown where the camera is not located in any medium
+fn missing_medium_color(screen_x: i32, screen_y: i32) -> Rgb<F> {
+    let checkerboard_size = 8;
+
+    if (screen_x / checkerboard_size + screen_y / checkerboard_size) % 2 == 0 {
+        Rgb::<F>::new(Cast::from(0.0), Cast::from(0.0), Cast::from(0.0))
+    } else {
+        Rgb::<F>::new(Cast::from(1.0), Cast::from(0.0), Cast::from(1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normals_are_projected_onto_cross_section() {
+        use universe::d4::Vector4;
+
+        let section_normal = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let normal = Vector4::new(1.0, 0.0, 0.0, 1.0).normalize();
+        let projected = project_onto_cross_section(&normal, &section_normal);
+
+        assert!(projected.approx_eq(&Vector4::new(1.0, 0.0, 0.0, 0.0)));
+        assert_eq!(project_onto_cross_section(&section_normal, &section_normal),
+                   section_normal);
+    }
+
+    /// A universe with a solid floor below `z = 0`
+    fn universe_with_floor(solid: bool) -> ::universe::d3::Universe3 {
+        use palette::Rgba;
+        use universe::d3::Point3;
+        use universe::d3::Universe3;
+        use universe::d3::Vector3;
+        use universe::d3::entity::*;
+        use universe::entity::Void;
+        use universe::entity::material::Vacuum;
+        use universe::entity::shape::HalfSpace;
+        use universe::entity::shape::Hyperplane;
+        use universe::entity::surface::*;
+
+        let mut universe = Universe3::construct(Box::new(FreeCamera3::new()));
+        let floor = HalfSpace::new_with_point(Hyperplane::new_with_point(Vector3::z(),
+                                                                         &Point3::new(0.0, 0.0, 0.0)),
+                                              &Point3::new(0.0, 0.0, -1.0));
+        let surface = ComposableSurface {
+            reflection_ratio: reflection_ratio_uniform(0.0).into(),
+            reflection_direction: reflection_direction_specular().into(),
+            threshold_direction: threshold_direction_identity().into(),
+            surface_color: surface_color_uniform(Rgba::new(0.0, 0.0, 0.0, 0.0)).into(),
+            normal: normal_geometric().into(),
+        };
+
+        universe.entities.push(Box::new(Void::<Point3, Vector3>::new_with_vacuum()));
+        universe.entities.push(Box::new(Entity3Impl::new_with_surface(Box::new(floor),
+                                                                      Box::new(Vacuum::new()),
+                                                                      Box::new(surface))
+                                        .with_solid(solid)));
+        universe
+    }
+
+    fn trace_towards_floor(direction: ::universe::d3::Vector3,
+                           distance: F,
+                           collision: Option<Collision>)
+                           -> ::universe::d3::Point3 {
+        use universe::d3::Point3;
+
+        let universe = universe_with_floor(true);
+        let (location, _) = universe.trace_path_unknown(&Duration::from_millis(0),
+                                                        &distance,
+                                                        &Point3::new(0.0, 0.0, 1.0),
+                                                        &direction.normalize(),
+                                                        collision,
+                                                        false)
+            .expect("The location should be inside the void.");
+
+        location
+    }
+
+    #[test]
+    fn collisions_stop_in_front_of_solid_entities() {
+        use universe::d3::Point3;
+        use universe::d3::Vector3;
+
+        let stopped = trace_towards_floor(-Vector3::z(), 5.0, Some(Collision::new(0.5)));
+        let passed = trace_towards_floor(-Vector3::z(), 5.0, None);
+
+        assert!(stopped.approx_eq(&Point3::new(0.0, 0.0, 0.5)), "{:?}", stopped);
+        assert!((passed.z + 4.0).abs() < 1e-3, "{:?}", passed);
+    }
+
+    #[test]
+    fn collisions_slide_along_solid_entities() {
+        use universe::d3::Point3;
+        use universe::d3::Vector3;
+
+        let direction = Vector3::new(1.0, 0.0, -1.0);
+        // Reaches the collision radius after half the diagonal, slides along X with the rest
+        let distance = (0.5 as F).sqrt() + (2.0 as F).sqrt();
+        let slid = trace_towards_floor(direction, distance, Some(Collision::new(0.5)));
+        let stuck = trace_towards_floor(direction, distance,
+                                        Some(Collision { radius: 0.5, slides: 0 }));
+
+        assert!(slid.approx_eq(&Point3::new(1.5, 0.0, 0.5)), "{:?}", slid);
+        assert!(stuck.approx_eq(&Point3::new(0.5, 0.0, 0.5)), "{:?}", stuck);
+    }
+
+    #[test]
+    fn ground_is_found_below_solid_entities_only() {
+        use universe::d3::Point3;
+        use universe::d3::Vector3;
+
+        let location = Point3::new(0.0, 0.0, 3.0);
+        let time = Duration::from_millis(0);
+        let ground = universe_with_floor(true)
+            .trace_distance_unknown(&time, &location, &-Vector3::z(), &|other| other.is_solid());
+        let missing = universe_with_floor(false)
+            .trace_distance_unknown(&time, &location, &-Vector3::z(), &|other| other.is_solid());
+
+        assert!(ground.expect("The floor should be found.").approx_eq(&3.0));
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn tiles_cover_image_from_center() {
+        let (width, height) = (100, 37);
+        let tiles = tiles_from_center(width, height);
+        let mut covered = vec![0; (width * height) as usize];
+
+        for tile in &tiles {
+            for y in tile.bottom..tile.bottom + tile.height {
+                for x in tile.left..tile.left + tile.width {
+                    covered[(y * width + x) as usize] += 1;
                 }
             }
         }
+
+        assert!(covered.iter().all(|count| *count == 1));
+        assert!(tiles[0].left <= width / 2 && width / 2 < tiles[0].left + tiles[0].width);
+        assert!(tiles[0].bottom <= height / 2 && height / 2 < tiles[0].bottom + tiles[0].height);
     }
 
-    fn update(&self, delta_time: &Duration, context: &SimulationContext) {
-        let mut camera = self.camera()
-            .try_write()
-            .expect("Could not update the camera. It is already borrowed.");
+    #[test]
+    fn buffer_is_assembled_in_order() {
+        let mut pool = Pool::new(3);
+        let data = render_buffer(40, 20, &mut pool, None, None, |x, y| (x, y));
 
-        camera.update(delta_time, context, self);
+        for (index, pixel) in data.iter().enumerate() {
+            assert_eq!(*pixel, (index as u32 % 40, index as u32 / 40));
+        }
     }
 }
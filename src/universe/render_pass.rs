@@ -0,0 +1,271 @@
+use ::F;
+use std::any::TypeId;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+use na::ApproxEq;
+use na::Cast;
+use num::NumCast;
+use num::Zero;
+use num::One;
+use palette::Hsv;
+use palette::Rgb;
+use palette::RgbHue;
+use universe::Universe;
+use universe::statistics;
+use universe::statistics::FrameStatistics;
+use universe::entity::Traceable;
+use universe::entity::shape::RayCone;
+use universe::entity::shape::TracingContext;
+use util::CustomPoint;
+use util::CustomVector;
+use util::HasId;
+use util::cast;
+
+/// The quantity displayed for each pixel. All passes but `Shaded` show properties of
+/// the closest surface hit by the primary ray, used to check shapes and intersectors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderPass {
+    /// The traced colors
+    Shaded,
+    /// The first three coordinates of the outward normal mapped from [-1; 1] to [0; 1]
+    Normals,
+    /// The distance to the closest surface, brighter when closer
+    Depth,
+    /// A color per type of the material of the hit entity
+    MaterialId,
+    /// A color per type of the shape of the hit entity
+    ShapeId,
+    /// A color per hit entity
+    EntityId,
+    /// The number of bounces the traced ray took, from blue (none) to red (the maximum depth)
+    Bounces,
+    /// Red where the intersection disagrees with `Shape::is_point_inside` of the hit shape
+    InsideMismatch,
+}
+
+pub const RENDER_PASSES: [RenderPass; 8] = [RenderPass::Shaded,
+                                            RenderPass::Normals,
+                                            RenderPass::Depth,
+                                            RenderPass::MaterialId,
+                                            RenderPass::ShapeId,
+                                            RenderPass::EntityId,
+                                            RenderPass::Bounces,
+                                            RenderPass::InsideMismatch];
+
+impl RenderPass {
+    /// The pass following this one, wrapping around
+    pub fn next(&self) -> RenderPass {
+        let index = RENDER_PASSES.iter().position(|pass| pass == self).unwrap();
+
+        RENDER_PASSES[(index + 1) % RENDER_PASSES.len()]
+    }
+}
+
+impl Default for RenderPass {
+    fn default() -> Self {
+        RenderPass::Shaded
+    }
+}
+
+impl FromStr for RenderPass {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        RENDER_PASSES.iter()
+            .find(|pass| pass.to_string() == name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| {
+                format!("Invalid render pass: \"{}\", expected one of {}", name,
+                        RENDER_PASSES.iter()
+                            .map(|pass| format!("`{}`", pass))
+                            .collect::<Vec<String>>()
+                            .join(", "))
+            })
+    }
+}
+
+impl fmt::Display for RenderPass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            RenderPass::Shaded => "shaded",
+            RenderPass::Normals => "normals",
+            RenderPass::Depth => "depth",
+            RenderPass::MaterialId => "material_id",
+            RenderPass::ShapeId => "shape_id",
+            RenderPass::EntityId => "entity_id",
+            RenderPass::Bounces => "bounces",
+            RenderPass::InsideMismatch => "inside_mismatch",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The distance at which the depth pass is half as bright as at the camera
+pub const DEPTH_HALF_DISTANCE: f64 = 10.0;
+
+fn black() -> Rgb<F> {
+    Rgb::new(<F as Zero>::zero(), <F as Zero>::zero(), <F as Zero>::zero())
+}
+
+/// A distinct, saturated color for each hashable value
+pub fn id_color<T: Hash>(id: &T) -> Rgb<F> {
+    let mut hasher = DefaultHasher::new();
+
+    id.hash(&mut hasher);
+
+    let hash = hasher.finish();
+    let hue = <F as NumCast>::from(hash % 360).unwrap();
+    let value = cast(0.6) + cast(0.4) * <F as NumCast>::from((hash / 360) % 5).unwrap() / cast(4.0);
+
+    Rgb::from(Hsv::new(RgbHue::from(hue), <F as One>::one(), value))
+}
+
+/// The identifier of the entity, unique for as long as the universe is not modified
+pub fn entity_id<P, V>(traceable: &Traceable<P, V>) -> usize
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    traceable as *const Traceable<P, V> as *const u8 as usize
+}
+
+/// Maps the depth to the range (0; 1]
+pub fn depth_brightness(distance: F) -> F {
+    let half = cast(DEPTH_HALF_DISTANCE);
+
+    half / (half + distance.max(<F as Zero>::zero()))
+}
+
+/// Maps the normal to a color, the coordinates beyond the third one are ignored
+pub fn normal_color<P: CustomPoint<V>, V: CustomVector<P>>(normal: &V) -> Rgb<F> {
+    let half = cast(0.5);
+    let normal = normal.normalize();
+
+    Rgb::new(normal[0] * half + half, normal[1] * half + half, normal[2] * half + half)
+}
+
+/// Interpolates from blue through green to red
+pub fn heat_color(ratio: F) -> Rgb<F> {
+    let ratio = ratio.max(<F as Zero>::zero()).min(<F as One>::one());
+
+    Rgb::from(Hsv::new(RgbHue::from(cast(240.0) * (<F as One>::one() - ratio)),
+                       <F as One>::one(),
+                       <F as One>::one()))
+}
+
+/// Whether `is_point_inside` agrees with the side the ray hit the shape from,
+/// checked just before and just after the intersection.
+pub fn is_inside_consistent<P, V>(context: &TracingContext<P, V>) -> bool
+    where P: CustomPoint<V>,
+          V: CustomVector<P> {
+    let shape = context.intersection_traceable.shape();
+    let offset = context.intersection.direction.normalize()
+        * <F as ApproxEq<F>>::approx_epsilon(None) * Cast::from(128.0);
+    let before = shape.is_point_inside(&(context.intersection.location - offset));
+    let after = shape.is_point_inside(&(context.intersection.location + offset));
+
+    before == context.exiting && after == !context.exiting
+}
+
+/// Computes the color of the pass for the ray, `None` if the ray does not start in any medium.
+pub fn trace_render_pass<U: Universe>(universe: &U,
+                                      pass: RenderPass,
+                                      time: &Duration,
+                                      max_depth: &u32,
+                                      location: &U::P,
+                                      direction: &U::V,
+                                      cone: RayCone)
+                                      -> Option<Rgb<F>> {
+    if pass == RenderPass::Bounces {
+        let target = Mutex::new(FrameStatistics::default());
+
+        let traced = {
+            let _recording = statistics::record(&target);
+
+            statistics::record_path(*max_depth);
+            universe.trace_unknown(time, max_depth, location, direction, None, cone, false, None)
+        };
+
+        if traced.is_none() {
+            return None;
+        }
+
+        let bounces = target.into_inner()
+            .expect("Could not read the statistics, the lock is poisoned.")
+            .bounces;
+
+        return Some(heat_color(<F as NumCast>::from(bounces).unwrap()
+                               / <F as NumCast>::from((*max_depth).max(1)).unwrap()));
+    }
+
+    let media = universe.media_at(location);
+
+    media.current().map(|belongs_to| {
+        let mut transitioned_direction = *direction;
+
+        belongs_to.material().enter(location, &mut transitioned_direction);
+
+        let result = universe.trace_closest(time, belongs_to, location, &transitioned_direction,
+                                            None, cone, false, &|other| other.surface().is_some());
+        let (closest, context) = match result {
+            Some(result) => result,
+            None => return black(),
+        };
+
+        match pass {
+            RenderPass::Normals => normal_color::<U::P, U::V>(&context.intersection.normal),
+            RenderPass::Depth => {
+                let brightness = depth_brightness(context.intersection.distance);
+
+                Rgb::new(brightness, brightness, brightness)
+            }
+            RenderPass::MaterialId => id_color::<TypeId>(&closest.material().id()),
+            RenderPass::ShapeId => id_color::<TypeId>(&closest.shape().id()),
+            RenderPass::EntityId => id_color(&entity_id(closest)),
+            RenderPass::InsideMismatch => {
+                if is_inside_consistent(&context) {
+                    // Keep the geometry recognizable
+                    let normal = normal_color::<U::P, U::V>(&context.intersection.normal);
+                    let dim = cast(0.25);
+
+                    Rgb::new(normal.red * dim, normal.green * dim, normal.blue * dim)
+                } else {
+                    Rgb::new(<F as One>::one(), <F as Zero>::zero(), <F as Zero>::zero())
+                }
+            }
+            RenderPass::Shaded | RenderPass::Bounces => unreachable!(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_cycle_and_parse() {
+        let mut pass = RenderPass::Shaded;
+
+        for _ in 0..RENDER_PASSES.len() {
+            assert_eq!(pass.to_string().parse::<RenderPass>(), Ok(pass));
+            pass = pass.next();
+        }
+
+        assert_eq!(pass, RenderPass::Shaded);
+        assert!("unknown".parse::<RenderPass>().is_err());
+    }
+
+    #[test]
+    fn id_colors_are_stable() {
+        assert_eq!(id_color(&42usize), id_color(&42usize));
+    }
+
+    #[test]
+    fn depth_halves_at_half_distance() {
+        assert!(depth_brightness(cast(DEPTH_HALF_DISTANCE)).approx_eq(&0.5));
+    }
+}
@@ -0,0 +1,164 @@
+use ::F;
+use num::Zero;
+use num::traits::NumCast;
+use palette::Rgb;
+use util::cast;
+
+/// The shortest traced wavelength in nanometres
+pub const WAVELENGTH_MIN: f64 = 380.0;
+/// The longest traced wavelength in nanometres
+pub const WAVELENGTH_MAX: f64 = 720.0;
+/// The wavelength refractive indices are evaluated at when not rendering spectrally,
+/// the Fraunhofer d line (helium), in nanometres
+pub const WAVELENGTH_REFERENCE: f64 = 587.56;
+
+/// Computes the refractive index for a given wavelength in nanometres.
+pub type RefractiveIndexProvider = (Fn(F) -> F) + Send + Sync;
+
+#[allow(unused_variables)]
+pub fn refractive_index_constant(index: F) -> Box<RefractiveIndexProvider> {
+    Box::new(move |wavelength: F| index)
+}
+
+/// Cauchy's equation `n = A + B / λ^2 + C / λ^4 + ...` with `λ` in micrometres,
+/// the coefficients are ordered from `A`.
+pub fn refractive_index_cauchy(coefficients: Vec<F>) -> Box<RefractiveIndexProvider> {
+    assert!(!coefficients.is_empty(), "At least one Cauchy coefficient is required.");
+
+    Box::new(move |wavelength: F| {
+        let micrometres = wavelength / <F as NumCast>::from(1000.0).unwrap();
+        let inverse_square = <F as NumCast>::from(1.0).unwrap() / (micrometres * micrometres);
+        let mut power = <F as NumCast>::from(1.0).unwrap();
+        let mut result = <F as Zero>::zero();
+
+        for coefficient in &coefficients {
+            result += *coefficient * power;
+            power *= inverse_square;
+        }
+
+        result
+    })
+}
+
+/// The Sellmeier equation `n^2 = 1 + Σ B_i * λ^2 / (λ^2 - C_i)` with `λ` in micrometres
+/// and `C_i` in square micrometres.
+pub fn refractive_index_sellmeier(b: Vec<F>, c: Vec<F>) -> Box<RefractiveIndexProvider> {
+    assert_eq!(b.len(), c.len(), "The number of B and C Sellmeier coefficients must be equal.");
+
+    Box::new(move |wavelength: F| {
+        let micrometres = wavelength / <F as NumCast>::from(1000.0).unwrap();
+        let squared = micrometres * micrometres;
+        let mut result = <F as NumCast>::from(1.0).unwrap();
+
+        for (b, c) in b.iter().zip(c.iter()) {
+            result += *b * squared / (squared - *c);
+        }
+
+        result.sqrt()
+    })
+}
+
+/// A piecewise Gaussian used to approximate the CIE colour matching functions
+fn gaussian(x: F, mean: F, sigma_lower: F, sigma_upper: F) -> F {
+    let sigma = if x < mean { sigma_lower } else { sigma_upper };
+    let t = (x - mean) / sigma;
+
+    (-t * t / <F as NumCast>::from(2.0).unwrap()).exp()
+}
+
+/// Computes the linear RGB response to light of the given wavelength in nanometres.
+/// Uses the multi-lobe fit of the CIE 1931 colour matching functions by Wyman, Sloan
+/// and Shirley and clamps the colours outside of the sRGB gamut.
+pub fn wavelength_to_rgb(wavelength: F) -> Rgb<F> {
+    let x = cast(1.056) * gaussian(wavelength, cast(599.8), cast(37.9), cast(31.0))
+        + cast(0.362) * gaussian(wavelength, cast(442.0), cast(16.0), cast(26.7))
+        - cast(0.065) * gaussian(wavelength, cast(501.1), cast(20.4), cast(26.2));
+    let y = cast(0.821) * gaussian(wavelength, cast(568.8), cast(46.9), cast(40.5))
+        + cast(0.286) * gaussian(wavelength, cast(530.9), cast(16.3), cast(31.1));
+    let z = cast(1.217) * gaussian(wavelength, cast(437.0), cast(11.8), cast(36.0))
+        + cast(0.681) * gaussian(wavelength, cast(459.0), cast(26.0), cast(13.8));
+
+    let red = cast(3.2406) * x - cast(1.5372) * y - cast(0.4986) * z;
+    let green = cast(-0.9689) * x + cast(1.8758) * y + cast(0.0415) * z;
+    let blue = cast(0.0557) * x - cast(0.2040) * y + cast(1.0570) * z;
+
+    Rgb::new(red.max(<F as Zero>::zero()),
+             green.max(<F as Zero>::zero()),
+             blue.max(<F as Zero>::zero()))
+}
+
+/// Returns the wavelength of the given sample, the samples are distributed uniformly
+/// across the visible spectrum.
+pub fn sample_wavelength(index: u32, samples: u32) -> F {
+    let offset = (<F as NumCast>::from(index).unwrap() + cast(0.5))
+        / <F as NumCast>::from(samples).unwrap();
+
+    cast(WAVELENGTH_MIN) + offset * cast(WAVELENGTH_MAX - WAVELENGTH_MIN)
+}
+
+/// Traces the given number of wavelengths and combines the results into a single color.
+/// Each traced color is weighted by the response to its wavelength, so that a scene without
+/// any wavelength-dependent effects looks the same as when traced without spectral sampling.
+pub fn integrate<T: Fn(F) -> Rgb<F>>(samples: u32, trace: T) -> Rgb<F> {
+    let mut sum: [F; 3] = [<F as Zero>::zero(); 3];
+    let mut weight_sum: [F; 3] = [<F as Zero>::zero(); 3];
+
+    for index in 0..samples {
+        let wavelength = sample_wavelength(index, samples);
+        let weight = wavelength_to_rgb(wavelength);
+        let weight = [weight.red, weight.green, weight.blue];
+        let color = trace(wavelength);
+        let color = [color.red, color.green, color.blue];
+
+        for channel in 0..3 {
+            sum[channel] += color[channel] * weight[channel];
+            weight_sum[channel] += weight[channel];
+        }
+    }
+
+    for channel in 0..3 {
+        if weight_sum[channel] > <F as Zero>::zero() {
+            sum[channel] /= weight_sum[channel];
+        }
+    }
+
+    Rgb::new(sum[0], sum[1], sum[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use na::ApproxEq;
+    use palette::Rgb;
+    use super::*;
+
+    #[test]
+    fn refractive_index_bk7() {
+        let cauchy = refractive_index_cauchy(vec![1.5046, 0.00420]);
+        let sellmeier = refractive_index_sellmeier(vec![1.03961212, 0.231792344, 1.01046945],
+                                                   vec![0.00600069867, 0.0200179144, 103.560653]);
+        let reference = WAVELENGTH_REFERENCE as F;
+
+        assert!(cauchy(reference).approx_eq_eps(&1.5168, &1e-3));
+        assert!(sellmeier(reference).approx_eq_eps(&1.5168, &1e-4));
+        // Normal dispersion, shorter wavelengths are refracted more
+        assert!(sellmeier(400.0) > sellmeier(700.0));
+    }
+
+    #[test]
+    fn integrate_uniform_color() {
+        let color = integrate(8, |_| Rgb::new(0.25, 0.5, 0.75));
+
+        assert!(color.red.approx_eq_eps(&0.25, &1e-6));
+        assert!(color.green.approx_eq_eps(&0.5, &1e-6));
+        assert!(color.blue.approx_eq_eps(&0.75, &1e-6));
+    }
+
+    #[test]
+    fn wavelength_to_rgb_hues() {
+        let red = wavelength_to_rgb(650.0);
+        let blue = wavelength_to_rgb(450.0);
+
+        assert!(red.red > red.blue);
+        assert!(blue.blue > blue.red);
+    }
+}
@@ -0,0 +1,226 @@
+use ::F;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Mutex;
+use num::NumCast;
+use num::Zero;
+
+/// The number of times the intersector of a material and a shape was invoked
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IntersectorCalls {
+    pub material: String,
+    pub shape: String,
+    pub calls: u64,
+}
+
+/// The counters collected while rendering a single frame
+#[derive(Clone, Debug, Default)]
+pub struct FrameStatistics {
+    /// The number of traced ray segments, including the primary rays
+    pub rays: u64,
+    /// The number of primary rays
+    pub paths: u64,
+    /// The sum of the deepest bounce of each path
+    pub bounces: u64,
+    pub intersector_calls: HashMap<(TypeId, TypeId), IntersectorCalls>,
+}
+
+impl FrameStatistics {
+    /// The average number of bounces a primary ray took
+    pub fn average_bounce_depth(&self) -> F {
+        if self.paths == 0 {
+            return <F as Zero>::zero();
+        }
+
+        <F as NumCast>::from(self.bounces).unwrap() / <F as NumCast>::from(self.paths).unwrap()
+    }
+
+    pub fn merge(&mut self, other: FrameStatistics) {
+        self.rays += other.rays;
+        self.paths += other.paths;
+        self.bounces += other.bounces;
+
+        for (key, calls) in other.intersector_calls {
+            self.intersector_calls.entry(key)
+                .or_insert_with(|| IntersectorCalls {
+                    material: calls.material.clone(),
+                    shape: calls.shape.clone(),
+                    calls: 0,
+                })
+                .calls += calls.calls;
+        }
+    }
+
+    /// The intersector calls ordered from the most frequent
+    pub fn intersector_calls_sorted(&self) -> Vec<&IntersectorCalls> {
+        let mut result: Vec<&IntersectorCalls> = self.intersector_calls.values().collect();
+
+        result.sort_by(|a, b| {
+            b.calls.cmp(&a.calls)
+                .then_with(|| a.material.cmp(&b.material))
+                .then_with(|| a.shape.cmp(&b.shape))
+        });
+
+        result
+    }
+}
+
+struct Recording {
+    statistics: FrameStatistics,
+    /// The depth the current path started with and the lowest remaining depth reached
+    path: Option<(u32, u32)>,
+}
+
+impl Recording {
+    fn finish_path(&mut self) {
+        if let Some((max_depth, remaining)) = self.path.take() {
+            self.statistics.bounces += u64::from(max_depth.saturating_sub(remaining));
+        }
+    }
+}
+
+thread_local! {
+    static RECORDING: RefCell<Option<Recording>> = RefCell::new(None);
+}
+
+/// Collects the statistics of the current thread while it exists and adds them
+/// to the target when dropped. Recordings may be nested, the statistics of the inner
+/// recording are also added to the outer one.
+pub struct RecordingGuard<'a> {
+    target: &'a Mutex<FrameStatistics>,
+    previous: Option<Recording>,
+}
+
+impl<'a> Drop for RecordingGuard<'a> {
+    fn drop(&mut self) {
+        let mut previous = self.previous.take();
+        let recording = RECORDING.with(|recording| recording.borrow_mut().take());
+
+        if let Some(mut recording) = recording {
+            recording.finish_path();
+
+            if let Some(ref mut previous) = previous {
+                previous.statistics.merge(recording.statistics.clone());
+            }
+
+            self.target.lock()
+                .expect("Could not collect the statistics, the lock is poisoned.")
+                .merge(recording.statistics);
+        }
+
+        RECORDING.with(|recording| *recording.borrow_mut() = previous);
+    }
+}
+
+/// Starts recording the statistics of the current thread. Nothing is recorded
+/// on threads without a recording, so that the counters cost nearly nothing when unused.
+pub fn record(target: &Mutex<FrameStatistics>) -> RecordingGuard {
+    let previous = RECORDING.with(|recording| {
+        recording.borrow_mut().take()
+    });
+
+    RECORDING.with(|recording| {
+        *recording.borrow_mut() = Some(Recording {
+            statistics: FrameStatistics::default(),
+            path: None,
+        });
+    });
+
+    RecordingGuard {
+        target: target,
+        previous: previous,
+    }
+}
+
+fn with_recording<T: FnOnce(&mut Recording)>(action: T) {
+    RECORDING.with(|recording| {
+        if let Some(ref mut recording) = *recording.borrow_mut() {
+            action(recording);
+        }
+    });
+}
+
+/// Starts a new path of a primary ray, traced up to the given depth.
+pub fn record_path(max_depth: u32) {
+    with_recording(|recording| {
+        recording.finish_path();
+        recording.statistics.paths += 1;
+        recording.path = Some((max_depth, max_depth));
+    });
+}
+
+/// Records a ray segment traced with the given remaining depth.
+pub fn record_ray(remaining_depth: u32) {
+    with_recording(|recording| {
+        recording.statistics.rays += 1;
+
+        if let Some((_, ref mut remaining)) = recording.path {
+            *remaining = (*remaining).min(remaining_depth);
+        }
+    });
+}
+
+/// The name of the type of an entity, without its parameters
+fn label<T: Display + ?Sized>(value: &T) -> String {
+    value.to_string()
+        .chars()
+        .take_while(|character| character.is_alphanumeric() || *character == '_')
+        .collect()
+}
+
+pub fn record_intersector_call<M, S>(material_id: TypeId, shape_id: TypeId, material: &M, shape: &S)
+    where M: Display + ?Sized,
+          S: Display + ?Sized {
+    with_recording(|recording| {
+        recording.statistics.intersector_calls.entry((material_id, shape_id))
+            .or_insert_with(|| IntersectorCalls {
+                material: label(material),
+                shape: label(shape),
+                calls: 0,
+            })
+            .calls += 1;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::TypeId;
+    use std::sync::Mutex;
+    use na::ApproxEq;
+    use super::*;
+
+    #[test]
+    fn records_paths_and_calls() {
+        let target = Mutex::new(FrameStatistics::default());
+
+        // Not recorded without a guard
+        record_ray(1);
+
+        {
+            let _guard = record(&target);
+
+            record_path(4);
+            record_ray(4);
+            record_ray(3);
+            record_ray(2);
+            record_path(4);
+            record_ray(4);
+            record_intersector_call(TypeId::of::<u8>(), TypeId::of::<u16>(),
+                                    "Vacuum", "Sphere { radius: 1 }");
+            record_intersector_call(TypeId::of::<u8>(), TypeId::of::<u16>(),
+                                    "Vacuum", "Sphere { radius: 2 }");
+        }
+
+        let statistics = target.into_inner().unwrap();
+        let calls = statistics.intersector_calls_sorted();
+
+        assert_eq!(statistics.rays, 4);
+        assert_eq!(statistics.paths, 2);
+        assert!(statistics.average_bounce_depth().approx_eq(&1.0));
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].shape, "Sphere");
+        assert_eq!(calls[0].calls, 2);
+    }
+}
@@ -0,0 +1,191 @@
+use ::F;
+use std::fmt;
+use std::str::FromStr;
+use num::NumCast;
+use num::Zero;
+use num::One;
+use palette::Rgb;
+use util::cast;
+
+/// The operator compressing the linear high dynamic range colors into the displayable range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapping {
+    /// Clamps the colors to the range [0; 1]
+    Clamp,
+    /// `x / (1 + x)`
+    Reinhard,
+    /// The fit of the ACES filmic curve by Krzysztof Narkowicz
+    Aces,
+    /// The filmic curve by John Hable, as used in Uncharted 2
+    Filmic,
+}
+
+impl ToneMapping {
+    fn map_component(&self, value: F) -> F {
+        let value = value.max(<F as Zero>::zero());
+        let result = match *self {
+            ToneMapping::Clamp => value,
+            ToneMapping::Reinhard => value / (<F as One>::one() + value),
+            ToneMapping::Aces => {
+                let a = cast(2.51);
+                let b = cast(0.03);
+                let c = cast(2.43);
+                let d = cast(0.59);
+                let e = cast(0.14);
+
+                (value * (a * value + b)) / (value * (c * value + d) + e)
+            }
+            ToneMapping::Filmic => {
+                const WHITE: f64 = 11.2;
+                // The curve is applied to twice the exposure, as in the original presentation
+                hable(value * cast(2.0)) / hable(cast(WHITE))
+            }
+        };
+
+        result.min(<F as One>::one())
+    }
+}
+
+fn hable(value: F) -> F {
+    let a = cast(0.15);
+    let b = cast(0.50);
+    let c = cast(0.10);
+    let d = cast(0.20);
+    let e = cast(0.02);
+    let f = cast(0.30);
+
+    ((value * (a * value + c * b) + d * e) / (value * (a * value + b) + d * f)) - e / f
+}
+
+impl FromStr for ToneMapping {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "clamp" | "none" => Ok(ToneMapping::Clamp),
+            "reinhard" => Ok(ToneMapping::Reinhard),
+            "aces" => Ok(ToneMapping::Aces),
+            "filmic" => Ok(ToneMapping::Filmic),
+            _ => Err(format!("Invalid tone mapping operator: \"{}\", expected one of \
+                              `clamp`, `reinhard`, `aces` or `filmic`", name)),
+        }
+    }
+}
+
+impl fmt::Display for ToneMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            ToneMapping::Clamp => "clamp",
+            ToneMapping::Reinhard => "reinhard",
+            ToneMapping::Aces => "aces",
+            ToneMapping::Filmic => "filmic",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Converts the traced linear colors to the displayed pixels.
+/// The defaults leave colors in the range [0; 1] unchanged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapper {
+    pub operator: ToneMapping,
+    /// The exposure adjustment in stops, each stop doubles the brightness
+    pub exposure: F,
+    /// The gamma the colors are encoded with, `1` keeps the colors linear
+    pub gamma: F,
+}
+
+impl ToneMapper {
+    pub fn new(operator: ToneMapping, exposure: F, gamma: F) -> Self {
+        ToneMapper {
+            operator: operator,
+            exposure: exposure,
+            gamma: gamma,
+        }
+    }
+
+    /// Maps the linear color to the range [0; 1].
+    pub fn map(&self, color: Rgb<F>) -> Rgb<F> {
+        let scale = cast(2.0).powf(self.exposure);
+        let inverse_gamma = <F as One>::one() / self.gamma;
+        let map = |value: F| {
+            self.operator.map_component(value * scale).powf(inverse_gamma)
+        };
+
+        Rgb::new(map(color.red), map(color.green), map(color.blue))
+    }
+
+    pub fn to_pixel(&self, color: Rgb<F>) -> [u8; 3] {
+        let color = self.map(color);
+        // Clamped so that invalid gammas, which map to NaN or infinity, cannot fail the cast
+        let quantize = |value: F| {
+            let value = (value * cast(255.0)).round().max(<F as Zero>::zero()).min(cast(255.0));
+
+            <u8 as NumCast>::from(value).unwrap()
+        };
+
+        [quantize(color.red), quantize(color.green), quantize(color.blue)]
+    }
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        ToneMapper::new(ToneMapping::Clamp, <F as Zero>::zero(), <F as One>::one())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use na::ApproxEq;
+    use num::Float;
+    use palette::Rgb;
+    use super::*;
+
+    #[test]
+    fn default_is_identity() {
+        let tone_mapper = ToneMapper::default();
+        let color = tone_mapper.map(Rgb::new(0.25, 0.5, 2.0));
+
+        assert!(color.red.approx_eq(&0.25));
+        assert!(color.green.approx_eq(&0.5));
+        assert!(color.blue.approx_eq(&1.0));
+        assert_eq!(tone_mapper.to_pixel(Rgb::new(0.0, 1.0, -1.0)), [0, 255, 0]);
+    }
+
+    #[test]
+    fn invalid_gammas_are_quantized() {
+        let color = Rgb::new(0.0, 0.5, 0.25);
+
+        assert_eq!(ToneMapper::new(ToneMapping::Clamp, 0.0, -1.0).to_pixel(color), [255, 255, 255]);
+        assert_eq!(ToneMapper::new(ToneMapping::Clamp, 0.0, <F as Float>::nan()).to_pixel(color),
+                   [0, 0, 0]);
+    }
+
+    #[test]
+    fn operators_are_monotonic_and_bounded() {
+        for operator in &[ToneMapping::Clamp, ToneMapping::Reinhard,
+                          ToneMapping::Aces, ToneMapping::Filmic] {
+            let mut previous = 0.0;
+
+            for index in 1..100 {
+                let value = operator.map_component(index as F * 0.25);
+
+                assert!(value >= previous, "{} is not monotonic", operator);
+                assert!(value <= 1.0, "{} exceeds the displayable range", operator);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_and_gamma() {
+        let tone_mapper = ToneMapper::new(ToneMapping::Clamp, 1.0, 2.0);
+        let color = tone_mapper.map(Rgb::new(0.125, 0.0, 1.0));
+
+        assert!(color.red.approx_eq(&0.5));
+        assert!(color.green.approx_eq(&0.0));
+        assert!(color.blue.approx_eq(&1.0));
+        assert_eq!("ACES".parse::<ToneMapping>(), Ok(ToneMapping::Aces));
+    }
+}
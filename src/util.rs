@@ -29,8 +29,6 @@ use std::default::Default;
 use std::mem;
 use std::cell::RefCell;
 use std::cell::RefMut;
-use std::sync::Arc;
-use std::sync::RwLock;
 use num::Num;
 use num::One;
 use num::Zero;
@@ -284,6 +282,11 @@ pub fn combine_palette_color<F: CustomFloat>(a: palette::Rgba<F>,
     }
 }
 
+/// Converts the constant to the floating point type used throughout the crate
+pub fn cast(value: f64) -> F {
+    <F as NumCast>::from(value).unwrap()
+}
+
 pub fn remainder<T: Add<Output = T> + Rem<Output = T> + PartialOrd<T> + Zero + Copy>(a: T,
                                                                                      b: T)
                                                                                      -> T {
@@ -369,86 +372,6 @@ impl<I: Clone, A: Array<Item=I>> Iterator for PossiblyImmediateIterator<I, A> {
     }
 }
 
-pub struct ProviderData<T: Clone, A: Array<Item=T>> {
-    items: Vec<Option<T>>,
-    iterator: PossiblyImmediateIterator<T, A>,
-}
-
-pub struct Provider<T: Clone, A: Array<Item=T>> {
-    data: Arc<RwLock<ProviderData<T, A>>>,
-}
-
-impl<T: Clone, A: Array<Item=T>> Provider<T, A> {
-    // Create an object that provides iterators which lazily compute values
-    // that have not been requested yet
-    pub fn new(iterator: PossiblyImmediateIterator<T, A>) -> Provider<T, A> {
-        Provider {
-            data: Arc::new(RwLock::new(ProviderData {
-                items: Vec::new(),
-                iterator,
-            })),
-        }
-    }
-
-    pub fn iter(&self) -> ProviderIter<T, A> {
-        ProviderIter {
-            index: 0,
-            data: self.data.clone(),
-        }
-    }
-
-    pub fn get(&self, index: usize) -> Option<T> {
-        {
-            let data = self.data.read().unwrap();
-            let cache_len = data.items.len();
-
-            if index < cache_len {
-                return data.items[index].clone();
-            }
-        };
-
-        let mut data = self.data.write().unwrap();
-
-        while index >= data.items.len() {
-            let item = data.iterator.next();
-
-            data.items.push(item);
-        }
-
-        data.items[index].clone()
-    }
-}
-
-pub struct ProviderIter<T: Clone, A: Array<Item=T>> {
-    index: usize,
-    data: Arc<RwLock<ProviderData<T, A>>>,
-}
-
-impl<T: Clone, A: Array<Item=T>> Iterator for ProviderIter<T, A> {
-    type Item = T;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut data = self.data.write().unwrap();
-        let result = if self.index >= data.items.len() {
-            let item = data.iterator.next();
-
-            if item.is_some() {
-                data.items.push(item.clone());
-                item
-            } else {
-                data.items.push(None);
-                None
-            }
-        } else {
-            data.items[self.index].clone()
-        };
-
-        self.index += 1;
-
-        result
-    }
-}
-
 pub trait Mopafied: mopa::Any {}
 
 mopafy!(Mopafied);